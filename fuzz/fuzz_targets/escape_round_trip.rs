@@ -0,0 +1,12 @@
+//! `escape_bytes` output unescapes back to the bytes it came from, and is never shorter than them
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use smashquote::{escape_bytes, unescape_bytes};
+
+fuzz_target!(|raw: &[u8]| {
+    let escaped = escape_bytes(raw);
+    assert!(escaped.len() >= raw.len());
+    assert_eq!(unescape_bytes(escaped.as_bytes()).unwrap(), raw);
+});
@@ -0,0 +1,22 @@
+//! Lexes arbitrary lines, which should never panic, and every token's span is inside the line
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use smashquote::{lex_line_with, LexOptions};
+
+fuzz_target!(|data: &[u8]| {
+    let (braces, line) = match data.split_first() {
+        Some((&flags, line)) => (flags & 1 != 0, line),
+        None => return,
+    };
+    let mut options = LexOptions { braces, ..LexOptions::default() };
+    if let Ok(tokens) = lex_line_with(line, &mut options) {
+        for token in tokens {
+            assert!(token.span.start <= token.span.end && token.span.end <= line.len());
+            for substitution in token.substitutions {
+                assert!(substitution.start < substitution.end && substitution.end <= line.len());
+            }
+        }
+    }
+});
@@ -0,0 +1,10 @@
+//! Escaped forms generated by `EscapedInput` unescape back to their raw bytes
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use smashquote::{unescape_bytes_with, EscapedInput};
+
+fuzz_target!(|input: EscapedInput| {
+    assert_eq!(unescape_bytes_with(&input.escaped, &input.dialect).unwrap(), input.raw);
+});
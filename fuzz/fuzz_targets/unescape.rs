@@ -0,0 +1,44 @@
+//! Unescapes arbitrary bytes in an arbitrary dialect, which should never panic
+//!
+//! The output is never longer than the bound from `unescaped_len_bound`, and errors point inside the input.
+//! Input that ends in an odd number of backslashes is an error, unless the last of them can be part of an
+//! escape like `\c\`, `\M-\`, or `^\`.
+
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{Arbitrary, Unstructured};
+use libfuzzer_sys::fuzz_target;
+use smashquote::{unescape_bytes_with, unescaped_len_bound, Dialect, ParseError};
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    let dialect = match Dialect::arbitrary(&mut u) {
+        Ok(dialect) => dialect,
+        Err(_) => return,
+    };
+    let input = u.take_rest();
+    let trailing = input.iter().rev().take_while(|&&b| b == b'\\').count();
+    let swallowed = matches!(input[..input.len() - trailing].last(), Some(b'c' | b'-' | b'^'));
+    match unescape_bytes_with(input, &dialect) {
+        Ok(output) => {
+            assert!(output.len() <= unescaped_len_bound(input, &dialect));
+            assert!(trailing % 2 == 0 || swallowed, "a trailing backslash was dropped");
+        }
+        Err(e) => {
+            let offset = match e {
+                ParseError::InvalidBackslash { offset, .. }
+                | ParseError::Utf16Bom { offset, .. }
+                | ParseError::NulByte { offset }
+                | ParseError::OutputTooLong { offset, .. }
+                | ParseError::NotUtf8 { offset }
+                | ParseError::Unmappable { offset, .. }
+                | ParseError::UnsetVariable { offset, .. }
+                | ParseError::CommandSubstitution { offset }
+                | ParseError::RawByte { offset, .. } => offset,
+                ParseError::MissingClose { .. } => 0,
+            };
+            // an empty input still reports offset 0 for errors found at the end
+            assert!(offset < input.len().max(1), "{e} for {} bytes", input.len());
+        }
+    }
+});
@@ -0,0 +1,93 @@
+//! Unescaping `&str` input, with errors that say where they are in chars as well as in bytes
+
+use crate::{unescape_bytes_with, Dialect, ParseError};
+
+/// Where a byte offset is in a `&str`, counting chars
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CharIndex {
+    /// The index of the char the offset is in, counting from 0
+    pub index: usize,
+    /// Whether the offset is in the middle of that char, instead of at its start
+    pub splits_char: bool,
+}
+
+/// Returns where a byte offset is in `s`, counting chars, or [None] if it's past the end of `s`
+///
+/// The offset just past the end is allowed, since that's where errors about the end point.
+pub fn char_index(s: &str, offset: usize) -> Option<CharIndex> {
+    if offset > s.len() {
+        return None;
+    }
+    let mut start = offset;
+    while ! s.is_char_boundary(start) {
+        start -= 1;
+    }
+    return Some(CharIndex { index: s[..start].chars().count(), splits_char: start != offset });
+}
+
+/// A [ParseError] from unescaping a `&str`, with where it is counted in chars
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StrError {
+    /// The error, with byte offsets like always. It's boxed to keep results small.
+    pub error: Box<ParseError>,
+    /// Where the error's [offset](ParseError::offset) is, or [None] for [MissingClose](ParseError::MissingClose)
+    pub char_index: Option<CharIndex>,
+    /// The chars that the error's [span](ParseError::span) covers, including all of any char it splits
+    pub char_span: Option<std::ops::Range<usize>>,
+}
+
+impl StrError {
+    /// Adds the char positions to an error from unescaping `s` in `dialect`
+    ///
+    /// Chars are counted from the start of `s`, even with a [base_offset](Dialect::base_offset).
+    pub fn new(error: ParseError, s: &str, dialect: &Dialect) -> Self {
+        let span = error.span().map(|span| {
+            let start = span.start.saturating_sub(dialect.base_offset).min(s.len());
+            let end = span.end.saturating_sub(dialect.base_offset).min(s.len());
+            start..end
+        });
+        let start = span.as_ref().and_then(|span| char_index(s, span.start));
+        let char_span = span.map(|span| {
+            let start = start.map_or(0, |c| c.index);
+            let end = char_index(s, span.end).map_or(start, |c| c.index + usize::from(c.splits_char));
+            start..end.max(start)
+        });
+        return Self { error: Box::new(error), char_index: start, char_span };
+    }
+}
+
+impl std::fmt::Display for StrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self.char_index {
+            Some(CharIndex { index, splits_char: false }) => write!(f, "{} (char {})", self.error, index),
+            Some(CharIndex { index, splits_char: true }) => write!(f, "{} (in the middle of char {})", self.error, index),
+            None => write!(f, "{}", self.error),
+        };
+    }
+}
+
+impl std::error::Error for StrError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return Some(self.error.as_ref());
+    }
+}
+
+impl From<StrError> for ParseError {
+    fn from(error: StrError) -> Self {
+        return *error.error;
+    }
+}
+
+/// Returns a new unescaped byte string from a `&str`, with errors that count chars too
+///
+/// This is [unescape_bytes](crate::unescape_bytes) for callers like editors that address text by char.
+pub fn unescape_str(s: &str) -> Result<Vec<u8>, StrError> {
+    return unescape_str_with(s, &Dialect::default());
+}
+
+/// Returns a new unescaped byte string from a `&str` using a specific [Dialect], like [unescape_str]
+pub fn unescape_str_with(s: &str, dialect: &Dialect) -> Result<Vec<u8>, StrError> {
+    return unescape_bytes_with(s.as_bytes(), dialect).map_err(|e| StrError::new(e, s, dialect));
+}
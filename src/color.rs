@@ -0,0 +1,50 @@
+//! Errors rendered with ANSI colors, for command line tools
+
+use crate::{pretty_string_with, ControlStyle, ParseError, PrettyStringOptions, UnescapeError};
+
+const RED: &str = "\x1B[31m";
+const BOLD_RED: &str = "\x1B[1;31m";
+const RESET: &str = "\x1B[0m";
+
+fn pretty(bs: &[u8]) -> String {
+    let options = PrettyStringOptions {
+        control: ControlStyle::Reversible,
+        ..PrettyStringOptions::default()
+    };
+    return pretty_string_with(bs, &options);
+}
+
+impl ParseError {
+    /// Shows the error like a compiler diagnostic: the message, then the line of `input` where the problem
+    /// is, with the bad bytes in red and underlined with `^`
+    ///
+    /// `input` should be what was being unescaped. This always uses ANSI color codes, so it's up to the
+    /// caller to check whether they're writing to a terminal. The line is shown with
+    /// [ControlStyle::Reversible], so bytes that aren't printable can't mess up the terminal.
+    pub fn render_colored(&self, input: &[u8]) -> String {
+        let span = self.span().unwrap_or(input.len()..input.len());
+        let start = span.start.min(input.len());
+        let end = span.end.min(input.len());
+        let line_start = input[..start].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        let line_end = input[end..].iter().position(|&b| b == b'\n').map_or(input.len(), |i| end + i);
+        let before = pretty(&input[line_start..start]);
+        let bad = pretty(&input[start..end]);
+        let after = pretty(&input[end..line_end]);
+        let carets = "^".repeat(bad.chars().count().max(1));
+        return format!(
+            "{BOLD_RED}error{RESET}: {self}\n  {before}{RED}{bad}{RESET}{after}\n  {}{RED}{carets}{RESET}",
+            " ".repeat(before.chars().count()),
+        );
+    }
+}
+
+impl UnescapeError {
+    /// Shows the error like a compiler diagnostic, with [ParseError::render_colored], or just the message for
+    /// I/O errors
+    pub fn render_colored(&self, input: &[u8]) -> String {
+        return match self {
+            Self::Parse(e) => e.render_colored(input),
+            Self::IOError(_) => format!("{BOLD_RED}error{RESET}: {self}"),
+        };
+    }
+}
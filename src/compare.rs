@@ -0,0 +1,112 @@
+//! Comparing and hashing escaped strings by what they unescape to, without keeping the unescaped bytes
+
+use std::hash::Hasher;
+use std::io::Write;
+
+use crate::{unescape_slice_with, Dialect, ParseError, UnescapeError};
+
+/// A [Write] that checks what's written against `raw`, and stops at the first difference
+struct CompareWriter<'a> {
+    raw: &'a [u8],
+    differs: bool,
+}
+
+impl Write for CompareWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.raw.strip_prefix(buf) {
+            Some(rest) => self.raw = rest,
+            None => {
+                self.differs = true;
+                return Err(std::io::Error::other("The unescaped bytes are different."));
+            }
+        }
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return Ok(());
+    }
+}
+
+/// Returns whether `escaped` unescapes to exactly `raw`, without allocating the unescaped bytes
+///
+/// This stops at the first byte that's different, so an invalid escape after that isn't an error.
+pub fn escaped_eq(escaped: &[u8], raw: &[u8]) -> Result<bool, ParseError> {
+    return escaped_eq_with(escaped, raw, &Dialect::default());
+}
+
+/// Returns whether `escaped` unescapes to exactly `raw` in `dialect`, like [escaped_eq]
+pub fn escaped_eq_with(escaped: &[u8], raw: &[u8], dialect: &Dialect) -> Result<bool, ParseError> {
+    let mut compare = CompareWriter { raw, differs: false };
+    let r = unescape_slice_with(escaped, &mut compare, None, dialect);
+    if compare.differs {
+        return Ok(false);
+    }
+    return match r {
+        Ok(_) => Ok(compare.raw.is_empty()),
+        Err(e) => Err(e.into_parse_error()),
+    };
+}
+
+/// How many unescaped bytes [HashWriter] passes to the hasher at once
+const HASH_BLOCK_LEN: usize = 64;
+
+/// A [Write] that passes what's written to a [Hasher], in blocks of [HASH_BLOCK_LEN] bytes
+///
+/// Escapes are written in different sized pieces depending on how they're spelled, and not every hasher
+/// gives the same hash for the same bytes split up differently, so the pieces are put back into blocks.
+struct HashWriter<'h, H: Hasher> {
+    hasher: &'h mut H,
+    block: [u8; HASH_BLOCK_LEN],
+    block_len: usize,
+    total: usize,
+}
+
+impl<H: Hasher> Write for HashWriter<'_, H> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for &byte in buf {
+            self.block[self.block_len] = byte;
+            self.block_len += 1;
+            if self.block_len == HASH_BLOCK_LEN {
+                self.hasher.write(&self.block);
+                self.block_len = 0;
+            }
+        }
+        self.total += buf.len();
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return Ok(());
+    }
+}
+
+/// Feeds what `bytes` unescapes to into `hasher`, without allocating the unescaped bytes
+///
+/// Escaped strings that unescape to the same bytes, however they're spelled, give the same hash. The
+/// unescaped bytes are written in blocks and followed by their length, which isn't how `[u8]` hashes
+/// itself, so hash raw bytes with [hash_raw] to compare them with escaped ones, like in a [Hash](std::hash::Hash)
+/// implementation for a key type that can hold either.
+pub fn hash_unescaped<H: Hasher>(bytes: &[u8], hasher: &mut H) -> Result<(), ParseError> {
+    return hash_unescaped_with(bytes, hasher, &Dialect::default());
+}
+
+/// Feeds what `bytes` unescapes to in `dialect` into `hasher`, like [hash_unescaped]
+pub fn hash_unescaped_with<H: Hasher>(bytes: &[u8], hasher: &mut H, dialect: &Dialect) -> Result<(), ParseError> {
+    let mut writer = HashWriter { hasher, block: [0; HASH_BLOCK_LEN], block_len: 0, total: 0 };
+    unescape_slice_with(bytes, &mut writer, None, dialect).map_err(UnescapeError::into_parse_error)?;
+    finish_hash(&mut writer);
+    return Ok(());
+}
+
+/// Feeds raw bytes into `hasher` the same way [hash_unescaped] feeds unescaped ones
+pub fn hash_raw<H: Hasher>(raw: &[u8], hasher: &mut H) {
+    let mut writer = HashWriter { hasher, block: [0; HASH_BLOCK_LEN], block_len: 0, total: 0 };
+    writer.write_all(raw).expect("Hashing never fails.");
+    finish_hash(&mut writer);
+}
+
+fn finish_hash<H: Hasher>(writer: &mut HashWriter<'_, H>) {
+    writer.hasher.write(&writer.block[..writer.block_len]);
+    writer.hasher.write_usize(writer.total);
+}
@@ -0,0 +1,191 @@
+//! smashquote's conformance test vectors, for checking other implementations against the same cases
+//!
+//! The vectors are in [VECTORS], in a plain text format that's easy to read from any language. Each line
+//! that isn't empty or a `#` comment has four tab-separated fields:
+//!
+//! 1. A name for the vector
+//! 2. The escaped input, in hex
+//! 3. The expected output in hex, or `error:` followed by the name of a [ParseError] variant
+//! 4. Comma-separated [Dialect] options like `octal_overflow=wrap,caret_notation`, or `-` for the default dialect.
+//!    Option names are the field names, and values are the variant names in snake case.
+//!    An option without a value is `true`.
+
+use crate::{
+    unescape_bytes_with, BomPolicy, Dialect, LineContinuation, NulPolicy, OctalOverflow, OutputEncoding, ParseError,
+    SurrogatePolicy,
+};
+
+/// The test vectors, in the format described in the [module documentation](self)
+pub const VECTORS: &str = include_str!("../conformance.txt");
+
+/// What a [TestVector] should unescape to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expected {
+    /// These bytes
+    Output(Vec<u8>),
+    /// An error, named by its [ParseError] variant, like `InvalidBackslash`
+    Error(String),
+}
+
+/// One test case: an escaped input, the [Dialect] to read it in, and what it should unescape to
+#[derive(Debug, Clone)]
+pub struct TestVector {
+    /// What the vector tests
+    pub name: String,
+    /// The escaped input
+    pub escaped: Vec<u8>,
+    /// The dialect to unescape it in
+    pub dialect: Dialect,
+    /// What it should unescape to
+    pub expected: Expected,
+}
+
+impl TestVector {
+    /// Unescapes the input with smashquote, and returns whether the result is what's expected
+    pub fn passes(&self) -> bool {
+        return match (unescape_bytes_with(&self.escaped, &self.dialect), &self.expected) {
+            (Ok(output), Expected::Output(expected)) => output == *expected,
+            (Err(e), Expected::Error(expected)) => variant_name(&e) == expected,
+            _ => false,
+        };
+    }
+}
+
+/// Why [parse_vectors] couldn't read some vectors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorError {
+    /// The line number, counting from 1
+    pub line: usize,
+    /// What was wrong with it
+    pub message: String,
+}
+
+impl std::fmt::Display for VectorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bad test vector on line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for VectorError {
+}
+
+/// Returns the built-in test vectors from [VECTORS]
+pub fn vectors() -> Vec<TestVector> {
+    return parse_vectors(VECTORS).expect("The built-in vectors should always parse.");
+}
+
+/// Reads test vectors in the format described in the [module documentation](self)
+pub fn parse_vectors(text: &str) -> Result<Vec<TestVector>, VectorError> {
+    let mut r: Vec<TestVector> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: String| VectorError { line: i + 1, message };
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [name, escaped, expected, options] = fields[..] else {
+            return Err(error(format!("expected 4 fields, but found {}", fields.len())));
+        };
+        let escaped = parse_hex(escaped).map_err(error)?;
+        let expected = match expected.strip_prefix("error:") {
+            Some(variant) => Expected::Error(variant.to_string()),
+            None => Expected::Output(parse_hex(expected).map_err(error)?),
+        };
+        let dialect = parse_dialect(options).map_err(error)?;
+        r.push(TestVector { name: name.to_string(), escaped, dialect, expected });
+    }
+    return Ok(r);
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if ! hex.len().is_multiple_of(2) || ! hex.is_ascii() {
+        return Err(format!("{:?} isn't hex bytes", hex));
+    }
+    return (0..hex.len()).step_by(2).map(|i| {
+        u8::from_str_radix(&hex[i..i+2], 16).map_err(|_| format!("{:?} isn't hex bytes", hex))
+    }).collect();
+}
+
+fn parse_dialect(options: &str) -> Result<Dialect, String> {
+    let mut dialect = Dialect::default();
+    if options == "-" {
+        return Ok(dialect);
+    }
+    for option in options.split(',') {
+        let (key, value) = option.split_once('=').unwrap_or((option, "true"));
+        let bad = || format!("bad value {:?} for {}", value, key);
+        let flag = || value.parse::<bool>().map_err(|_| bad());
+        let number = || value.parse::<usize>().map_err(|_| bad());
+        match key {
+            "caret_notation" => dialect.caret_notation = flag()?,
+            "octal_overflow" => dialect.octal_overflow = match value {
+                "error" => OctalOverflow::Error,
+                "wrap" => OctalOverflow::Wrap,
+                "two_digits" => OctalOverflow::TwoDigits,
+                _ => { return Err(bad()); }
+            },
+            "max_hex_digits" => dialect.max_hex_digits = number()?,
+            "max_octal_digits" => dialect.max_octal_digits = number()?,
+            "max_u_digits" => dialect.max_u_digits = number()?,
+            "max_big_u_digits" => dialect.max_big_u_digits = number()?,
+            "fixed_width_unicode" => dialect.fixed_width_unicode = flag()?,
+            "control_delete" => dialect.control_delete = flag()?,
+            "control_double_backslash" => dialect.control_double_backslash = flag()?,
+            "space_escape" => dialect.space_escape = flag()?,
+            "line_continuation" => dialect.line_continuation = match value {
+                "off" => LineContinuation::Off,
+                "newline" => LineContinuation::Newline,
+                "newline_or_crlf" => LineContinuation::NewlineOrCrlf,
+                _ => { return Err(bad()); }
+            },
+            "bom" => dialect.bom = match value {
+                "keep" => BomPolicy::Keep,
+                "strip" => BomPolicy::Strip,
+                "strip_and_reject_utf16" => BomPolicy::StripAndRejectUtf16,
+                _ => { return Err(bad()); }
+            },
+            "nul" => dialect.nul = match value {
+                "allow" => NulPolicy::Allow,
+                "reject" => NulPolicy::Reject,
+                "strip" => NulPolicy::Strip,
+                _ => { return Err(bad()); }
+            },
+            "max_output_len" => dialect.max_output_len = Some(number()?),
+            "surrogates" => dialect.surrogates = match value {
+                "error" => SurrogatePolicy::Error,
+                "wtf8" => SurrogatePolicy::Wtf8,
+                "replace" => SurrogatePolicy::Replace,
+                _ => { return Err(bad()); }
+            },
+            "canonical" => dialect.canonical = flag()?,
+            "reject_raw_controls" => dialect.reject_raw_controls = flag()?,
+            "allowed_raw_controls" => dialect.allowed_raw_controls = parse_hex(value)?,
+            "ascii_source" => dialect.ascii_source = flag()?,
+            "base_offset" => dialect.base_offset = number()?,
+            "encoding" => dialect.encoding = match value {
+                "utf8" => OutputEncoding::Utf8,
+                "cesu8" => OutputEncoding::Cesu8,
+                "utf16le" => OutputEncoding::Utf16Le,
+                "utf16be" => OutputEncoding::Utf16Be,
+                _ => { return Err(bad()); }
+            },
+            _ => { return Err(format!("unknown option {:?}", key)); }
+        }
+    }
+    return Ok(dialect);
+}
+
+fn variant_name(e: &ParseError) -> &'static str {
+    return match e {
+        ParseError::InvalidBackslash { .. } => "InvalidBackslash",
+        ParseError::MissingClose { .. } => "MissingClose",
+        ParseError::Utf16Bom { .. } => "Utf16Bom",
+        ParseError::NulByte { .. } => "NulByte",
+        ParseError::OutputTooLong { .. } => "OutputTooLong",
+        ParseError::NotUtf8 { .. } => "NotUtf8",
+        ParseError::Unmappable { .. } => "Unmappable",
+        ParseError::UnsetVariable { .. } => "UnsetVariable",
+        ParseError::CommandSubstitution { .. } => "CommandSubstitution",
+        ParseError::RawByte { .. } => "RawByte",
+    };
+}
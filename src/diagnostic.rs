@@ -0,0 +1,45 @@
+//! Errors as [codespan-reporting](codespan_reporting) diagnostics
+
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+use crate::{ParseError, Suggestion, UnescapeError};
+
+impl ParseError {
+    /// Makes a [Diagnostic] for the error, with a label on the bytes of `file_id` that it's about
+    ///
+    /// `input_len` is how long the input is, so that a [MissingClose](ParseError::MissingClose) error can
+    /// point at the end of it. If the input was part of a bigger file, set [base_offset](crate::Dialect::base_offset)
+    /// while unescaping so that the label points at the right place in the file. A [Suggestion] becomes a
+    /// secondary label on the byte that isn't a digit, or a `help:` note.
+    pub fn to_diagnostic<FileId: Clone>(&self, file_id: FileId, input_len: usize) -> Diagnostic<FileId> {
+        let (span, label) = match self {
+            Self::InvalidBackslash{kind, ..} => (self.span(), format!("{:?}", kind)),
+            Self::MissingClose{string, ..} => (None, format!("expected {} before here", string)),
+            _ => (self.span(), String::from("here")),
+        };
+        let span = span.unwrap_or(input_len..input_len);
+        let mut labels = vec![Label::primary(file_id.clone(), span).with_message(label)];
+        let mut notes = Vec::new();
+        match self.suggestion() {
+            Some(Suggestion::NotADigit{offset, ..}) => {
+                labels.push(Label::secondary(file_id, *offset..*offset + 1).with_message("not a digit"));
+            }
+            Some(suggestion) => notes.push(format!("help: {}", suggestion)),
+            None => {}
+        }
+        return Diagnostic::error()
+            .with_message(self.to_string())
+            .with_labels(labels)
+            .with_notes(notes);
+    }
+}
+
+impl UnescapeError {
+    /// Makes a [Diagnostic] for the error, like [ParseError::to_diagnostic]. I/O errors don't have a label.
+    pub fn to_diagnostic<FileId: Clone>(&self, file_id: FileId, input_len: usize) -> Diagnostic<FileId> {
+        return match self {
+            Self::Parse(e) => e.to_diagnostic(file_id, input_len),
+            Self::IOError(_) => Diagnostic::error().with_message(self.to_string()),
+        };
+    }
+}
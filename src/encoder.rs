@@ -0,0 +1,208 @@
+//! Turning unescaped output into bytes in whatever encoding the output needs
+
+use std::io::Write;
+
+use crate::OutputEncoding;
+#[cfg(feature = "encoding_rs")]
+use crate::UnmappablePolicy;
+
+/// Why a [ScalarEncoder] couldn't write something
+#[derive(Debug)]
+pub enum EncodeError {
+    /// The encoder needs to transcode bytes that didn't come from unicode escapes, but they aren't valid UTF-8
+    NotUtf8,
+    /// The encoding doesn't have this character
+    Unmappable {
+        /// The character that couldn't be written
+        character: char,
+        /// The name of the encoding
+        encoding: &'static str,
+    },
+    /// Some I/O error happened...
+    IOError(std::io::Error),
+}
+
+impl From<std::io::Error> for EncodeError {
+    fn from(error: std::io::Error) -> Self {
+        EncodeError::IOError(error)
+    }
+}
+
+/// Writes unescaped output in some encoding
+///
+/// [unescape_iter_with_encoder](crate::unescape_iter_with_encoder) calls it, in order, with each character
+/// from a unicode escape like `é` and with all the other bytes, like literal text or `\xNN`.
+/// By then, the [NulPolicy](crate::NulPolicy) has been applied and surrogate pairs have been put together.
+pub trait ScalarEncoder {
+    /// Writes a character from a unicode escape
+    fn write_char(&mut self, c: char, out: &mut dyn Write) -> Result<(), EncodeError>;
+
+    /// Writes a surrogate that isn't part of a pair, when [surrogates](crate::Dialect::surrogates) is
+    /// [SurrogatePolicy::Wtf8](crate::SurrogatePolicy::Wtf8). The default writes it as WTF-8.
+    fn write_surrogate(&mut self, unit: u16, out: &mut dyn Write) -> Result<(), EncodeError> {
+        out.write_all(&three_byte_utf8(unit as u32))?;
+        return Ok(());
+    }
+
+    /// Writes bytes that didn't come from unicode escapes. The default writes them as-is.
+    fn write_bytes(&mut self, bytes: &[u8], out: &mut dyn Write) -> Result<(), EncodeError> {
+        out.write_all(bytes)?;
+        return Ok(());
+    }
+
+    /// Called once at the end of the input. The default does nothing.
+    fn finish(&mut self, _out: &mut dyn Write) -> Result<(), EncodeError> {
+        return Ok(());
+    }
+}
+
+/// Encodes a code point from `0x800` through `0xFFFF` as 3 bytes like UTF-8 does, even if it's a surrogate
+pub(crate) fn three_byte_utf8(ord: u32) -> [u8; 3] {
+    return [
+        0xE0 | (ord >> 12) as u8,
+        0x80 | ((ord >> 6) & 0x3F) as u8,
+        0x80 | (ord & 0x3F) as u8,
+    ];
+}
+
+/// The [ScalarEncoder] for [OutputEncoding::Utf8]
+#[derive(Debug, Clone, Default)]
+pub struct Utf8Encoder;
+
+impl ScalarEncoder for Utf8Encoder {
+    fn write_char(&mut self, c: char, out: &mut dyn Write) -> Result<(), EncodeError> {
+        out.write_all(c.encode_utf8(&mut [0u8; 4]).as_bytes())?;
+        return Ok(());
+    }
+}
+
+/// The [ScalarEncoder] for [OutputEncoding::Cesu8]
+#[derive(Debug, Clone, Default)]
+pub struct Cesu8Encoder;
+
+impl ScalarEncoder for Cesu8Encoder {
+    fn write_char(&mut self, c: char, out: &mut dyn Write) -> Result<(), EncodeError> {
+        if c > '\u{FFFF}' {
+            for &unit in c.encode_utf16(&mut [0u16; 2]).iter() {
+                out.write_all(&three_byte_utf8(unit as u32))?;
+            }
+        } else {
+            out.write_all(c.encode_utf8(&mut [0u8; 4]).as_bytes())?;
+        }
+        return Ok(());
+    }
+}
+
+/// The [ScalarEncoder] for [OutputEncoding::Utf16Le] and [OutputEncoding::Utf16Be]
+///
+/// Bytes that didn't come from unicode escapes are transcoded from UTF-8, so an incomplete
+/// UTF-8 sequence is held back until the rest of it is written.
+#[derive(Debug, Clone, Default)]
+pub struct Utf16Encoder {
+    /// Write each code unit with the high byte first
+    pub big_endian: bool,
+    partial_utf8: Vec<u8>,
+}
+
+impl Utf16Encoder {
+    /// Makes a UTF-16 encoder with the given byte order
+    pub fn new(big_endian: bool) -> Self {
+        return Self { big_endian, partial_utf8: Vec::with_capacity(4) };
+    }
+
+    fn write_units(&mut self, units: &[u16], out: &mut dyn Write) -> Result<(), EncodeError> {
+        if ! self.partial_utf8.is_empty() {
+            self.partial_utf8.clear();
+            return Err(EncodeError::NotUtf8);
+        }
+        let mut buf = [0u8; 4];
+        for (i, &unit) in units.iter().enumerate() {
+            let unit_bytes = if self.big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() };
+            buf[i*2..i*2+2].copy_from_slice(&unit_bytes);
+        }
+        out.write_all(&buf[..units.len()*2])?;
+        return Ok(());
+    }
+}
+
+impl ScalarEncoder for Utf16Encoder {
+    fn write_char(&mut self, c: char, out: &mut dyn Write) -> Result<(), EncodeError> {
+        return self.write_units(c.encode_utf16(&mut [0u16; 2]), out);
+    }
+
+    fn write_surrogate(&mut self, unit: u16, out: &mut dyn Write) -> Result<(), EncodeError> {
+        return self.write_units(&[unit], out);
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8], out: &mut dyn Write) -> Result<(), EncodeError> {
+        for &byte in bytes {
+            self.partial_utf8.push(byte);
+            match std::str::from_utf8(&self.partial_utf8) {
+                Ok(s) => {
+                    let c = s.chars().next().expect("partial_utf8 is never empty here.");
+                    self.partial_utf8.clear();
+                    self.write_char(c, out)?;
+                }
+                Err(e) if e.error_len().is_none() => {} // not finished yet
+                Err(_) => {
+                    self.partial_utf8.clear();
+                    return Err(EncodeError::NotUtf8);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    fn finish(&mut self, _out: &mut dyn Write) -> Result<(), EncodeError> {
+        if ! self.partial_utf8.is_empty() {
+            self.partial_utf8.clear();
+            return Err(EncodeError::NotUtf8);
+        }
+        return Ok(());
+    }
+}
+
+/// The [ScalarEncoder] for [OutputEncoding::Legacy]
+#[cfg(feature = "encoding_rs")]
+#[derive(Debug, Clone)]
+pub struct LegacyEncoder {
+    /// The encoding to write. Like in [encoding_rs], asking for UTF-16 gets UTF-8.
+    pub encoding: &'static encoding_rs::Encoding,
+    /// What to do with characters that `encoding` doesn't have
+    pub unmappable: UnmappablePolicy,
+}
+
+#[cfg(feature = "encoding_rs")]
+impl ScalarEncoder for LegacyEncoder {
+    fn write_char(&mut self, c: char, out: &mut dyn Write) -> Result<(), EncodeError> {
+        let encoding = self.encoding.output_encoding();
+        // each character is encoded on its own, so stateful encodings like ISO-2022-JP switch back every time
+        let mut encoder = encoding.new_encoder();
+        let mut buf = [0u8; 16];
+        let (result, _, written) = encoder.encode_from_utf8_without_replacement(c.encode_utf8(&mut [0u8; 4]), &mut buf, true);
+        match result {
+            encoding_rs::EncoderResult::InputEmpty => out.write_all(&buf[..written])?,
+            encoding_rs::EncoderResult::Unmappable(_) => match self.unmappable {
+                UnmappablePolicy::Error => { return Err(EncodeError::Unmappable { character: c, encoding: encoding.name() }); }
+                UnmappablePolicy::Question => out.write_all(b"?")?,
+                UnmappablePolicy::HtmlEntity => out.write_all(format!("&#{};", c as u32).as_bytes())?,
+            },
+            encoding_rs::EncoderResult::OutputFull => unreachable!("16 bytes is enough for any one character."),
+        }
+        return Ok(());
+    }
+}
+
+impl OutputEncoding {
+    /// Makes a new [ScalarEncoder] that writes this encoding
+    pub fn encoder(&self) -> Box<dyn ScalarEncoder> {
+        return match *self {
+            OutputEncoding::Utf8 => Box::new(Utf8Encoder),
+            OutputEncoding::Cesu8 => Box::new(Cesu8Encoder),
+            OutputEncoding::Utf16Le => Box::new(Utf16Encoder::new(false)),
+            OutputEncoding::Utf16Be => Box::new(Utf16Encoder::new(true)),
+            #[cfg(feature = "encoding_rs")]
+            OutputEncoding::Legacy(encoding, unmappable) => Box::new(LegacyEncoder { encoding, unmappable }),
+        };
+    }
+}
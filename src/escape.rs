@@ -0,0 +1,421 @@
+//! Helpers for going the other way: turning raw bytes into backslash escapes
+
+use std::fmt;
+use std::fmt::Write;
+use crate::{unescape_bytes_with, Dialect, ParseError, NAMED_ESCAPES};
+
+#[cfg(any(feature = "arbitrary", feature = "proptest"))]
+use crate::BomPolicy;
+
+/// Shows bytes with backslash escapes, without allocating
+///
+/// The output can be turned back into the same bytes with [unescape_bytes](crate::unescape_bytes).
+/// Printable ASCII and valid UTF-8 are shown as they are, control characters use named
+/// escapes like `\n` where there is one, and everything else is shown as `\xNN` or `\uNNNN`.
+/// [Display](fmt::Display) doesn't add quotes, while [Debug](fmt::Debug) wraps the output in
+/// double quotes and escapes any double quotes inside.
+#[derive(Clone, Copy)]
+pub struct EscapedDisplay<'a>(pub &'a [u8]);
+
+/// How [escape_byte] says a single byte should be written
+///
+/// Its [Display](fmt::Display) implementation writes the escape sequence, like `\n` or `\xFF`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscapeForm {
+    /// The byte is written as it is
+    Literal(u8),
+    /// The byte is written as a backslash followed by this letter, like `\n`
+    Named(u8),
+    /// The byte is written as `\xNN`
+    Hex(u8),
+}
+
+impl fmt::Display for EscapeForm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Literal(byte) => f.write_char(*byte as char),
+            Self::Named(letter) => write!(f, "\\{}", *letter as char),
+            Self::Hex(byte) => write!(f, "\\x{:02X}", byte),
+        }
+    }
+}
+
+/// Decides how a single byte should be escaped, consistently with [EscapedDisplay]
+///
+/// Printable ASCII is [Literal](EscapeForm::Literal), except for the backslash. Control characters
+/// with a named escape, like `\n`, are [Named](EscapeForm::Named), and everything else is
+/// [Hex](EscapeForm::Hex). Since this only looks at one byte, all bytes `0x80` and up are
+/// [Hex](EscapeForm::Hex), even though [EscapedDisplay] shows valid UTF-8 as it is.
+pub fn escape_byte(byte: u8) -> EscapeForm {
+    return match byte {
+        0x00..=0x1F | b'\\' => match NAMED_ESCAPES.iter().find(|&&(_, b)| b == byte) {
+            Some(&(letter, _)) => EscapeForm::Named(letter),
+            None => EscapeForm::Hex(byte),
+        },
+        b' '..=b'~' => EscapeForm::Literal(byte),
+        _ => EscapeForm::Hex(byte),
+    };
+}
+
+/// Which escape [escape_bytes_with] writes for a byte that has to be escaped, like a newline
+///
+/// Characters that aren't ASCII, like a `U+0085` control character, are always written as unicode escapes,
+/// or as a `\xNN` for each byte without the `unicode` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EscapeStyle {
+    /// A named escape like `\n` where there is one, and `\xNN` otherwise, like C programmers expect
+    #[default]
+    Named,
+    /// Always `\xNN`, like `\x0A`
+    Hex,
+    /// Always three octal digits, like `\012`, like `printf %q` and older tools
+    Octal,
+    /// Whichever is shortest, like `\n`, `\1`, or `\xFF`, using fewer digits when the next byte can't be
+    /// taken for another digit. Hex wins a tie.
+    Shortest,
+}
+
+/// Options for [escape_bytes_with]
+///
+/// The [Default] options produce the same output as [EscapedDisplay], which only escapes what it has to:
+/// control characters, the backslash, and bytes that aren't valid UTF-8. Printable text, including
+/// non-ASCII characters, is left as it is, so mostly-text data stays readable. Set [quote](EscapeOptions::quote)
+/// to escape a delimiter too.
+#[derive(Debug, Clone, Default)]
+pub struct EscapeOptions {
+    /// Write bytes that don't have a named escape as `\x{NN}` instead of `\xNN`
+    pub braced_hex: bool,
+    /// Escape everything that isn't ASCII, so the output is 7-bit clean. Characters from valid UTF-8 are
+    /// written as `\u{...}`, or as a `\xNN` for each byte without the `unicode` feature, and other bytes
+    /// as `\xNN`.
+    pub ascii_only: bool,
+    /// Also escape this delimiter with a backslash, like `'` for output that goes between `$'...'`. It has
+    /// to be printable ASCII, and other bytes are ignored. Letters and digits are written as `\xNN` instead,
+    /// since `\n` or `\x` would mean something else. Unescaping it needs the same byte as the close delimiter, unless it's a quote, which has a named escape.
+    pub quote: Option<u8>,
+    /// Which escape to write for each byte that has to be escaped
+    pub style: EscapeStyle,
+}
+
+fn write_hex<W: fmt::Write>(w: &mut W, byte: u8, options: &EscapeOptions) -> fmt::Result {
+    if options.braced_hex {
+        return write!(w, "\\x{{{:02X}}}", byte);
+    } else {
+        return write!(w, "\\x{:02X}", byte);
+    }
+}
+
+/// Writes `byte`, which has to be escaped, in the [EscapeStyle] of `options`. `next` is the byte after it.
+fn write_byte_escape<W: fmt::Write>(w: &mut W, byte: u8, next: Option<u8>, options: &EscapeOptions) -> fmt::Result {
+    let named = escape_byte(byte);
+    return match options.style {
+        EscapeStyle::Named | EscapeStyle::Shortest if matches!(named, EscapeForm::Named(_)) => write!(w, "{}", named),
+        EscapeStyle::Named | EscapeStyle::Hex => write_hex(w, byte, options),
+        EscapeStyle::Octal => write!(w, "\\{:03o}", byte),
+        EscapeStyle::Shortest => {
+            let octal = match next {
+                Some(b'0'..=b'7') => format!("\\{:03o}", byte),
+                _ => format!("\\{:o}", byte),
+            };
+            let hex = match next {
+                _ if options.braced_hex => format!("\\x{{{:X}}}", byte),
+                Some(b) if b.is_ascii_hexdigit() => format!("\\x{:02X}", byte),
+                _ => format!("\\x{:X}", byte),
+            };
+            w.write_str(if octal.len() < hex.len() { &octal } else { &hex })
+        }
+    };
+}
+
+fn write_escaped_char<W: fmt::Write>(w: &mut W, c: char, next: Option<u8>, quote: Option<char>, options: &EscapeOptions) -> fmt::Result {
+    match c {
+        // `\n` or `\x` would be an escape, so letters and digits are written like any other byte
+        _ if Some(c) == quote && c.is_ascii_alphanumeric() => write_byte_escape(w, c as u8, next, options),
+        _ if Some(c) == quote => write!(w, "\\{}", c),
+        _ if c.is_ascii() => match escape_byte(c as u8) {
+            EscapeForm::Literal(byte) => w.write_char(byte as char),
+            _ => write_byte_escape(w, c as u8, next, options),
+        },
+        _ if options.ascii_only && cfg!(feature = "unicode") => write!(w, "\\u{{{:X}}}", c as u32),
+        _ if c.is_control() && cfg!(feature = "unicode") => write!(w, "\\u{:04X}", c as u32),
+        // without `\u`, the character is written as its UTF-8 bytes
+        _ if options.ascii_only || c.is_control() => {
+            let mut utf8 = [0u8; 4];
+            let utf8 = c.encode_utf8(&mut utf8).as_bytes();
+            for (j, &byte) in utf8.iter().enumerate() {
+                write_byte_escape(w, byte, utf8.get(j + 1).copied().or(next), options)?;
+            }
+            return Ok(());
+        }
+        _ => w.write_char(c),
+    }
+}
+
+/// Writes bytes with backslash escapes, also escaping the `quote` character if there is one
+fn write_escaped<W: fmt::Write>(w: &mut W, bs: &[u8], quote: Option<char>, options: &EscapeOptions) -> fmt::Result {
+    let quote: Option<char> = quote.or(options.quote.filter(u8::is_ascii_graphic).map(char::from));
+    // the offset of the next byte, so an escape can tell what comes after it
+    let mut i: usize = 0;
+    for chunk in bs.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            i += c.len_utf8();
+            write_escaped_char(w, c, bs.get(i).copied(), quote, options)?;
+        }
+        for &byte in chunk.invalid() {
+            i += 1;
+            write_byte_escape(w, byte, bs.get(i).copied(), options)?;
+        }
+    }
+    return Ok(());
+}
+
+/// Returns bytes with backslash escapes, the same way [EscapedDisplay] shows them
+pub fn escape_bytes(bs: &[u8]) -> String {
+    return escape_bytes_with(bs, &EscapeOptions::default());
+}
+
+/// Returns bytes with backslash escapes, formatted according to `options`
+pub fn escape_bytes_with(bs: &[u8], options: &EscapeOptions) -> String {
+    let mut r = String::with_capacity(bs.len());
+    write_escaped(&mut r, bs, None, options).expect("Writing to a String never fails.");
+    return r;
+}
+
+/// Returns the canonical escaped form of `escaped`, which is written in `dialect`
+///
+/// Two escaped strings mean the same bytes exactly when their canonical forms are equal, so it can be
+/// used to compare, deduplicate, or hash escaped strings however they were spelled: `\x41`, `\101`, and `A`
+/// all become `A`. The canonical form is what [escape_bytes] writes for the unescaped bytes, so it's
+/// accepted by a [Dialect] with [canonical](Dialect::canonical) set. Control characters that aren't ASCII,
+/// like `U+0085`, are written as `\uNNNN`, or as a `\xNN` for each byte without the `unicode` feature.
+pub fn canonicalize(escaped: &[u8], dialect: &Dialect) -> Result<String, ParseError> {
+    return Ok(escape_bytes(&unescape_bytes_with(escaped, dialect)?));
+}
+
+impl fmt::Display for EscapedDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write_escaped(f, self.0, None, &EscapeOptions::default());
+    }
+}
+
+impl fmt::Debug for EscapedDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        write_escaped(f, self.0, Some('"'), &EscapeOptions::default())?;
+        return f.write_char('"');
+    }
+}
+
+/// Quotes bytes for a POSIX-like shell, picking the most readable form that is safe
+///
+/// In order of preference, this returns:
+/// * a bare word, if every byte is a letter, digit, or one of `_@%+=:,./-`
+/// * `'...'`, if there are no single quotes or control characters and it's valid UTF-8
+/// * `"..."`, if there are single quotes, but nothing that's special inside double quotes
+/// * bash-style `$'...'` with backslash escapes, otherwise
+pub fn shell_quote(bs: &[u8]) -> String {
+    let printable: bool = match std::str::from_utf8(bs) {
+        Ok(s) => ! s.chars().any(|c| c.is_control()),
+        Err(_) => false,
+    };
+    if ! bs.is_empty() && bs.iter().all(|&byte| byte.is_ascii_alphanumeric() || b"_@%+=:,./-".contains(&byte)) {
+        return String::from_utf8(bs.to_vec()).expect("ASCII is valid UTF-8.");
+    } else if printable && ! bs.contains(&b'\'') {
+        return format!("'{}'", String::from_utf8_lossy(bs));
+    } else if printable && ! bs.iter().any(|byte| b"\"$`\\!".contains(byte)) {
+        return format!("\"{}\"", String::from_utf8_lossy(bs));
+    } else {
+        let mut r = String::with_capacity(bs.len() + 3);
+        r.push_str("$'");
+        write_escaped(&mut r, bs, Some('\''), &EscapeOptions::default()).expect("Writing to a String never fails.");
+        r.push('\'');
+        return r;
+    }
+}
+
+/// Writes bytes as a Rust byte string literal, like `b"\r\n"`
+///
+/// Printable ASCII is written as it is, except for `"` and `\`, and everything else uses
+/// escapes that Rust understands: `\n`, `\r`, `\t`, `\0`, or `\xNN`.
+pub fn to_rust_byte_literal(bs: &[u8]) -> String {
+    let mut r = String::with_capacity(bs.len() + 3);
+    r.push_str("b\"");
+    for &byte in bs {
+        match byte {
+            b'\n' => r.push_str("\\n"),
+            b'\r' => r.push_str("\\r"),
+            b'\t' => r.push_str("\\t"),
+            b'\0' => r.push_str("\\0"),
+            b'"' => r.push_str("\\\""),
+            b'\\' => r.push_str("\\\\"),
+            b' '..=b'~' => r.push(byte as char),
+            _ => r.push_str(&format!("\\x{:02X}", byte)),
+        }
+    }
+    r.push('"');
+    return r;
+}
+
+/// What [to_json_string] does with bytes that aren't valid UTF-8
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonUtf8Policy {
+    /// Return an error
+    #[default]
+    Error,
+    /// Replace each invalid sequence with a literal `U+FFFD` character, like [String::from_utf8_lossy]
+    Lossy,
+    /// Replace each invalid sequence with the escape `\uFFFD`, which means the same thing once parsed
+    EscapedReplacement,
+}
+
+/// Writes bytes as a JSON string, including the surrounding double quotes
+///
+/// JSON strings can only hold Unicode, so `policy` decides what happens to bytes that aren't
+/// valid UTF-8. Control characters use `\n`-style escapes where JSON has them and `\u00NN` otherwise.
+pub fn to_json_string(bs: &[u8], policy: NonUtf8Policy) -> Result<String, std::str::Utf8Error> {
+    if policy == NonUtf8Policy::Error {
+        std::str::from_utf8(bs)?;
+    }
+    let mut r = String::with_capacity(bs.len() + 2);
+    r.push('"');
+    for chunk in bs.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '"' => r.push_str("\\\""),
+                '\\' => r.push_str("\\\\"),
+                '\n' => r.push_str("\\n"),
+                '\r' => r.push_str("\\r"),
+                '\t' => r.push_str("\\t"),
+                '\u{08}' => r.push_str("\\b"),
+                '\u{0C}' => r.push_str("\\f"),
+                '\u{0}'..='\u{1F}' => r.push_str(&format!("\\u{:04X}", c as u32)),
+                _ => r.push(c),
+            }
+        }
+        if ! chunk.invalid().is_empty() {
+            match policy {
+                NonUtf8Policy::Error => unreachable!(),
+                NonUtf8Policy::Lossy => r.push('\u{FFFD}'),
+                NonUtf8Policy::EscapedReplacement => r.push_str("\\uFFFD"),
+            }
+        }
+    }
+    r.push('"');
+    return Ok(r);
+}
+
+/// Writes bytes as a C string literal, like `"\r\n"`
+///
+/// Bytes without a named escape are written as `\xNN`. Since C hex escapes don't stop after two
+/// digits, the literal is split into two adjacent literals, like `"\x0A" "b"`, whenever a hex
+/// digit comes right after a hex escape. A `?` that follows another `?` is escaped as `\?`, so
+/// nothing is read as a trigraph.
+pub fn to_c_literal(bs: &[u8]) -> String {
+    let mut r = String::with_capacity(bs.len() + 2);
+    r.push('"');
+    let mut after_hex = false;
+    let mut after_question = false;
+    for &byte in bs {
+        if after_hex && byte.is_ascii_hexdigit() {
+            r.push_str("\" \"");
+        }
+        after_hex = false;
+        match byte {
+            0x07 => r.push_str("\\a"),
+            0x08 => r.push_str("\\b"),
+            0x0C => r.push_str("\\f"),
+            b'\n' => r.push_str("\\n"),
+            b'\r' => r.push_str("\\r"),
+            b'\t' => r.push_str("\\t"),
+            0x0B => r.push_str("\\v"),
+            b'"' => r.push_str("\\\""),
+            b'\\' => r.push_str("\\\\"),
+            b'?' if after_question => r.push_str("\\?"),
+            b' '..=b'~' => r.push(byte as char),
+            _ => {
+                r.push_str(&format!("\\x{:02X}", byte));
+                after_hex = true;
+            }
+        }
+        after_question = byte == b'?';
+    }
+    r.push('"');
+    return r;
+}
+
+/// Quotes bytes exactly the way bash's `printf %q` does in the C locale
+///
+/// If there are any bytes that aren't printable ASCII, this uses `$'...'` quoting with octal
+/// escapes like `\377`. Otherwise, each character that's special to the shell gets a backslash
+/// in front of it, and the empty string becomes `''`. This is uglier than [shell_quote], but
+/// it's what bash itself would print.
+pub fn printf_q(bs: &[u8]) -> String {
+    if bs.is_empty() {
+        return String::from("''");
+    }
+    let mut r = String::with_capacity(bs.len() * 2);
+    if bs.iter().any(|&byte| ! (b' '..=b'~').contains(&byte)) {
+        r.push_str("$'");
+        for &byte in bs {
+            match byte {
+                0x07 => r.push_str("\\a"),
+                0x08 => r.push_str("\\b"),
+                0x1B => r.push_str("\\E"),
+                0x0C => r.push_str("\\f"),
+                b'\n' => r.push_str("\\n"),
+                b'\r' => r.push_str("\\r"),
+                b'\t' => r.push_str("\\t"),
+                0x0B => r.push_str("\\v"),
+                b'\\' => r.push_str("\\\\"),
+                b'\'' => r.push_str("\\'"),
+                b' '..=b'~' => r.push(byte as char),
+                _ => r.push_str(&format!("\\{:03o}", byte)),
+            }
+        }
+        r.push('\'');
+        return r;
+    }
+    for (i, &byte) in bs.iter().enumerate() {
+        let special: bool = match byte {
+            b'#' => i == 0,
+            b'~' => i == 0 || bs[i - 1] == b':' || bs[i - 1] == b'=',
+            _ => b" !\"$&'()*,;<>?[\\]^`{|}".contains(&byte),
+        };
+        if special {
+            r.push('\\');
+        }
+        r.push(byte as char);
+    }
+    return r;
+}
+
+/// How many different spellings [spell_byte] knows about
+#[cfg(any(feature = "arbitrary", feature = "proptest"))]
+pub(crate) const SPELLINGS: u8 = 6;
+
+/// Writes one of several different spellings of `byte` that unescape back to `byte` in `dialect`
+///
+/// Numeric spellings always use as many digits as `dialect` allows, so they can be concatenated
+/// without running into each other. `spelling` picks which one, falling back to `\xNN` if that
+/// spelling doesn't work for `byte`. `dialect` must allow at least 2 hex digits, 3 octal digits,
+/// and 2 `\u` digits.
+#[cfg(any(feature = "arbitrary", feature = "proptest"))]
+pub(crate) fn spell_byte(out: &mut Vec<u8>, byte: u8, spelling: u8, dialect: &Dialect) {
+    // a literal byte at the very start might be taken for part of a byte order mark
+    let literal_ok: bool = byte != b'\\' && ! (dialect.caret_notation && byte == b'^') && ! (out.is_empty() && dialect.bom != BomPolicy::Keep);
+    match spelling % SPELLINGS {
+        0 if literal_ok => out.push(byte),
+        1 if (literal_ok || byte == b'\\') && ! matches!(escape_byte(byte), EscapeForm::Hex(_)) => out.extend(escape_byte(byte).to_string().as_bytes()),
+        2 => out.extend(format!("\\{:0width$o}", byte, width = dialect.max_octal_digits).as_bytes()),
+        3 if byte == 0x1C && dialect.control_double_backslash => out.extend(b"\\c\\\\"),
+        3 if byte < 0x20 => out.extend([b'\\', b'c', byte + 0x40]),
+        3 if byte == 0x7F && dialect.control_delete => out.extend(b"\\c?"),
+        3 if byte >= 0x80 && byte != 0xDE && (byte & 0x7F) >= 0x20 && byte != 0xFF => out.extend([b'\\', b'M', b'-', byte & 0x7F]),
+        3 if (0x80..0xA0).contains(&byte) => out.extend([b'\\', b'M', b'-', b'^', (byte & 0x7F) + 0x40]),
+        3 if byte == 0xFF => out.extend(b"\\M-^?"),
+        4 if cfg!(feature = "unicode") && byte < 0x80 => out.extend(format!("\\u{:0width$X}", byte, width = dialect.max_u_digits).as_bytes()),
+        5 if dialect.caret_notation && byte < 0x20 => out.extend([b'^', byte + 0x40]),
+        5 if dialect.caret_notation && byte == 0x7F => out.extend(b"^?"),
+        _ => out.extend(format!("\\x{:0width$X}", byte, width = dialect.max_hex_digits).as_bytes()),
+    }
+}
@@ -0,0 +1,59 @@
+//! [Arbitrary] implementations, for fuzzing code that uses smashquote
+
+use arbitrary::{Arbitrary, Unstructured};
+
+use crate::{Dialect, NulPolicy, OutputEncoding};
+use crate::escape::{spell_byte, SPELLINGS};
+
+/// Raw bytes together with an escaped form that unescapes back to exactly those bytes
+///
+/// Its [Arbitrary] implementation picks a random [Dialect] and random bytes, and then spells
+/// each byte in a randomly chosen way, like a literal byte, `\xNN`, octal, `\cX`, or `\M-x`.
+#[derive(Debug, Clone)]
+pub struct EscapedInput {
+    /// The dialect that `escaped` is written in
+    pub dialect: Dialect,
+    /// The bytes before escaping
+    pub raw: Vec<u8>,
+    /// The escaped form of `raw`, which [unescape_bytes_with](crate::unescape_bytes_with) turns back into `raw`
+    pub escaped: Vec<u8>,
+}
+
+impl<'a> Arbitrary<'a> for EscapedInput {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let dialect = Dialect::arbitrary(u)?;
+        let mut raw: Vec<u8> = Vec::arbitrary(u)?;
+        if dialect.nul != NulPolicy::Allow {
+            raw.retain(|&byte| byte != 0);
+        }
+        if let Some(max_len) = dialect.max_output_len {
+            raw.truncate(max_len);
+        }
+        let mut escaped: Vec<u8> = Vec::with_capacity(raw.len() * 4);
+        for &byte in &raw {
+            spell_byte(&mut escaped, byte, u.int_in_range(0..=SPELLINGS-1)?, &dialect);
+        }
+        return Ok(Self { dialect, raw, escaped });
+    }
+}
+
+// The digit limits in a generated [Dialect] are kept big enough that every byte can be
+// spelled with a fixed number of digits, so spellings can't run into each other.
+
+pub(crate) fn max_hex_digits(u: &mut Unstructured<'_>) -> arbitrary::Result<usize> {
+    return u.int_in_range(2..=8);
+}
+
+pub(crate) fn max_octal_digits(u: &mut Unstructured<'_>) -> arbitrary::Result<usize> {
+    return u.int_in_range(3..=10);
+}
+
+pub(crate) fn max_u_digits(u: &mut Unstructured<'_>) -> arbitrary::Result<usize> {
+    return u.int_in_range(2..=8);
+}
+
+// UTF-16 output isn't generated, because then the output isn't the raw bytes anymore.
+
+pub(crate) fn encoding(u: &mut Unstructured<'_>) -> arbitrary::Result<OutputEncoding> {
+    return Ok(*u.choose(&[OutputEncoding::Utf8, OutputEncoding::Cesu8])?);
+}
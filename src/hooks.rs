@@ -0,0 +1,146 @@
+//! Callbacks that extend the decoder, for applications with escapes of their own
+
+use std::io::Write;
+use std::iter::Peekable;
+
+use crate::{unescape_iter_at_zero, unescape_slice_at_zero, Dialect, EncodeError, ScalarEncoder, UnescapeError};
+
+/// What an [EscapeHandler] did with an escape
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handled {
+    /// It wrote the output for the escape, which ends here
+    Done,
+    /// The escape goes on, so call the handler again with one more byte of it
+    NeedMore,
+    /// It isn't an escape the handler knows, so it's a [BackslashEscapeUnknown](crate::InvalidBackslashKind::BackslashEscapeUnknown) error
+    Unknown,
+}
+
+/// Decodes an escape that the [Dialect] doesn't understand, like `\%d`
+///
+/// It's called with the offset of the backslash, the bytes of the escape so far, starting with the
+/// backslash, and the output to write to. Errors it returns have [base_offset](Dialect::base_offset) added,
+/// like any other error. When decoding from an iterator, it can be called again with bytes it has
+/// already seen, so it should only write once it returns [Handled::Done].
+pub type EscapeHandler<'a> = &'a mut dyn FnMut(usize, &[u8], &mut dyn Write) -> Result<Handled, UnescapeError>;
+
+/// One piece of decoded output, for a [ScalarHook]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scalar {
+    /// A character from a unicode escape like `\u{E9}`
+    Char(char),
+    /// Any other byte, like literal text or `\xNN`
+    Byte(u8),
+}
+
+/// Looks at each piece of decoded output before it's written, and returns what to write instead, or
+/// [None] to leave it out
+///
+/// It's called after the [NulPolicy](crate::NulPolicy) is applied and before the output is encoded.
+/// Literal text is passed one byte at a time, even if it's UTF-8, and surrogates that aren't part of a
+/// pair aren't passed to it at all.
+pub type ScalarHook<'a> = &'a mut dyn FnMut(Scalar) -> Option<Scalar>;
+
+/// Callbacks for [unescape_iter_with_hooks]
+///
+/// The [Default] hooks don't do anything, so the output is the same as [unescape_iter_with].
+///
+/// [unescape_iter_with]: crate::unescape_iter_with
+#[derive(Default)]
+pub struct DecodeHooks<'a> {
+    /// Decodes escapes that the dialect doesn't understand, instead of them being errors. To reserve
+    /// an escape for the application, pick one that isn't in the dialect, like `\%` or `\q`.
+    pub escape: Option<EscapeHandler<'a>>,
+    /// Changes or leaves out pieces of the output, like for case folding or filtering, as they're decoded
+    pub scalar: Option<ScalarHook<'a>>,
+}
+
+/// A [ScalarEncoder] that passes everything through a [ScalarHook] first
+pub(crate) struct HookedEncoder<'e, 'h> {
+    pub(crate) encoder: &'e mut dyn ScalarEncoder,
+    pub(crate) hook: &'e mut ScalarHook<'h>,
+}
+
+impl ScalarEncoder for HookedEncoder<'_, '_> {
+    fn write_char(&mut self, c: char, out: &mut dyn Write) -> Result<(), EncodeError> {
+        return match (self.hook)(Scalar::Char(c)) {
+            Some(Scalar::Char(c)) => self.encoder.write_char(c, out),
+            Some(Scalar::Byte(b)) => self.encoder.write_bytes(&[b], out),
+            None => Ok(()),
+        };
+    }
+
+    fn write_surrogate(&mut self, unit: u16, out: &mut dyn Write) -> Result<(), EncodeError> {
+        return self.encoder.write_surrogate(unit, out);
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8], out: &mut dyn Write) -> Result<(), EncodeError> {
+        let mut pending: Vec<u8> = Vec::with_capacity(bytes.len());
+        for &byte in bytes {
+            match (self.hook)(Scalar::Byte(byte)) {
+                Some(Scalar::Byte(b)) => pending.push(b),
+                Some(Scalar::Char(c)) => {
+                    if ! pending.is_empty() {
+                        self.encoder.write_bytes(&pending, out)?;
+                        pending.clear();
+                    }
+                    self.encoder.write_char(c, out)?;
+                }
+                None => {}
+            }
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
+        return self.encoder.write_bytes(&pending, out);
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), EncodeError> {
+        return self.encoder.finish(out);
+    }
+}
+
+/// Writes an unescaped string from an iterator, using a specific [Dialect] and some [DecodeHooks]
+///
+/// # Arguments
+///
+/// * `bytes` - An iterator that yields a position and byte like `[u8].iter().enumerate().peekable()`
+/// * `out` - An output stream, like `Vec<u8>`
+/// * `close` - An optional closing delimiter to look for
+/// * `dialect` - Which optional escape syntaxes to understand
+/// * `hooks` - Callbacks that extend the decoder
+pub fn unescape_iter_with_hooks<'a, I, O>(
+    bytes: &mut Peekable<I>,
+    out: &mut O,
+    close: Option<u8>,
+    dialect: &Dialect,
+    hooks: &mut DecodeHooks<'_>,
+) -> Result<usize, UnescapeError>
+where
+    I: Iterator<Item = (usize, &'a u8)>,
+    I: ExactSizeIterator<Item = (usize, &'a u8)>,
+    O: Write,
+{
+    let mut encoder = dialect.encoding.encoder();
+    return unescape_iter_at_zero(bytes, out, close, dialect, encoder.as_mut(), hooks).map_err(|e| match e {
+        UnescapeError::Parse(e) => UnescapeError::Parse(e.offset_by(dialect.base_offset)),
+        e => e,
+    });
+}
+
+/// Returns a new unescaped byte string from a byte slice, using a specific [Dialect] and some [DecodeHooks]
+///
+/// Hooks can fail with any [UnescapeError], so this returns one, unlike [unescape_bytes_with](crate::unescape_bytes_with).
+pub fn unescape_bytes_with_hooks(
+    bytes: &[u8],
+    dialect: &Dialect,
+    hooks: &mut DecodeHooks<'_>,
+) -> Result<Vec<u8>, UnescapeError> {
+    let mut r: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut encoder = dialect.encoding.encoder();
+    unescape_slice_at_zero(bytes, &mut r, None, dialect, encoder.as_mut(), hooks).map_err(|e| match e {
+        UnescapeError::Parse(e) => UnescapeError::Parse(e.offset_by(dialect.base_offset)),
+        e => e,
+    })?;
+    return Ok(r);
+}
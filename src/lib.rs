@@ -1,6 +1,7 @@
 #![deny(rust_2018_idioms)]
 #![deny(rust_2021_compatibility)]
 #![deny(missing_docs)]
+#![allow(clippy::needless_return)]
 
 //! smashquote - Removes C-like quotes from byte slices
 //!
@@ -30,16 +31,40 @@
 //! * `\\` - backslash `0x5C` (a single `\`)
 //! * `\'` - single quote `0x27` (a single `'`)
 //! * `\"` - double quote `0x22` (a single `"`)
-//! * `\0` through `\377` - a single byte, specified in octal. The sequence stops at the first character that's not a hexidecimal digit.
-//! * `\x0` through `\xFF` - a single byte, specified in hex. The sequence stops at the first character that's not a hexidecimal digit.
-//! * `\u0` through `\uFFFF` - utf8 bytes of a single character, specified in hex. The sequence stops at the first character that's not a hexidecimal digit.
+//! * `\0` through `\377` - a single byte, specified in octal. The sequence stops at the first character that's not an octal digit, or after 3 digits. See `OctalOverflow` for `\400` through `\777`.
+//! * `\o{0}` through `\o{377}` - a single byte, specified in octal between braces
+//! * `\x0` through `\xFF` - a single byte, specified in hex. The sequence stops at the first character that's not a hexidecimal digit, or after 2 digits.
+//! * `\x{0}` through `\x{FF}` - a single byte, specified in hex between braces, like Perl and Ruby
+//! * `\x{DEADBEEF}` - any number of bytes, specified in hex between braces, two digits per byte
+//! * `\u0` through `\uFFFF` - utf8 bytes of a single character, specified in hex. The sequence stops at the first character that's not a hexidecimal digit, or after 4 digits.
 //! * `\u{0}` through `\u{10FFFF}` - utf8 bytes of a single character, specified in Rust style hex
-//! * `\U0` through `\UFFFFFFFF` - utf8 bytes of a single character, specified in hex (of course, the actual maximum is 10FFFF, because that's currently the maximum valid codepoint). The sequence stops at the first character that's not a hexidecimal digit.
+//! * `\U0` through `\UFFFFFFFF` - utf8 bytes of a single character, specified in hex (of course, the actual maximum is 10FFFF, because that's currently the maximum valid codepoint). The sequence stops at the first character that's not a hexidecimal digit, or after 8 digits.
 //! * `\c@`, `\cA` through `\cZ`, `\c[`, `\c\`, `\c]`, `\c^`, `\c_` - a control-x character (case insensitive, for some reason) `0x0` through `0x1F`
 //! * ``\c` ``, `\ca` through `\cz`, `\c{`, `\c|`, `\c}`, `\c~` - a control-x character (same as above) `0x0` through `0x1F`
+//! * `\c?` - delete `0x7F`, like bash
+//! * `\c\\` - control-backslash `0x1C`, like bash (a lone `\c\` works too)
+//! * `\M-x` - the byte `x` with the high bit set, as printed by `cat -v`, `0x80` through `0xFF`
+//! * `\M-^@` through `\M-^_`, `\M-^?` - a control-x character with the high bit set, as printed by `cat -v`, `0x80` through `0x9F` and `0xFF`
+//!
+//! The number of digits read by the numeric escapes, and a few other details, can be changed with a `Dialect`.
 //!
 //! smashquote produces errors that are compatible with crates like [anyhow](https://crates.io/crates/anyhow).
 //!
+//! ## Optional features
+//!
+//! * `shell` (default) - The lexer and other helpers for shell-like text, like `lex_line` and `split_fields`
+//! * `unicode` (default) - `\u` and `\U` escapes. Without it, they're unknown escapes, which saves some code size where they're never used
+//! * `arbitrary` - [Arbitrary](https://crates.io/crates/arbitrary) implementations, including `EscapedInput`, for fuzzing code that uses smashquote
+//! * `codespan-reporting` - `to_diagnostic` for `ParseError` and `UnescapeError`, which makes a [codespan-reporting](https://crates.io/crates/codespan-reporting) `Diagnostic` labelled with where the problem is
+//! * `color` - `render_colored` for `ParseError` and `UnescapeError`, which shows where the problem is in red, like a compiler diagnostic
+//! * `bash-oracle` - The `oracle` module, which compares smashquote with what bash does for the same strings. It runs bash, so it's for testing smashquote itself
+//! * `encoding_rs` - Encode characters from unicode escapes in legacy encodings like Latin-1 or Shift_JIS, with [encoding_rs](https://crates.io/crates/encoding_rs)
+//! * `mmap` - `--mmap` in the `smashquote` binary, which maps the input file into memory instead of reading it, with [memmap2](https://crates.io/crates/memmap2)
+//! * `proptest` - [proptest](https://crates.io/crates/proptest) strategies in the `strategies` module, for round-trip property tests
+//! * `serde` - [Serialize](https://docs.rs/serde/latest/serde/trait.Serialize.html) for `UnescapeError` and `ParseError`, so services can report exactly what was wrong and where, and `--error-format=json` in the `smashquote` binary, with [serde_json](https://crates.io/crates/serde_json)
+//! * `unicode-normalization` - `unescape_bytes_normalized`, which puts the output in NFC or NFD with [unicode-normalization](https://crates.io/crates/unicode-normalization)
+//! * `width` - `max_columns` in `PrettyStringOptions`, which truncates to a number of terminal columns without splitting graphemes, with [unicode-width](https://crates.io/crates/unicode-width) and [unicode-segmentation](https://crates.io/crates/unicode-segmentation)
+//!
 //! ## Acknowledgements
 //!
 //! Thanks to [Zoybean](https://github.com/Zoybean)
@@ -49,69 +74,147 @@
 use std::iter::Peekable;
 use std::io::Write;
 
-/// Prints bytes as space-separated hex digits
-pub fn pretty_bytes(bs: &[u8]) -> String {
-    bs
-        .iter()
-        .map(|byte| format!("{:02X}", byte))
-        .collect::<Vec<String>>()
-        .join(" ")
-}
-
-/// Tries to represent bytes as presentable unicode
-pub fn pretty_string(bs: &[u8]) -> String {
-    String::from_utf8_lossy(bs).chars()
-        .map(|c| match c {
-        '\u{0}'..='\u{20}' => char::from_u32((c as u32) + 0x2400u32).expect("Unicode code points 0x2400-2420 are valid."),
-        '\u{7F}' => '\u{247F}',
-        _ => c,
-        }).collect()
-}
+mod pretty;
+pub use pretty::*;
+mod escape;
+pub use escape::*;
+mod encoder;
+pub use encoder::*;
+mod stream;
+pub use stream::*;
+mod placeholder;
+pub use placeholder::*;
+mod lines;
+pub use lines::*;
+mod hooks;
+pub use hooks::*;
+mod compare;
+pub use compare::*;
+mod vectored;
+pub use vectored::*;
+mod chars;
+pub use chars::*;
+#[cfg(feature = "shell")]
+mod shell;
+#[cfg(feature = "shell")]
+pub use shell::*;
+#[cfg(feature = "arbitrary")]
+mod fuzzing;
+#[cfg(feature = "arbitrary")]
+pub use fuzzing::*;
+pub mod conformance;
+#[cfg(feature = "color")]
+mod color;
+#[cfg(feature = "codespan-reporting")]
+mod diagnostic;
+#[cfg(feature = "proptest")]
+pub mod strategies;
+#[cfg(all(unix, feature = "bash-oracle"))]
+pub mod oracle;
+#[cfg(feature = "unicode-normalization")]
+mod normalize;
+#[cfg(feature = "unicode-normalization")]
+pub use normalize::*;
 
 /// Describes what kind of invalid backslash escape was found
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum InvalidBackslashKind {
     /// `\u{`
     RustStyleUnicodeMissingCloseBrace,
     /// `\u{}`
     RustStyleUnicodeMissingDigits,
-    /// Unicode escape wasn't a valid codepoint
-    UnicodeEscapeBadCodepoint,
-    /// Non-unicode bytes instead of hex digits
-    HexDigitsNotUnicode,
-    /// Hex digits werent 0-9A-Fa-f
+    /// Unicode escape wasn't a valid codepoint, which is this value
+    UnicodeEscapeBadCodepoint(u32),
+    /// `\x{`
+    BracedHexMissingCloseBrace,
+    /// `\x{}`
+    BracedHexMissingDigits,
+    /// `\x{...}` with more than 2 digits, but not an even number of them
+    BracedHexOddDigits,
+    /// Non-unicode bytes instead of hex digits, which are these bytes
+    HexDigitsNotUnicode(Vec<u8>),
+    /// Hex digits werent 0-9A-Fa-f, or were too many to fit in a [u32], which are these bytes
     HexDigitsNotHexDigits(Vec<u8>),
     /// `\x` not followed by any digits
     HexDigitsNoDigits,
-    /// Non-unicode bytes instead of octal digits
-    OctalDigitsNotUnicode,
-    /// Octal digits weren't 0-7
-    OctalDigitsNotOctalDigits,
+    /// Non-unicode bytes instead of octal digits, which are these bytes
+    OctalDigitsNotUnicode(Vec<u8>),
+    /// Octal digits weren't 0-7, which are these bytes
+    OctalDigitsNotOctalDigits(Vec<u8>),
+    /// Octal escape was `\400` or more, which doesn't fit in a byte, and this is its value
+    OctalEscapeOverflow(u32),
+    /// `\o` not followed by `{`
+    BracedOctalMissingOpenBrace,
+    /// `\o{`
+    BracedOctalMissingCloseBrace,
+    /// `\o{}`
+    BracedOctalMissingDigits,
+    /// `\x` escape was more than `0xFF`, which doesn't fit in a byte, and this is its value
+    HexEscapeOverflow(u32),
     /// `\u` not followed by any digits
     UnicodeEscapeNoDigits,
     /// `\u` right at end of the string
     UnicodeEscapeEndOfString,
-    /// `\c` followed by an unknown key
-    ControlEscapeBadKey,
+    /// `\u` or `\U` with fewer digits than required by [fixed_width_unicode](Dialect::fixed_width_unicode)
+    UnicodeEscapeTooFewDigits,
+    /// `\c` followed by an unknown key, which is this byte
+    ControlEscapeBadKey(u8),
     /// `\c` right at the end of the string
     ControlEscapeEndOfString,
-    /// `\` followed by an unknown character
-    BackslashEscapeUnknown,
+    /// `\M` not followed by `-`
+    MetaEscapeMissingDash,
+    /// `\M-` followed by a non-ASCII byte, which is this byte
+    MetaEscapeBadKey(u8),
+    /// `\M` or `\M-` right at the end of the string
+    MetaEscapeEndOfString,
+    /// `\` followed by an unknown character, which starts with this byte
+    BackslashEscapeUnknown(u8),
     /// `\` right at the end of the string
     BackslashEndOfString,
+    /// An escape that isn't the canonical way to write its output, while [canonical](Dialect::canonical) is set
+    NotCanonical,
 }
 
 use InvalidBackslashKind::*;
 
-/// Error type of unescape/unquote functions.
-#[derive(Debug)]
-pub enum UnescapeError 
+impl InvalidBackslashKind {
+    /// The digits of a numeric escape that couldn't be parsed, or [None] for other kinds
+    pub fn digits(&self) -> Option<&[u8]> {
+        return match self {
+            HexDigitsNotUnicode(digits) |
+            HexDigitsNotHexDigits(digits) |
+            OctalDigitsNotUnicode(digits) |
+            OctalDigitsNotOctalDigits(digits) => Some(digits),
+            _ => None,
+        };
+    }
+    
+    /// The radix of the [digits](InvalidBackslashKind::digits), or of the escape that overflowed,
+    /// or [None] for kinds that aren't about digits
+    pub fn radix(&self) -> Option<u32> {
+        return match self {
+            HexDigitsNotUnicode(_) |
+            HexDigitsNotHexDigits(_) |
+            HexEscapeOverflow(_) => Some(16),
+            OctalDigitsNotUnicode(_) |
+            OctalDigitsNotOctalDigits(_) |
+            OctalEscapeOverflow(_) => Some(8),
+            _ => None,
+        };
+    }
+}
+
+/// Something wrong with the input, found while unescaping it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ParseError 
 where
-    UnescapeError: Send,
-    UnescapeError: Sync,
-    UnescapeError: 'static,
-    UnescapeError: std::fmt::Display,
-    UnescapeError: std::error::Error,
+    ParseError: Send,
+    ParseError: Sync,
+    ParseError: 'static,
+    ParseError: std::fmt::Display,
+    ParseError: std::error::Error,
 {
     /// An invalid backslash escape sequence while parsing
     InvalidBackslash {
@@ -121,11 +224,17 @@ where
         /// The byte offset of the backslash escape
         offset: usize,
         
+        /// How many bytes of the input the backslash escape takes up, as far as it was read
+        len: usize,
+        
         /// An attempt at showing the backslash escape sequence as a string
         string: String,
         
         /// The backslash escape sequence as raw hex bytes
         bytes: String,
+        
+        /// A hint about how to fix the escape, if there's an obvious one
+        suggestion: Option<Suggestion>,
     },
     
     /// Reached end of string while looking for closing delimiter byte
@@ -137,22 +246,147 @@ where
         bytes: String,
     },
     
-    /// Some I/O error happened...
-    IOError(std::io::Error),
+    /// Found a UTF-16 byte order mark at the start, while [bom](Dialect::bom) is [BomPolicy::StripAndRejectUtf16]
+    Utf16Bom {
+        /// The byte offset of the byte order mark
+        offset: usize,
+        
+        /// The byte order mark as raw hex bytes
+        bytes: String,
+    },
+    
+    /// Decoded a NUL byte, while [nul](Dialect::nul) is [NulPolicy::Reject]
+    NulByte {
+        /// The byte offset of the escape sequence or literal byte that decoded to NUL
+        offset: usize,
+    },
+    
+    /// The output would have been longer than [max_output_len](Dialect::max_output_len)
+    OutputTooLong {
+        /// The byte offset of the escape sequence or literal byte that went over the limit
+        offset: usize,
+        
+        /// The limit that was reached
+        max_len: usize,
+    },
+    
+    /// Found bytes that aren't valid UTF-8, while [encoding](Dialect::encoding) is UTF-16
+    NotUtf8 {
+        /// The byte offset of the escape sequence or literal byte where the invalid UTF-8 was noticed
+        offset: usize,
+    },
+    
+    /// Found a character that the output encoding doesn't have
+    Unmappable {
+        /// The byte offset of the escape sequence
+        offset: usize,
+        
+        /// The character that couldn't be written
+        character: char,
+        
+        /// The name of the encoding
+        encoding: &'static str,
+    },
+    
+    /// A variable that [lex_line_with] couldn't find, while [unset](LexOptions::unset) is [UnsetPolicy::Error],
+    /// or a placeholder that [unescape_placeholders] couldn't find
+    UnsetVariable {
+        /// The byte offset of the `$`, or the start of the placeholder
+        offset: usize,
+        
+        /// An attempt at showing the variable name as a string
+        name: String,
+    },
+    
+    /// A command substitution like `$(...)`, while [substitution](LexOptions::substitution) is [SubstitutionPolicy::Error]
+    CommandSubstitution {
+        /// The byte offset of the `$` or `` ` ``
+        offset: usize,
+    },
+    
+    /// Found a control character in the input as-is, while [reject_raw_controls](Dialect::reject_raw_controls) is set,
+    /// or a byte that isn't ASCII, while [ascii_source](Dialect::ascii_source) is set
+    RawByte {
+        /// The byte offset of the byte
+        offset: usize,
+        
+        /// The byte that had to be written as an escape
+        byte: u8,
+    },
 }
 
-impl std::fmt::Display for UnescapeError {
+impl std::fmt::Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::InvalidBackslash{kind, offset, string, bytes} => write!(f, "Invalid backslash ({:?}) at byte {}: {} ({})", kind, offset, string, bytes),
+            Self::InvalidBackslash{kind, offset, string, bytes, suggestion: None, ..} => write!(f, "Invalid backslash ({:?}) at byte {}: {} ({})", kind, offset, string, bytes),
+            Self::InvalidBackslash{kind, offset, string, bytes, suggestion: Some(suggestion), ..} => write!(f, "Invalid backslash ({:?}) at byte {}: {} ({}); {}", kind, offset, string, bytes, suggestion),
             Self::MissingClose{string, bytes} => write!(f, "Reached end of string while looking for closing delimiter byte {} ({})", string, bytes),
-            Self::IOError(e) => write!(f, "While unescaping: {e}"),
+            Self::Utf16Bom{offset, bytes} => write!(f, "Found a UTF-16 byte order mark at byte {} ({}), but expected UTF-8", offset, bytes),
+            Self::NulByte{offset} => write!(f, "Decoded a NUL byte from byte {}, which isn't allowed", offset),
+            Self::OutputTooLong{offset, max_len} => write!(f, "Unescaping byte {} would make the output longer than {} bytes", offset, max_len),
+            Self::NotUtf8{offset} => write!(f, "Can't encode the output as UTF-16, because it isn't valid UTF-8 at byte {}", offset),
+            Self::Unmappable{offset, character, encoding} => write!(f, "Can't encode {:?} (U+{:04X}) from byte {} in {}", character, *character as u32, offset, encoding),
+            Self::UnsetVariable{offset, name} => write!(f, "Variable {} at byte {} isn't set", name, offset),
+            Self::CommandSubstitution{offset} => write!(f, "Found a command substitution at byte {}, which isn't allowed", offset),
+            Self::RawByte{offset, byte} => write!(f, "Found {} (0x{:02X}) as-is at byte {}, but it has to be written as an escape", pretty_string(&[*byte]), byte, offset),
         }
     }
 }
 
-impl UnescapeError {
-    /// Generates a [MissingClose](UnescapeError::MissingClose) error from a 1-byte delimiter
+impl ParseError {
+    /// The byte offset where the problem was found, or [None] for [MissingClose](ParseError::MissingClose),
+    /// which is about the end of the input
+    pub fn offset(&self) -> Option<usize> {
+        return match self {
+            Self::InvalidBackslash{offset, ..} |
+            Self::Utf16Bom{offset, ..} |
+            Self::NulByte{offset} |
+            Self::OutputTooLong{offset, ..} |
+            Self::NotUtf8{offset} |
+            Self::Unmappable{offset, ..} |
+            Self::UnsetVariable{offset, ..} |
+            Self::CommandSubstitution{offset} |
+            Self::RawByte{offset, ..} => Some(*offset),
+            Self::MissingClose{..} => None,
+        };
+    }
+    
+    /// The bytes of the input that the problem is about, like the whole of an invalid escape, or [None] for
+    /// [MissingClose](ParseError::MissingClose), which is about the end of the input
+    pub fn span(&self) -> Option<std::ops::Range<usize>> {
+        let offset = self.offset()?;
+        let len = match self {
+            Self::InvalidBackslash{len, ..} => *len,
+            Self::Utf16Bom{..} => 2,
+            _ => 1,
+        };
+        return Some(offset..offset + len);
+    }
+    
+    /// Returns the same error, with `base` added to its offset
+    pub fn offset_by(self, base: usize) -> Self {
+        return match self {
+            Self::InvalidBackslash{kind, offset, len, string, bytes, suggestion} => Self::InvalidBackslash{
+                kind,
+                offset: base + offset,
+                len,
+                string,
+                bytes,
+                suggestion: suggestion.map(|s| s.offset_by(base)),
+            },
+            Self::Utf16Bom{offset, bytes} => Self::Utf16Bom{offset: base + offset, bytes},
+            Self::NulByte{offset} => Self::NulByte{offset: base + offset},
+            Self::OutputTooLong{offset, max_len} => Self::OutputTooLong{offset: base + offset, max_len},
+            Self::NotUtf8{offset} => Self::NotUtf8{offset: base + offset},
+            Self::Unmappable{offset, character, encoding} => Self::Unmappable{offset: base + offset, character, encoding},
+            Self::UnsetVariable{offset, name} => Self::UnsetVariable{offset: base + offset, name},
+            Self::CommandSubstitution{offset} => Self::CommandSubstitution{offset: base + offset},
+            Self::RawByte{offset, byte} => Self::RawByte{offset: base + offset, byte},
+            Self::MissingClose{..} => self,
+        };
+    }
+    
+    /// Generates a [MissingClose](ParseError::MissingClose) error from a 1-byte delimiter
     pub fn missing_close(byte: u8) -> Self {
         return Self::MissingClose {
             string: pretty_string(&[byte]),
@@ -160,93 +394,1063 @@ impl UnescapeError {
         };
     }
     
-    /// Generates an [InvalidBackslash](UnescapeError::InvalidBackslash) error
+    /// Generates an [InvalidBackslash](ParseError::InvalidBackslash) error
+    ///
+    /// An unknown escape like `\q` gets a [Suggestion::EscapeBackslash].
     pub fn invalid_backslash(
         offset: usize,
         bytes: &[u8],
         kind: InvalidBackslashKind,
     ) -> Self {
+        let suggestion = match kind {
+            BackslashEscapeUnknown(_) => {
+                let mut escaped: Vec<u8> = Vec::with_capacity(bytes.len() + 1);
+                escaped.push(b'\\');
+                escaped.extend_from_slice(bytes);
+                Some(Suggestion::EscapeBackslash { replacement: pretty_string(&escaped) })
+            }
+            _ => None,
+        };
         return Self::InvalidBackslash {
-            kind: kind,
-            offset: offset,
+            kind,
+            offset,
+            len: bytes.len(),
             string: pretty_string(bytes),
             bytes: pretty_bytes(bytes),
+            suggestion,
+        }
+    }
+    
+    /// The hint about how to fix the error, if there is one
+    pub fn suggestion(&self) -> Option<&Suggestion> {
+        return match self {
+            Self::InvalidBackslash{suggestion, ..} => suggestion.as_ref(),
+            _ => None,
+        };
+    }
+}
+
+/// A hint about how to fix an [InvalidBackslash](ParseError::InvalidBackslash) error
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Suggestion {
+    /// The escape isn't one smashquote knows, so maybe the backslash was meant literally
+    EscapeBackslash {
+        /// An attempt at showing the escape with its backslash escaped, like `\\q` for `\q`
+        replacement: String,
+    },
+    
+    /// The digits of a numeric escape had something in them that isn't a digit
+    NotADigit {
+        /// The byte offset of the first byte that isn't a digit
+        offset: usize,
+        
+        /// The byte that isn't a digit
+        byte: u8,
+        
+        /// The radix the digits were supposed to be in, 8 or 16
+        radix: u32,
+    },
+}
+
+impl Suggestion {
+    /// Returns the same suggestion, with `base` added to its offset
+    pub fn offset_by(self, base: usize) -> Self {
+        return match self {
+            Self::NotADigit{offset, byte, radix} => Self::NotADigit{offset: base + offset, byte, radix},
+            Self::EscapeBackslash{..} => self,
+        };
+    }
+}
+
+impl std::fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EscapeBackslash{replacement} => write!(f, "did you mean {} for a literal backslash?", replacement),
+            Self::NotADigit{offset, byte, radix} => {
+                let digit = match radix {
+                    8 => String::from("an octal"),
+                    16 => String::from("a hex"),
+                    _ => format!("a base {}", radix),
+                };
+                write!(f, "{} at byte {} isn't {} digit", pretty_string(&[*byte]), offset, digit)
+            }
+        }
+    }
+}
+
+/// Makes a digit error for `escape`, with a [Suggestion::NotADigit] pointing at the first byte of
+/// `escape[digits]` that isn't a digit in the [radix](InvalidBackslashKind::radix) of `kind`, if there is one
+fn bad_digits(
+    offset: usize,
+    escape: &[u8],
+    digits: std::ops::Range<usize>,
+    kind: InvalidBackslashKind,
+) -> ParseError {
+    let radix = kind.radix().unwrap_or(10);
+    let mut error = ParseError::invalid_backslash(offset, escape, kind);
+    if let ParseError::InvalidBackslash{suggestion, ..} = &mut error {
+        let start = digits.start;
+        *suggestion = escape[digits].iter().position(|&b| ! (b as char).is_digit(radix)).map(|i| Suggestion::NotADigit {
+            offset: offset + start + i,
+            byte: escape[start + i],
+            radix,
+        });
+    }
+    return error;
+}
+
+impl std::error::Error for ParseError {
+}
+
+/// Error type of unescape/unquote functions, which can fail either because of the input or because of
+/// the [Write] they write to
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum UnescapeError 
+where
+    UnescapeError: Send,
+    UnescapeError: Sync,
+    UnescapeError: 'static,
+    UnescapeError: std::fmt::Display,
+    UnescapeError: std::error::Error,
+{
+    /// Something was wrong with the input
+    Parse(ParseError),
+    
+    /// Some I/O error happened...
+    IOError(IoError),
+}
+
+impl std::fmt::Display for UnescapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "{e}"),
+            Self::IOError(e) => write!(f, "While unescaping: {e}"),
         }
     }
 }
 
+impl UnescapeError {
+    /// Unwraps the [ParseError], for when writing can't fail, like into a [Vec]
+    pub(crate) fn into_parse_error(self) -> ParseError {
+        return match self {
+            Self::Parse(e) => e,
+            Self::IOError(e) => unreachable!("Writing to a Vec can't fail, but: {e}"),
+        };
+    }
+}
+
+impl From<ParseError> for UnescapeError {
+    fn from(error: ParseError) -> Self {
+        UnescapeError::Parse(error)
+    }
+}
+
 impl From<std::io::Error> for UnescapeError {
     fn from(error: std::io::Error) -> Self {
-        UnescapeError::IOError(error)
+        UnescapeError::IOError(IoError::from(error))
     }
 }
 
 impl std::error::Error for UnescapeError {
 }
 
-fn unhex<'a>(
+/// An I/O error that can be cloned and compared, so that [UnescapeError] can be too
+///
+/// Two of them are equal when they have the same [kind](std::io::ErrorKind) and message.
+#[derive(Debug, Clone)]
+pub struct IoError(pub std::sync::Arc<std::io::Error>);
+
+impl IoError {
+    /// The kind of the I/O error
+    pub fn kind(&self) -> std::io::ErrorKind {
+        return self.0.kind();
+    }
+}
+
+impl From<std::io::Error> for IoError {
+    fn from(error: std::io::Error) -> Self {
+        IoError(std::sync::Arc::new(error))
+    }
+}
+
+impl PartialEq for IoError {
+    fn eq(&self, other: &Self) -> bool {
+        return self.kind() == other.kind() && self.0.to_string() == other.0.to_string();
+    }
+}
+
+impl Eq for IoError {
+}
+
+impl std::fmt::Display for IoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return self.0.fmt(f);
+    }
+}
+
+impl std::error::Error for IoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return self.0.source();
+    }
+}
+
+/// I/O errors aren't serializable, so they're serialized as their message
+#[cfg(feature = "serde")]
+impl serde::Serialize for IoError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        return serializer.collect_str(self);
+    }
+}
+
+/// Selects which optional escape syntaxes are understood while unescaping
+///
+/// The [Default] dialect understands exactly the backslash escapes listed in the
+/// crate documentation, and is what [unescape_iter] and [unescape_bytes] use.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Dialect {
+    /// Also decode caret notation, like `^M` for carriage return and `^?` for delete.
+    ///
+    /// `^@` through `^_` are `0x0` through `0x1F` (case insensitive, like `\c`), and
+    /// `^?` is `0x7F`. A `^` that isn't followed by one of those is left as a literal `^`.
+    /// Use `\x5E` for a literal `^` that is followed by one of those.
+    pub caret_notation: bool,
+    /// What to do with octal escapes from `\400` through `\777`, which don't fit in a byte
+    pub octal_overflow: OctalOverflow,
+    /// The most digits read after `\x`. bash stops after 2, while C keeps going.
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = fuzzing::max_hex_digits))]
+    pub max_hex_digits: usize,
+    /// The most digits read in an octal escape like `\377`, counting the first one
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = fuzzing::max_octal_digits))]
+    pub max_octal_digits: usize,
+    /// The most digits read after `\u`, when it isn't followed by `{`
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = fuzzing::max_u_digits))]
+    pub max_u_digits: usize,
+    /// The most digits read after `\U`
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = fuzzing::max_u_digits))]
+    pub max_big_u_digits: usize,
+    /// Require `\u` and `\U` to have exactly `max_u_digits` and `max_big_u_digits` digits,
+    /// like Java, JSON, and Go do, instead of stopping at the first character that isn't a hex digit.
+    /// `\u{...}` isn't affected.
+    pub fixed_width_unicode: bool,
+    /// Decode `\c?` as delete `0x7F`, like bash does. Otherwise it's a
+    /// [ControlEscapeBadKey](InvalidBackslashKind::ControlEscapeBadKey) error.
+    pub control_delete: bool,
+    /// Read control-backslash written as `\c\\` as one escape, like bash does. A lone `\c\` is
+    /// still control-backslash either way, but otherwise the second backslash starts a new escape.
+    pub control_double_backslash: bool,
+    /// Also decode `\s` as a space `0x20`, like Ruby does
+    pub space_escape: bool,
+    /// Whether a backslash at the end of a line joins it to the next line, like in C and shell
+    pub line_continuation: LineContinuation,
+    /// What to do with a byte order mark at the start, which editors on Windows like to add
+    pub bom: BomPolicy,
+    /// What to do with NUL bytes in the output, whether they came from an escape like `\0` or were
+    /// in the input as-is. Rejecting them is handy before passing the output to a C string or the OS.
+    pub nul: NulPolicy,
+    /// The most bytes to write, before giving up with an [OutputTooLong](ParseError::OutputTooLong) error.
+    /// Set this when the input isn't trusted and might be arbitrarily long, like when it's read from the network.
+    pub max_output_len: Option<usize>,
+    /// What to do with surrogates from unicode escapes like `\uD800`, which can't be encoded in UTF-8
+    pub surrogates: SurrogatePolicy,
+    /// Also stop at the close delimiter in the other case, like `X` for `x`
+    pub close_ignore_case: bool,
+    /// Other bytes that also count as the close delimiter, like `"` to stop at either `'` or `"`.
+    /// These only count when there's a close delimiter at all.
+    pub close_bytes: Vec<u8>,
+    /// An open delimiter that pairs with the close delimiter, like `{` for `}`. Each one inside the string
+    /// means one more close delimiter is needed to end it, and both are kept in the output, so
+    /// `a{b}c}` ends at the second `}`. Escaped ones like `\{` or `\}` don't count, and unescape to
+    /// just the delimiter.
+    pub open_delimiter: Option<u8>,
+    /// Reject escapes that aren't the one obvious way to write their output, so inputs can't dodge
+    /// pattern matching by writing the same thing differently. The one way is what [escape_bytes] writes:
+    /// a named escape like `\n` or `\e` where there is one, otherwise `\xNN` with two uppercase digits,
+    /// and `\uNNNN` with four uppercase digits only for control characters that aren't ASCII. Anything else
+    /// is a [NotCanonical](InvalidBackslashKind::NotCanonical) error, like `\x0A`, `\012`, `\cJ`, `\E`,
+    /// `\M-A`, `\xff`, `^A`, `\u00E9`, escapes for printable ASCII like `\x41` or `\"`, and line continuations.
+    /// Escaped delimiters are still allowed, since they're the only way to write the delimiter. Bytes that
+    /// aren't escaped aren't checked, and each `\xNN` is checked on its own, so UTF-8 written as `\xC3\xA9`
+    /// is still allowed even though [escape_bytes] writes `é`.
+    #[cfg_attr(feature = "arbitrary", arbitrary(default))]
+    pub canonical: bool,
+    /// Reject control characters below `0x20` that are in the input as-is, rather than written as escapes
+    /// like `\t`, with a [RawByte](ParseError::RawByte) error. That's handy for file formats that promise
+    /// every record is one printable line.
+    #[cfg_attr(feature = "arbitrary", arbitrary(default))]
+    pub reject_raw_controls: bool,
+    /// The control characters that can still be in the input as-is when [reject_raw_controls](Dialect::reject_raw_controls)
+    /// is set, like `\t`
+    pub allowed_raw_controls: Vec<u8>,
+    /// Reject bytes from `0x80` through `0xFF` that are in the input as-is, with a [RawByte](ParseError::RawByte)
+    /// error, for protocols that are ASCII on the wire. Escapes can still write any bytes.
+    #[cfg_attr(feature = "arbitrary", arbitrary(default))]
+    pub ascii_source: bool,
+    /// How the output is encoded. Except for UTF-16, this only changes characters from unicode escapes like
+    /// `\u00E9`, and other bytes are written as-is.
+    #[cfg_attr(feature = "arbitrary", arbitrary(with = fuzzing::encoding))]
+    pub encoding: OutputEncoding,
+    /// Added to the offsets in errors, for when the input is a slice of a bigger document and errors
+    /// should point into the whole document
+    #[cfg_attr(feature = "arbitrary", arbitrary(default))]
+    pub base_offset: usize,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        return Self {
+            caret_notation: false,
+            octal_overflow: OctalOverflow::Error,
+            max_hex_digits: 2,
+            max_octal_digits: 3,
+            max_u_digits: 4,
+            max_big_u_digits: 8,
+            fixed_width_unicode: false,
+            control_delete: true,
+            control_double_backslash: true,
+            space_escape: false,
+            line_continuation: LineContinuation::Off,
+            bom: BomPolicy::Keep,
+            nul: NulPolicy::Allow,
+            max_output_len: None,
+            surrogates: SurrogatePolicy::Error,
+            encoding: OutputEncoding::Utf8,
+            close_ignore_case: false,
+            close_bytes: Vec::new(),
+            open_delimiter: None,
+            canonical: false,
+            reject_raw_controls: false,
+            allowed_raw_controls: Vec::new(),
+            ascii_source: false,
+            base_offset: 0,
+        };
+    }
+}
+
+/// What to do with a byte order mark at the start of the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum BomPolicy {
+    /// Treat it like any other bytes
+    #[default]
+    Keep,
+    /// Skip a UTF-8 byte order mark `EF BB BF`
+    Strip,
+    /// Skip a UTF-8 byte order mark, and return a [Utf16Bom](ParseError::Utf16Bom) error
+    /// for a UTF-16 byte order mark `FF FE` or `FE FF`
+    StripAndRejectUtf16,
+}
+
+/// How characters from unicode escapes are encoded in the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum OutputEncoding {
+    /// UTF-8
+    #[default]
+    Utf8,
+    /// [CESU-8](https://www.unicode.org/reports/tr26/), which is UTF-8 except that characters above
+    /// `U+FFFF` are written as a surrogate pair, 3 bytes for each half, like some Java and Oracle systems want
+    Cesu8,
+    /// Little-endian UTF-16, like Windows wide strings. Unlike the others, this transcodes everything,
+    /// so the output must be valid UTF-8 before encoding, or it's a [NotUtf8](ParseError::NotUtf8) error.
+    Utf16Le,
+    /// Big-endian UTF-16, like [Utf16Le](OutputEncoding::Utf16Le) otherwise
+    Utf16Be,
+    /// Any encoding that [encoding_rs] can write, like Latin-1 or Shift_JIS, and what to do with
+    /// characters it can't encode. Like in [encoding_rs], asking for UTF-16 here gets UTF-8.
+    #[cfg(feature = "encoding_rs")]
+    #[cfg_attr(feature = "arbitrary", arbitrary(skip))]
+    Legacy(&'static encoding_rs::Encoding, UnmappablePolicy),
+}
+
+/// What to do with characters that can't be written in an [OutputEncoding::Legacy] encoding
+#[cfg(feature = "encoding_rs")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum UnmappablePolicy {
+    /// Return an [Unmappable](ParseError::Unmappable) error
+    #[default]
+    Error,
+    /// Write a `?` instead
+    Question,
+    /// Write an HTML numeric character reference like `&#128512;` instead, like [encoding_rs] does
+    HtmlEntity,
+}
+
+/// What to do with surrogates `U+D800` through `U+DFFF` from unicode escapes
+///
+/// Except with [Error](SurrogatePolicy::Error), a high surrogate escape followed right away by a
+/// low surrogate escape, like `\uD83D\uDE00`, is a surrogate pair and decodes to one character
+/// like it does in JavaScript, JSON, and Java.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum SurrogatePolicy {
+    /// Return a [UnicodeEscapeBadCodepoint](InvalidBackslashKind::UnicodeEscapeBadCodepoint) error
+    #[default]
+    Error,
+    /// Encode surrogates that aren't part of a pair as [WTF-8](https://simonsapin.github.io/wtf-8/),
+    /// which is what Rust's `OsString` uses on Windows. UTF-16 output gets them as-is.
+    Wtf8,
+    /// Replace surrogates that aren't part of a pair with `U+FFFD`
+    Replace,
+}
+
+/// What to do with NUL bytes `0x0` in the unescaped output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum NulPolicy {
+    /// Write them like any other byte
+    #[default]
+    Allow,
+    /// Return a [NulByte](ParseError::NulByte) error
+    Reject,
+    /// Leave them out of the output
+    Strip,
+}
+
+/// Which line endings after a backslash are removed, joining two lines together
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum LineContinuation {
+    /// A backslash followed by a line ending is a [BackslashEscapeUnknown](InvalidBackslashKind::BackslashEscapeUnknown) error
+    #[default]
+    Off,
+    /// A backslash followed by `\n` produces nothing
+    Newline,
+    /// A backslash followed by `\n` or `\r\n` produces nothing
+    NewlineOrCrlf,
+}
+
+/// What to do with octal escapes that don't fit in a byte, like `\777`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum OctalOverflow {
+    /// Return an [OctalEscapeOverflow](InvalidBackslashKind::OctalEscapeOverflow) error
+    #[default]
+    Error,
+    /// Keep only the low 8 bits, so `\777` is `0xFF`, like bash does
+    Wrap,
+    /// Stop after 2 digits if the escape starts with 4 through 7, so `\777` is `\77` followed by `7`.
+    /// Braced escapes like `\o{777}` can't stop early, so they're still an error.
+    TwoDigits,
+}
+
+#[cfg(feature = "unicode")]
+/// Turns the hex digits in `escape[start..=end]` into a code point
+///
+/// Surrogates are only allowed if [surrogates](Dialect::surrogates) isn't [SurrogatePolicy::Error].
+fn unhex(
     offset: usize,
     escape: &[u8],
     start: usize,
     end: Option<usize>,
-) -> Result<Vec<u8>, UnescapeError>
+    dialect: &Dialect,
+) -> Result<u32, ParseError>
 {
-    let range = match end {
-        Some(i) => escape[start..=i].to_vec(),
-        None => escape[start..].to_vec(),
-    };
-    let hex: String = match String::from_utf8(range.clone()) {
-        Ok(s) => s,
-        Err(_) => { return Err(UnescapeError::invalid_backslash(offset, &escape, HexDigitsNotUnicode)); }
+    let range = start..end.map_or(escape.len(), |i| i + 1);
+    let digits = &escape[range.clone()];
+    if std::str::from_utf8(digits).is_err() {
+        return Err(bad_digits(offset, escape, range, HexDigitsNotUnicode(digits.to_vec())));
+    }
+    let ord: u32 = match parse_digits(digits, 16) {
+        Some(b) => b,
+        None => { return Err(bad_digits(offset, escape, range, HexDigitsNotHexDigits(digits.to_vec()))); }
     };
-    let ord: u32 = match u32::from_str_radix(&hex, 16) {
-        Ok(b) => b,
-        Err(_) => { return Err(UnescapeError::invalid_backslash(offset, &escape, HexDigitsNotHexDigits(range))); }
+    if char::from_u32(ord).is_none() && ! (is_surrogate(ord) && dialect.surrogates != SurrogatePolicy::Error) {
+        return Err(ParseError::invalid_backslash(offset, escape, UnicodeEscapeBadCodepoint(ord)));
+    }
+    return Ok(ord);
+}
+
+/// Parses digits in `radix` in place, or returns [None] if there aren't any, one isn't a digit,
+/// or the number doesn't fit in a [u32]
+fn parse_digits(digits: &[u8], radix: u32) -> Option<u32> {
+    if digits.is_empty() {
+        return None;
+    }
+    let mut r: u32 = 0;
+    for &byte in digits {
+        let digit = (byte as char).to_digit(radix)?;
+        r = r.checked_mul(radix)?.checked_add(digit)?;
+    }
+    return Some(r);
+}
+
+#[cfg(feature = "unicode")]
+fn is_surrogate(ord: u32) -> bool {
+    return (0xD800..=0xDFFF).contains(&ord);
+}
+
+/// Turns the hex digits in `escape[digits]` into a byte
+fn unhex_byte(
+    offset: usize,
+    escape: &[u8],
+    digits: std::ops::Range<usize>,
+) -> Result<u8, ParseError>
+{
+    let range = digits;
+    let digits = &escape[range.clone()];
+    if std::str::from_utf8(digits).is_err() {
+        return Err(bad_digits(offset, escape, range, HexDigitsNotUnicode(digits.to_vec())));
+    }
+    let ord: u32 = match parse_digits(digits, 16) {
+        Some(b) => b,
+        None => { return Err(bad_digits(offset, escape, range, HexDigitsNotHexDigits(digits.to_vec()))); }
     };
-    let out_char: char = match char::from_u32(ord) {
-        Some(c) => c,
-        None => {
-            return Err(UnescapeError::invalid_backslash(offset, &escape, UnicodeEscapeBadCodepoint));
-        }
+    return match u8::try_from(ord) {
+        Ok(b) => Ok(b),
+        Err(_) => Err(ParseError::invalid_backslash(offset, escape, HexEscapeOverflow(ord))),
     };
-    let mut s = String::with_capacity(8);
-    s.push(out_char);
-    return Ok(s.into_bytes());
 }
 
-fn un_rust_style_u<'a, I>(
-    bytes: &mut Peekable<I>,
+/// Turns the hex digits between the braces of `\x{...}` into bytes
+///
+/// One or two digits are a single byte, while more digits must come in pairs, one pair per byte.
+fn unhex_bytes(
     offset: usize,
-    escape: &mut Vec<u8>,
-) -> Result<Vec<u8>, UnescapeError>
-where
-    I: Iterator<Item = (usize, &'a u8)>,
-    I: ExactSizeIterator<Item = (usize, &'a u8)>,
+    escape: &[u8],
+    digits: std::ops::Range<usize>,
+) -> Result<Vec<u8>, ParseError>
+{
+    if digits.is_empty() { // just \x{}
+        return Err(ParseError::invalid_backslash(offset, escape, BracedHexMissingDigits));
+    } else if digits.len() <= 2 {
+        return Ok(vec![unhex_byte(offset, escape, digits)?]);
+    } else if ! digits.len().is_multiple_of(2) {
+        return Err(ParseError::invalid_backslash(offset, escape, BracedHexOddDigits));
+    }
+    let mut r: Vec<u8> = Vec::with_capacity(digits.len() / 2);
+    for start in digits.step_by(2) {
+        r.push(unhex_byte(offset, escape, start..start+2)?);
+    }
+    return Ok(r);
+}
+
+/// Turns the octal digits in `escape[digits]` into a byte, applying the [OctalOverflow] policy
+fn unoctal(
+    offset: usize,
+    escape: &[u8],
+    digits: std::ops::Range<usize>,
+    dialect: &Dialect,
+) -> Result<u8, ParseError>
 {
-    let mut found_close = false;
-    while let Some((_, &byte4)) = bytes.next() {
+    let range = digits;
+    let digits = &escape[range.clone()];
+    if std::str::from_utf8(digits).is_err() {
+        return Err(bad_digits(offset, escape, range, OctalDigitsNotUnicode(digits.to_vec())));
+    }
+    let ord: u32 = match parse_digits(digits, 8) {
+        Some(b) => b,
+        None => { return Err(bad_digits(offset, escape, range, OctalDigitsNotOctalDigits(digits.to_vec()))); }
+    };
+    return match u8::try_from(ord) {
+        Ok(b) => Ok(b),
+        Err(_) if dialect.octal_overflow == OctalOverflow::Wrap => Ok((ord & 0xFF) as u8),
+        Err(_) => Err(ParseError::invalid_backslash(offset, escape, OctalEscapeOverflow(ord))),
+    };
+}
+
+/// With [canonical](Dialect::canonical), rejects an escape for `byte` that isn't spelled the way
+/// [escape_byte] spells it, including escapes for bytes that don't need one
+fn check_canonical_byte(offset: usize, escape: &[u8], byte: u8, dialect: &Dialect) -> Result<(), ParseError> {
+    if ! dialect.canonical {
+        return Ok(());
+    }
+    return match escape_byte(byte) {
+        EscapeForm::Literal(_) => Err(ParseError::invalid_backslash(offset, escape, NotCanonical)),
+        form if form.to_string().as_bytes() != escape => Err(ParseError::invalid_backslash(offset, escape, NotCanonical)),
+        _ => Ok(()),
+    };
+}
+
+/// With [canonical](Dialect::canonical), rejects a unicode escape for `ord`, unless it's a control
+/// character that isn't ASCII, written as `\uNNNN` with uppercase digits like [escape_bytes] does
+#[cfg(feature = "unicode")]
+fn check_canonical_char(offset: usize, escape: &[u8], ord: u32, dialect: &Dialect) -> Result<(), ParseError> {
+    if ! dialect.canonical {
+        return Ok(());
+    }
+    return match char::from_u32(ord) {
+        Some(c) if c.is_control() && ! c.is_ascii() && format!("\\u{:04X}", ord).as_bytes() == escape => Ok(()),
+        _ => Err(ParseError::invalid_backslash(offset, escape, NotCanonical)),
+    };
+}
+
+/// How many bytes of an escape sequence [EscapeBuf] keeps on the stack
+const ESCAPE_INLINE_LEN: usize = 12;
+
+/// The bytes of one escape sequence, kept for error messages
+///
+/// Almost every escape fits in [ESCAPE_INLINE_LEN] bytes, so those don't allocate. Only long ones,
+/// like `\x{...}` with lots of digits, are moved to the heap.
+struct EscapeBuf {
+    inline: [u8; ESCAPE_INLINE_LEN],
+    len: usize,
+    spilled: Vec<u8>,
+}
+
+impl EscapeBuf {
+    fn new() -> Self {
+        return Self { inline: [0; ESCAPE_INLINE_LEN], len: 0, spilled: Vec::new() };
+    }
+    
+    fn push(&mut self, byte: u8) {
+        if self.len < ESCAPE_INLINE_LEN {
+            self.inline[self.len] = byte;
+        } else {
+            if self.spilled.is_empty() {
+                self.spilled.extend_from_slice(&self.inline);
+            }
+            self.spilled.push(byte);
+        }
+        self.len += 1;
+    }
+}
+
+impl std::ops::Deref for EscapeBuf {
+    type Target = [u8];
+    
+    fn deref(&self) -> &[u8] {
+        if self.len <= ESCAPE_INLINE_LEN {
+            return &self.inline[..self.len];
+        }
+        return &self.spilled;
+    }
+}
+
+/// Why [decode_slice] stopped before the end of its input
+enum Stop {
+    Error(UnescapeError),
+    /// The slice ended in the middle of the piece that starts at this index, but it isn't the end of
+    /// the input, so that piece has to be decoded again with more bytes
+    NeedMore(usize),
+}
+
+impl From<UnescapeError> for Stop {
+    fn from(e: UnescapeError) -> Self {
+        return Stop::Error(e);
+    }
+}
+
+impl From<ParseError> for Stop {
+    fn from(e: ParseError) -> Self {
+        return Stop::Error(e.into());
+    }
+}
+
+impl From<std::io::Error> for Stop {
+    fn from(e: std::io::Error) -> Self {
+        return Stop::Error(e.into());
+    }
+}
+
+/// The input of [decode_slice]: a slice and the index of the next byte to decode
+struct Cursor<'s> {
+    bs: &'s [u8],
+    i: usize,
+    /// The offset of `bs[0]` in the whole input
+    base: usize,
+    /// Whether `bs` runs to the end of the input. If it doesn't, reading past it is [Stop::NeedMore].
+    eof: bool,
+    /// Where the piece that's being decoded starts in `bs`
+    start: usize,
+}
+
+impl Cursor<'_> {
+    /// Returns the byte `ahead` bytes after the next one without moving
+    fn peek_at(&self, ahead: usize) -> Result<Option<u8>, Stop> {
+        return match self.bs.get(self.i + ahead) {
+            Some(&byte) => Ok(Some(byte)),
+            None if self.eof => Ok(None),
+            None => Err(Stop::NeedMore(self.start)),
+        };
+    }
+    
+    fn peek(&self) -> Result<Option<u8>, Stop> {
+        return self.peek_at(0);
+    }
+    
+    fn next(&mut self) -> Result<Option<u8>, Stop> {
+        let byte = self.peek()?;
+        if byte.is_some() {
+            self.i += 1;
+        }
+        return Ok(byte);
+    }
+    
+    /// The offset of the next byte in the whole input
+    fn offset(&self) -> usize {
+        return self.base + self.i;
+    }
+}
+
+/// Moves bytes from `input` to `escape` up to and including the next `}`
+fn take_braced(
+    input: &mut Cursor<'_>,
+    offset: usize,
+    escape: &mut EscapeBuf,
+    missing_close: InvalidBackslashKind,
+) -> Result<(), Stop> {
+    while let Some(byte4) = input.next()? {
         escape.push(byte4);
         if byte4 == b'}' {
-            found_close = true;
-            break;
+            return Ok(());
         }
     }
-    if ! found_close {
-        return Err(UnescapeError::invalid_backslash(offset, &escape, RustStyleUnicodeMissingCloseBrace));
-    }
+    return Err(ParseError::invalid_backslash(offset, escape, missing_close).into());
+}
+
+#[cfg(feature = "unicode")]
+fn un_rust_style_u(
+    input: &mut Cursor<'_>,
+    offset: usize,
+    escape: &mut EscapeBuf,
+    dialect: &Dialect,
+) -> Result<u32, Stop> {
+    take_braced(input, offset, escape, RustStyleUnicodeMissingCloseBrace)?;
     let end = escape.len()-2;
     let start = 3;
     if end == start-1 {
-        return Err(UnescapeError::invalid_backslash(offset, &escape, RustStyleUnicodeMissingDigits));
+        return Err(ParseError::invalid_backslash(offset, escape, RustStyleUnicodeMissingDigits).into());
     } else if end < start {
         unreachable!();
     }
-    return Ok(unhex(offset, &escape, start, Some(end))?);
+    return unhex(offset, escape, start, Some(end), dialect).map_err(Stop::from);
 }
 
+/// Rejects a byte that's in the input as-is, if the dialect says it has to be written as an escape
+fn check_raw(offset: usize, byte: u8, dialect: &Dialect) -> Result<(), ParseError> {
+    let control = dialect.reject_raw_controls && byte < 0x20 && ! dialect.allowed_raw_controls.contains(&byte);
+    if control || (dialect.ascii_source && ! byte.is_ascii()) {
+        return Err(ParseError::RawByte { offset, byte });
+    }
+    return Ok(());
+}
+
+/// Calls `handler` for an escape the dialect doesn't understand, with one more byte each time it needs more
+fn handle_escape(
+    input: &mut Cursor<'_>,
+    offset: usize,
+    escape: &mut EscapeBuf,
+    out: &mut dyn Write,
+    handler: &mut EscapeHandler<'_>,
+) -> Result<(), Stop> {
+    loop {
+        match handler(offset, escape, out)? {
+            Handled::Done => { return Ok(()); }
+            Handled::NeedMore => match input.next()? {
+                Some(byte) => escape.push(byte),
+                None => { return Err(ParseError::invalid_backslash(offset, escape, BackslashEscapeUnknown(escape[1])).into()); }
+            },
+            Handled::Unknown => { return Err(ParseError::invalid_backslash(offset, escape, BackslashEscapeUnknown(escape[1])).into()); }
+        }
+    }
+}
+
+/// Moves up to `max` more digits from `input` to `escape`, stopping at the first byte that isn't a digit
+fn take_digits(
+    input: &mut Cursor<'_>,
+    escape: &mut EscapeBuf,
+    max: usize,
+    is_digit: fn(&u8) -> bool,
+) -> Result<(), Stop> {
+    for _ in 0..max {
+        match input.peek()? {
+            Some(byte) if is_digit(&byte) => {
+                input.i += 1;
+                escape.push(byte);
+            }
+            _ => break,
+        }
+    }
+    return Ok(());
+}
+
+/// The single-letter escapes the decoder understands in every [Dialect], as `(letter, byte)` pairs
+///
+/// For example, `(b'n', b'\n')` means `\n` is a newline. Where two letters mean the same byte, like
+/// `\e` and `\E`, the one listed first is the one formatters should prefer. `\s` isn't here, because
+/// it's only an escape with [Dialect::space_escape].
+pub const NAMED_ESCAPES: &[(u8, u8)] = &[
+    (b'a', 0x07), // alert/bell
+    (b'b', 0x08), // backspace
+    (b'e', 0x1B), // escape
+    (b'E', 0x1B),
+    (b'f', 0x0C), // form feed
+    (b'n', 0x0A), // newline or line feed
+    (b'r', 0x0D), // carriage return
+    (b't', 0x09), // horizontal tab
+    (b'v', 0x0B), // vertical tab
+    (b'\'', b'\''),
+    (b'"', b'"'),
+    (b'\\', b'\\'),
+];
+
+/// Returns the byte that the escape `\letter` stands for, if it's one of the [NAMED_ESCAPES]
+pub fn named_escape(letter: u8) -> Option<u8> {
+    return NAMED_ESCAPES.iter().find(|&&(l, _)| l == letter).map(|&(_, byte)| byte);
+}
+
+/// Maps the key in a control-x sequence like `\cX` or `^X` to its control character
+fn control_key(key: u8) -> Option<u8> {
+    if (b'@'..=b'_').contains(&key) {
+        return Some(key-0x40);
+    } else if (b'`'..=b'~').contains(&key) {
+        return Some(key-0x60);
+    } else if key == b'?' {
+        return Some(0x7F);
+    } else {
+        return None;
+    }
+}
+
+fn un_meta(
+    input: &mut Cursor<'_>,
+    offset: usize,
+    escape: &mut EscapeBuf,
+) -> Result<u8, Stop> {
+    match input.next()? {
+        Some(b'-') => escape.push(b'-'),
+        Some(byte3) => {
+            escape.push(byte3);
+            return Err(ParseError::invalid_backslash(offset, escape, MetaEscapeMissingDash).into());
+        }
+        None => { return Err(ParseError::invalid_backslash(offset, escape, MetaEscapeEndOfString).into()); }
+    }
+    let byte4: u8 = match input.next()? {
+        Some(byte4) => byte4,
+        None => { return Err(ParseError::invalid_backslash(offset, escape, MetaEscapeEndOfString).into()); }
+    };
+    escape.push(byte4);
+    if byte4 != b'^' {
+        if byte4.is_ascii() {
+            return Ok(byte4 | 0x80);
+        } else {
+            return Err(ParseError::invalid_backslash(offset, escape, MetaEscapeBadKey(byte4)).into());
+        }
+    }
+    // `\M-^X` is a control-x character with the high bit set, but `cat -v` also
+    // prints 0xDE as a bare `M-^`, so only take the next byte if it's a control key
+    let control: Option<u8> = match input.peek()? {
+        Some(b'?') => Some(0x7F),
+        Some(byte5) if (b'@'..=b'_').contains(&byte5) => Some(byte5-0x40),
+        _ => None,
+    };
+    match control {
+        Some(c) => {
+            let byte5 = input.bs[input.i];
+            input.i += 1;
+            escape.push(byte5);
+            return Ok(c | 0x80);
+        }
+        None => { return Ok(b'^' | 0x80); }
+    }
+}
+
+/// Skips a byte order mark at the start of `input`, according to [bom](Dialect::bom)
+fn skip_bom(input: &mut Cursor<'_>, dialect: &Dialect) -> Result<(), Stop> {
+    if dialect.bom == BomPolicy::Keep {
+        return Ok(());
+    }
+    let utf8_bom: &[u8] = b"\xEF\xBB\xBF";
+    let mut matched: usize = 0;
+    while matched < utf8_bom.len() && input.peek_at(matched)? == Some(utf8_bom[matched]) {
+        matched += 1;
+    }
+    if matched == utf8_bom.len() {
+        input.i += matched;
+    } else if matched == 0 && dialect.bom == BomPolicy::StripAndRejectUtf16 {
+        if let Some(byte @ (0xFE | 0xFF)) = input.peek()? {
+            if input.peek_at(1)? == Some(byte ^ 0x01) {
+                return Err(ParseError::Utf16Bom { offset: input.offset(), bytes: pretty_bytes(&[byte, byte ^ 0x01]) }.into());
+            }
+        }
+    }
+    return Ok(());
+}
+
+/// Wraps the output of [unescape_iter_with_encoder] to apply [max_output_len](Dialect::max_output_len)
+///
+/// Bytes past the limit aren't written. Instead `too_long` is set, like [OutputFilter] does for its errors.
+struct LimitedWriter<'o, O: Write> {
+    out: &'o mut O,
+    limit: usize,
+    written: usize,
+    too_long: bool,
+}
+
+impl<O: Write> Write for LimitedWriter<'_, O> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.len() > self.limit - self.written {
+            self.too_long = true;
+            return Ok(buf.len());
+        }
+        self.out.write_all(buf)?;
+        self.written += buf.len();
+        return Ok(buf.len());
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        return self.out.flush();
+    }
+}
+
+/// Wraps the output of [unescape_iter_with_encoder] to apply [nul](Dialect::nul) and
+/// [surrogates](Dialect::surrogates), and to hand everything to the [ScalarEncoder]
+///
+/// Bytes that aren't allowed aren't written. Instead a flag is set, so the caller can
+/// [check](OutputFilter::check) it and return an error with the offset of the escape that produced them.
+/// Errors from the encoder are kept the same way.
+///
+/// A high surrogate is held back until the next write, in case it's the first half of a surrogate pair,
+/// so call [finish](OutputFilter::finish) at the end.
+struct OutputFilter<'o, 'e, O: Write> {
+    out: LimitedWriter<'o, O>,
+    encoder: &'e mut dyn ScalarEncoder,
+    surrogates: SurrogatePolicy,
+    high_surrogate: Option<u32>,
+    nul: NulPolicy,
+    found_nul: bool,
+    encode_error: Option<EncodeError>,
+}
+
+impl<'o, 'e, O: Write> OutputFilter<'o, 'e, O> {
+    fn new(out: &'o mut O, dialect: &Dialect, encoder: &'e mut dyn ScalarEncoder) -> Self {
+        return Self {
+            out: LimitedWriter {
+                out,
+                limit: dialect.max_output_len.unwrap_or(usize::MAX),
+                written: 0,
+                too_long: false,
+            },
+            encoder,
+            surrogates: dialect.surrogates,
+            high_surrogate: None,
+            nul: dialect.nul,
+            found_nul: false,
+            encode_error: None,
+        };
+    }
+    
+    /// Keeps the first encoding error for [check](OutputFilter::check), but passes I/O errors on right away
+    fn encoded(&mut self, result: Result<(), EncodeError>) -> std::io::Result<()> {
+        match result {
+            Ok(()) => {}
+            Err(EncodeError::IOError(e)) => { return Err(e); }
+            Err(e) => {
+                if self.encode_error.is_none() {
+                    self.encode_error = Some(e);
+                }
+            }
+        }
+        return Ok(());
+    }
+    
+    /// Writes a code point from a unicode escape, which is either a [char] or a surrogate
+    #[cfg(feature = "unicode")]
+    fn write_codepoint(&mut self, ord: u32) -> std::io::Result<()> {
+        if let Some(high) = self.high_surrogate.take() {
+            if (0xDC00..=0xDFFF).contains(&ord) {
+                let c = char::from_u32(0x10000 + ((high - 0xD800) << 10) + (ord - 0xDC00))
+                    .expect("Surrogate pairs are always valid code points.");
+                return self.write_char(c);
+            }
+            self.write_lone_surrogate(high)?;
+        }
+        if (0xD800..=0xDBFF).contains(&ord) {
+            self.high_surrogate = Some(ord);
+            return Ok(());
+        } else if is_surrogate(ord) {
+            return self.write_lone_surrogate(ord);
+        }
+        let c = char::from_u32(ord).expect("unhex only returns chars and surrogates.");
+        return self.write_char(c);
+    }
+    
+    fn write_char(&mut self, c: char) -> std::io::Result<()> {
+        if c == '\0' {
+            match self.nul {
+                NulPolicy::Allow => {}
+                NulPolicy::Reject => { self.found_nul = true; return Ok(()); }
+                NulPolicy::Strip => { return Ok(()); }
+            }
+        }
+        let result = self.encoder.write_char(c, &mut self.out);
+        return self.encoded(result);
+    }
+    
+    fn write_lone_surrogate(&mut self, ord: u32) -> std::io::Result<()> {
+        return match self.surrogates {
+            SurrogatePolicy::Wtf8 => {
+                let result = self.encoder.write_surrogate(ord as u16, &mut self.out);
+                self.encoded(result)
+            }
+            SurrogatePolicy::Replace => self.write_char('\u{FFFD}'),
+            SurrogatePolicy::Error => unreachable!("unhex doesn't return surrogates in this case."),
+        };
+    }
+    
+    /// Writes a held back high surrogate, if there is one
+    fn flush_surrogate(&mut self) -> std::io::Result<()> {
+        if let Some(high) = self.high_surrogate.take() {
+            self.write_lone_surrogate(high)?;
+        }
+        return Ok(());
+    }
+    
+    /// Writes anything that was held back, at the end of the input
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.flush_surrogate()?;
+        let result = self.encoder.finish(&mut self.out);
+        return self.encoded(result);
+    }
+    
+    fn check(&mut self, offset: usize) -> Result<(), UnescapeError> {
+        if self.found_nul {
+            return Err(ParseError::NulByte { offset }.into());
+        }
+        if self.out.too_long {
+            return Err(ParseError::OutputTooLong { offset, max_len: self.out.limit }.into());
+        }
+        return match self.encode_error.take() {
+            None => Ok(()),
+            Some(EncodeError::NotUtf8) => Err(ParseError::NotUtf8 { offset }.into()),
+            Some(EncodeError::Unmappable { character, encoding }) => Err(ParseError::Unmappable { offset, character, encoding }.into()),
+            Some(EncodeError::IOError(e)) => Err(UnescapeError::from(e)),
+        };
+    }
+}
+
+impl<O: Write> Write for OutputFilter<'_, '_, O> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.flush_surrogate()?;
+        if self.nul == NulPolicy::Allow {
+            let result = self.encoder.write_bytes(buf, &mut self.out);
+            self.encoded(result)?;
+            return Ok(buf.len());
+        }
+        for part in buf.split(|&byte| byte == 0) {
+            let result = self.encoder.write_bytes(part, &mut self.out);
+            self.encoded(result)?;
+        }
+        if self.nul == NulPolicy::Reject && buf.contains(&0) {
+            self.found_nul = true;
+        }
+        return Ok(buf.len());
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        return self.out.flush();
+    }
+}
 
 /// Writes an unescaped string from an iterator
 /// 
+/// The positions have to count up by one from the first, like [Iterator::enumerate]'s do, since errors
+/// are reported at the first position plus how far into the input they are.
+/// 
 /// # Arguments
 /// 
 /// * `bytes` - An iterator that yields a position and byte like `[u8].iter().enumerate().peekable()`
@@ -262,6 +1466,177 @@ where
     I: ExactSizeIterator<Item = (usize, &'a u8)>,
     O: Write,
 {
+    return unescape_iter_with(bytes, out, close, &Dialect::default());
+}
+
+/// Writes an unescaped string from an iterator, using a specific [Dialect]
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - An iterator that yields a position and byte like `[u8].iter().enumerate().peekable()`
+/// * `out` - An output stream, like `Vec<u8>`
+/// * `close` - An optional closing delimiter to look for
+/// * `dialect` - Which optional escape syntaxes to understand
+pub fn unescape_iter_with<'a, I, O>(
+    bytes: &mut Peekable<I>, 
+    out: &mut O, 
+    close: Option<u8>,
+    dialect: &Dialect,
+) -> Result<usize, UnescapeError>
+where
+    I: Iterator<Item = (usize, &'a u8)>,
+    I: ExactSizeIterator<Item = (usize, &'a u8)>,
+    O: Write,
+{
+    let mut encoder = dialect.encoding.encoder();
+    return unescape_iter_with_encoder(bytes, out, close, dialect, encoder.as_mut());
+}
+
+/// Writes an unescaped string from an iterator, using a specific [Dialect] and a custom [ScalarEncoder]
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - An iterator that yields a position and byte like `[u8].iter().enumerate().peekable()`
+/// * `out` - An output stream, like `Vec<u8>`
+/// * `close` - An optional closing delimiter to look for
+/// * `dialect` - Which optional escape syntaxes to understand. Its [encoding](Dialect::encoding) is ignored.
+/// * `encoder` - Writes the output in some encoding
+pub fn unescape_iter_with_encoder<'a, I, O>(
+    bytes: &mut Peekable<I>, 
+    out: &mut O, 
+    close: Option<u8>,
+    dialect: &Dialect,
+    encoder: &mut dyn ScalarEncoder,
+) -> Result<usize, UnescapeError>
+where
+    I: Iterator<Item = (usize, &'a u8)>,
+    I: ExactSizeIterator<Item = (usize, &'a u8)>,
+    O: Write,
+{
+    return unescape_iter_at_zero(bytes, out, close, dialect, encoder, &mut DecodeHooks::default()).map_err(|e| match e {
+        UnescapeError::Parse(e) => UnescapeError::Parse(e.offset_by(dialect.base_offset)),
+        e => e,
+    });
+}
+
+/// [unescape_iter_with_encoder] with `hooks`, without adding [base_offset](Dialect::base_offset) to errors
+///
+/// The bytes are decoded by [decode_slice]. It only ever asks for one more byte than it has, so this never
+/// takes anything from `bytes` after the close delimiter.
+fn unescape_iter_at_zero<'a, I, O>(
+    bytes: &mut Peekable<I>, 
+    out: &mut O, 
+    close: Option<u8>,
+    dialect: &Dialect,
+    encoder: &mut dyn ScalarEncoder,
+    hooks: &mut DecodeHooks<'_>,
+) -> Result<usize, UnescapeError>
+where
+    I: Iterator<Item = (usize, &'a u8)>,
+    I: ExactSizeIterator<Item = (usize, &'a u8)>,
+    O: Write,
+{
+    let DecodeHooks { escape, scalar } = hooks;
+    let mut hooked: HookedEncoder<'_, '_>;
+    let encoder: &mut dyn ScalarEncoder = match scalar.as_mut() {
+        Some(hook) => {
+            hooked = HookedEncoder { encoder, hook };
+            &mut hooked
+        }
+        None => encoder,
+    };
+    let out = &mut OutputFilter::new(out, dialect, encoder);
+    let mut state = DecodeState::default();
+    // the bytes of the piece decode_slice is in the middle of, and the offset of the first one
+    let mut buf: Vec<u8> = Vec::new();
+    let mut base: usize = bytes.peek().map_or(0, |&(offset, _)| offset);
+    let mut eof = false;
+    loop {
+        let mut input = Cursor { bs: &buf, i: 0, base, eof, start: 0 };
+        match decode_slice(&mut input, out, close, dialect, escape, &mut state) {
+            Ok(offset) => { return Ok(offset); }
+            Err(Stop::Error(e)) => { return Err(e); }
+            Err(Stop::NeedMore(start)) => {
+                buf.drain(..start);
+                base += start;
+                match bytes.next() {
+                    Some((_, &byte)) => buf.push(byte),
+                    None => eof = true,
+                }
+            }
+        }
+    }
+}
+
+/// [unescape_slice_with] with `hooks`, without adding [base_offset](Dialect::base_offset) to errors
+fn unescape_slice_at_zero<O: Write>(
+    bs: &[u8],
+    out: &mut O,
+    close: Option<u8>,
+    dialect: &Dialect,
+    encoder: &mut dyn ScalarEncoder,
+    hooks: &mut DecodeHooks<'_>,
+) -> Result<usize, UnescapeError> {
+    let DecodeHooks { escape, scalar } = hooks;
+    let mut hooked: HookedEncoder<'_, '_>;
+    let encoder: &mut dyn ScalarEncoder = match scalar.as_mut() {
+        Some(hook) => {
+            hooked = HookedEncoder { encoder, hook };
+            &mut hooked
+        }
+        None => encoder,
+    };
+    let out = &mut OutputFilter::new(out, dialect, encoder);
+    let mut input = Cursor { bs, i: 0, base: 0, eof: true, start: 0 };
+    return match decode_slice(&mut input, out, close, dialect, escape, &mut DecodeState::default()) {
+        Ok(offset) => Ok(offset),
+        Err(Stop::Error(e)) => Err(e),
+        Err(Stop::NeedMore(_)) => unreachable!("The slice is the whole input, so there's never more."),
+    };
+}
+
+/// Unescapes a field that ends at `delim` or at the end of `bs`, whichever is first
+///
+/// Escaped delimiters like `\,` are understood all the way to the end, even in the last field.
+fn unescape_field(bs: &[u8], out: &mut Vec<u8>, delim: u8, dialect: &Dialect) -> Result<Consumed, ParseError> {
+    let mut encoder = dialect.encoding.encoder();
+    let out = &mut OutputFilter::new(out, dialect, encoder.as_mut());
+    let mut input = Cursor { bs, i: 0, base: 0, eof: true, start: 0 };
+    let mut state = DecodeState { end_closes: true, ..DecodeState::default() };
+    return match decode_slice(&mut input, out, Some(delim), dialect, &mut None, &mut state) {
+        Ok(offset) if state.closed => Ok(Consumed { content: offset, closed: true }),
+        Ok(_) => Ok(Consumed { content: bs.len(), closed: false }),
+        Err(Stop::Error(e)) => Err(e.into_parse_error().offset_by(dialect.base_offset)),
+        Err(Stop::NeedMore(_)) => unreachable!("The slice is the whole input, so there's never more."),
+    };
+}
+
+/// What [decode_slice] has to remember when it's called again with more of the input
+#[derive(Default)]
+struct DecodeState {
+    /// how many unescaped open delimiters are still waiting for their close delimiter
+    depth: usize,
+    last_offset: Option<usize>,
+    bom_checked: bool,
+    /// Whether the end of the input ends the string too, like the close delimiter does
+    end_closes: bool,
+    /// Whether it stopped at the close delimiter
+    closed: bool,
+}
+
+/// Decodes `input` from its index, and returns the offset of the close delimiter, or of the last piece
+/// if there isn't one
+///
+/// If `input` isn't the end of the input and a piece runs past it, this returns [Stop::NeedMore] with
+/// where that piece starts, without writing any of it. Everything before that is written already.
+fn decode_slice<O: Write>(
+    input: &mut Cursor<'_>,
+    out: &mut OutputFilter<'_, '_, O>,
+    close: Option<u8>,
+    dialect: &Dialect,
+    escape_hook: &mut Option<EscapeHandler<'_>>,
+    state: &mut DecodeState,
+) -> Result<usize, Stop> {
     // This is a workaround for https://github.com/rust-lang/rust/issues/53667
     let close_delimiter: u8;
     let have_close: bool;
@@ -276,161 +1651,741 @@ where
         }
     }
     
-    let mut last_offset: Option<usize> = None;
+    let is_close = |byte: u8| -> bool {
+        return have_close && std::iter::once(&close_delimiter).chain(&dialect.close_bytes).any(|&c| {
+            c == byte || (dialect.close_ignore_case && c.eq_ignore_ascii_case(&byte))
+        });
+    };
+    
+    if ! state.bom_checked {
+        input.start = input.i;
+        skip_bom(input, dialect)?;
+        state.bom_checked = true;
+    }
     
-    while let Some((offset, &byte)) = bytes.next() {
+    // literal bytes are written from the input itself, so VectoredWriter can tell they're runs of it
+    let bs = input.bs;
+    loop {
+        input.start = input.i;
+        let offset = input.offset();
+        let byte = match input.next()? {
+            Some(byte) => byte,
+            None => break,
+        };
+        let literal = &bs[input.i-1];
         if byte == b'\\' {
-            let mut escape: Vec<u8> = Vec::with_capacity(12);
+            let mut escape = EscapeBuf::new();
             escape.push(byte);
-            if let Some((_, &byte2)) = bytes.next() {
+            if let Some(byte2) = input.next()? {
                 escape.push(byte2);
-                let _wrote = match byte2 {
-                    b'a' => out.write(&[0x07])?, // alert/bell
-                    b'b' => out.write(&[0x08])?, // backspace
-                    b'e' | b'E' => out.write(&[0x1B])?, // escape
-                    b'f' => out.write(&[0x0C])?, // form feed
-                    b'n' => out.write(&[0x0A])?, // newline or line feed
-                    b'r' => out.write(&[0x0D])?, // carriage return
-                    b't' => out.write(&[0x09])?, // horizontal tab
-                    b'v' => out.write(&[0x0B])?, // vertical tab
-                    b'\'' => out.write(&[b'\''])?, // single quote
-                    b'"' => out.write(&[b'"'])?, // double quote
-                    b'\\' => out.write(&[b'\\'])?, // literal backslash
-                    b'0'..=b'9' => {
-                        for _ in 3..=4 {
-                            if let Some((_, &byte3)) = bytes.peek() {
-                                if byte3.is_ascii_digit() {
-                                    escape.push(byte3);
-                                }
-                                let (_, _) = bytes.next().expect("Just peeked, so this should never return None.");
+                if let Some(unescaped) = named_escape(byte2) {
+                    // an escaped delimiter is the only way to write it
+                    if ! (is_close(byte2) || (have_close && dialect.open_delimiter == Some(byte2))) {
+                        check_canonical_byte(offset, &escape, unescaped, dialect)?;
+                    }
+                    out.write_all(&[unescaped])?;
+                } else {
+                    match byte2 {
+                        b's' if dialect.space_escape => {
+                            check_canonical_byte(offset, &escape, b' ', dialect)?;
+                            out.write_all(b" ")? // space
+                        }
+                        b'\n' if dialect.line_continuation != LineContinuation::Off => { // line continuation
+                            if dialect.canonical {
+                                return Err(ParseError::invalid_backslash(offset, &escape, NotCanonical).into());
                             }
                         }
-                        let octal: String = match String::from_utf8(escape[1..].to_vec()) {
-                            Ok(s) => s,
-                            Err(_) => { return Err(UnescapeError::invalid_backslash(offset, &escape, OctalDigitsNotUnicode)); }
-                        };
-                        let out_byte: u8 = match u8::from_str_radix(&octal, 8) {
-                            Ok(b) => b,
-                            Err(_) => { return Err(UnescapeError::invalid_backslash(offset, &escape, OctalDigitsNotOctalDigits)); }
-                        };
-                        out.write(&[out_byte])?
-                    }
-                    b'x' => { // this one could be bad unicode, its a byte
-                        for _ in 3..=4 {
-                            if let Some((_, &byte3)) = bytes.peek() {
-                                if byte3.is_ascii_hexdigit() {
+                        b'\r' if dialect.line_continuation == LineContinuation::NewlineOrCrlf && input.peek()? == Some(b'\n') => {
+                            input.i += 1;
+                            escape.push(b'\n');
+                            if dialect.canonical {
+                                return Err(ParseError::invalid_backslash(offset, &escape, NotCanonical).into());
+                            }
+                        }
+                        b'0'..=b'9' => {
+                            // bash stops early rather than read a third digit that can't fit in a byte
+                            let max_digits: usize = if dialect.octal_overflow == OctalOverflow::TwoDigits && byte2 > b'3' {
+                                dialect.max_octal_digits.min(2)
+                            } else {
+                                dialect.max_octal_digits
+                            };
+                            take_digits(input, &mut escape, max_digits.saturating_sub(1), |b| (b'0'..=b'7').contains(b))?;
+                            let out_byte: u8 = unoctal(offset, &escape, 1..escape.len(), dialect)?;
+                            check_canonical_byte(offset, &escape, out_byte, dialect)?;
+                            out.write_all(&[out_byte])?
+                        }
+                        b'o' => {
+                            match input.next()? {
+                                Some(b'{') => escape.push(b'{'),
+                                Some(byte3) => {
                                     escape.push(byte3);
+                                    return Err(ParseError::invalid_backslash(offset, &escape, BracedOctalMissingOpenBrace).into());
                                 }
-                                let (_, _) = bytes.next().expect("Just peeked, so this should never return None.");
+                                None => { return Err(ParseError::invalid_backslash(offset, &escape, BracedOctalMissingOpenBrace).into()); }
                             }
+                            take_braced(input, offset, &mut escape, BracedOctalMissingCloseBrace)?;
+                            if escape.len() == 4 { // just \o{}
+                                return Err(ParseError::invalid_backslash(offset, &escape, BracedOctalMissingDigits).into());
+                            }
+                            let out_byte: u8 = unoctal(offset, &escape, 3..escape.len()-1, dialect)?;
+                            check_canonical_byte(offset, &escape, out_byte, dialect)?;
+                            out.write_all(&[out_byte])?
                         }
-                        if escape.len() == 2 { // just \x
-                            return Err(UnescapeError::invalid_backslash(offset, &escape, HexDigitsNoDigits));
-                        }
-                        let hex: String = match String::from_utf8(escape[2..].to_vec()) {
-                            Ok(s) => s,
-                            Err(_) => { return Err(UnescapeError::invalid_backslash(offset, &escape, HexDigitsNotUnicode)); }
-                        };
-                        let out_byte: u8 = match u8::from_str_radix(&hex, 16) {
-                            Ok(b) => b,
-                            Err(_) => { return Err(UnescapeError::invalid_backslash(offset, &escape, HexDigitsNotHexDigits(hex.as_bytes().to_vec()))); }
-                        };
-                        out.write(&[out_byte])?
-                    }
-                    b'u' => {
-                        if let Some((_, &byte3)) = bytes.next() {
-                            escape.push(byte3);
-                            if byte3 == b'{' {
-                                let u_bytes: Vec<u8> = un_rust_style_u(bytes, offset, &mut escape)?;
-                                out.write(&u_bytes.as_slice())?
+                        b'x' => { // this one could be bad unicode, its a byte
+                            if input.peek()? == Some(b'{') {
+                                input.i += 1;
+                                escape.push(b'{');
+                                take_braced(input, offset, &mut escape, BracedHexMissingCloseBrace)?;
+                                let raw: Vec<u8> = unhex_bytes(offset, &escape, 3..escape.len()-1)?;
+                                for &raw_byte in &raw {
+                                    check_canonical_byte(offset, &escape, raw_byte, dialect)?;
+                                }
+                                out.write_all(&raw)?
                             } else {
-                                if ! byte3.is_ascii_hexdigit() {
-                                    return Err(UnescapeError::invalid_backslash(offset, &escape, UnicodeEscapeNoDigits));
+                                take_digits(input, &mut escape, dialect.max_hex_digits, u8::is_ascii_hexdigit)?;
+                                if escape.len() == 2 { // just \x
+                                    return Err(ParseError::invalid_backslash(offset, &escape, HexDigitsNoDigits).into());
                                 }
-                                for _ in 4..=6 {
-                                    if let Some((_, &byte4)) = bytes.peek() {
-                                        if byte3.is_ascii_hexdigit() {
-                                            escape.push(byte4);
-                                        }
-                                        let (_, _) = bytes.next().expect("Just peeked, so this should never return None.");
+                                let out_byte: u8 = unhex_byte(offset, &escape, 2..escape.len())?;
+                                check_canonical_byte(offset, &escape, out_byte, dialect)?;
+                                out.write_all(&[out_byte])?
+                            }
+                        }
+                        #[cfg(feature = "unicode")]
+                        b'u' => {
+                            if let Some(byte3) = input.next()? {
+                                escape.push(byte3);
+                                if byte3 == b'{' {
+                                    let ord = un_rust_style_u(input, offset, &mut escape, dialect)?;
+                                    check_canonical_char(offset, &escape, ord, dialect)?;
+                                    out.write_codepoint(ord)?
+                                } else {
+                                    if ! byte3.is_ascii_hexdigit() {
+                                        return Err(ParseError::invalid_backslash(offset, &escape, UnicodeEscapeNoDigits).into());
+                                    }
+                                    take_digits(input, &mut escape, dialect.max_u_digits.saturating_sub(1), u8::is_ascii_hexdigit)?;
+                                    if dialect.fixed_width_unicode && escape.len() - 2 != dialect.max_u_digits {
+                                        return Err(ParseError::invalid_backslash(offset, &escape, UnicodeEscapeTooFewDigits).into());
                                     }
+                                    let ord = unhex(offset, &escape, 2, None, dialect)?;
+                                    check_canonical_char(offset, &escape, ord, dialect)?;
+                                    out.write_codepoint(ord)?
                                 }
-                                let utf8 = unhex(offset, &escape, 2, None)?;
-                                out.write(&utf8.as_slice())?
+                            } else {
+                                return Err(ParseError::invalid_backslash(offset, &escape, UnicodeEscapeEndOfString).into());
                             }
-                        } else {
-                            return Err(UnescapeError::invalid_backslash(offset, &escape, UnicodeEscapeEndOfString));
                         }
-                    }
-                    b'U' => {
-                        if let Some((_, &byte3)) = bytes.next() {
-                            escape.push(byte3);
-                            if ! byte3.is_ascii_hexdigit() {
-                                return Err(UnescapeError::invalid_backslash(offset, &escape, UnicodeEscapeNoDigits));
-                            }
-                            for _ in 4..=10 {
-                                if let Some((_, &byte4)) = bytes.peek() {
-                                    if byte3.is_ascii_hexdigit() {
-                                        escape.push(byte4);
-                                    }
-                                    let (_, _) = bytes.next().expect("Just peeked, so this should never return None.");
+                        #[cfg(feature = "unicode")]
+                        b'U' => {
+                            if let Some(byte3) = input.next()? {
+                                escape.push(byte3);
+                                if ! byte3.is_ascii_hexdigit() {
+                                    return Err(ParseError::invalid_backslash(offset, &escape, UnicodeEscapeNoDigits).into());
+                                }
+                                take_digits(input, &mut escape, dialect.max_big_u_digits.saturating_sub(1), u8::is_ascii_hexdigit)?;
+                                if dialect.fixed_width_unicode && escape.len() - 2 != dialect.max_big_u_digits {
+                                    return Err(ParseError::invalid_backslash(offset, &escape, UnicodeEscapeTooFewDigits).into());
                                 }
+                                let ord = unhex(offset, &escape, 2, None, dialect)?;
+                                check_canonical_char(offset, &escape, ord, dialect)?;
+                                out.write_codepoint(ord)?
+                            } else {
+                                return Err(ParseError::invalid_backslash(offset, &escape, UnicodeEscapeEndOfString).into());
                             }
-                            let utf8 = unhex(offset, &escape, 2, None)?;
-                            out.write(&utf8.as_slice())?
-                        } else {
-                            return Err(UnescapeError::invalid_backslash(offset, &escape, UnicodeEscapeEndOfString));
                         }
-                    }
-                    b'c' => {
-                        if let Some((_, &byte3)) = bytes.next() {
-                            escape.push(byte3);
-                            if (b'@'..=b'_').contains(&byte3) {
-                                out.write(&[byte3-0x40].as_slice())?
-                            } else if (b'`'..=b'~').contains(&byte3) {
-                                out.write(&[byte3-0x60].as_slice())?
+                        b'c' => {
+                            if let Some(byte3) = input.next()? {
+                                escape.push(byte3);
+                                // bash wants control-backslash written as `\c\\`, so the second backslash is part of it
+                                if byte3 == b'\\' && dialect.control_double_backslash && input.peek()? == Some(b'\\') {
+                                    input.i += 1;
+                                    escape.push(b'\\');
+                                }
+                                match control_key(byte3) {
+                                    Some(c) if byte3 != b'?' || dialect.control_delete => {
+                                        check_canonical_byte(offset, &escape, c, dialect)?;
+                                        out.write_all([c].as_slice())?
+                                    }
+                                    _ => { return Err(ParseError::invalid_backslash(offset, &escape, ControlEscapeBadKey(byte3)).into()); }
+                                }
                             } else {
-                                return Err(UnescapeError::invalid_backslash(offset, &escape, ControlEscapeBadKey));
+                                return Err(ParseError::invalid_backslash(offset, &escape, ControlEscapeEndOfString).into());
                             }
-                        } else {
-                            return Err(UnescapeError::invalid_backslash(offset, &escape, ControlEscapeEndOfString));
                         }
-                    }
-                    _ => { return Err(UnescapeError::invalid_backslash(offset, &escape, BackslashEscapeUnknown)); }
-                };
+                        b'M' => {
+                            let meta_byte: u8 = un_meta(input, offset, &mut escape)?;
+                            check_canonical_byte(offset, &escape, meta_byte, dialect)?;
+                            out.write_all([meta_byte].as_slice())?
+                        }
+                        _ if is_close(byte2) || (have_close && dialect.open_delimiter == Some(byte2)) => out.write_all(&[byte2])?, // escaped delimiter
+                        _ => match escape_hook.as_mut() {
+                            Some(handler) => handle_escape(input, offset, &mut escape, out, handler)?,
+                            None => { return Err(ParseError::invalid_backslash(offset, &escape, BackslashEscapeUnknown(byte2)).into()); }
+                        },
+                    };
+                }
             } else {
-                UnescapeError::invalid_backslash(offset, &escape, BackslashEndOfString);
+                return Err(ParseError::invalid_backslash(offset, &escape, BackslashEndOfString).into());
+            }
+        } else if dialect.caret_notation && byte == b'^' {
+            let control: Option<u8> = input.peek()?.and_then(control_key);
+            match control {
+                Some(c) => {
+                    let byte2 = bs[input.i];
+                    input.i += 1;
+                    check_canonical_byte(offset, &[byte, byte2], c, dialect)?;
+                    out.write_all(&[c])?;
+                }
+                None => out.write_all(std::slice::from_ref(literal))?,
             }
-        } else if have_close && byte == close_delimiter {
+        } else if is_close(byte) && state.depth == 0 {
+            out.finish()?;
+            out.check(offset)?;
+            state.closed = true;
             return Ok(offset);
         } else {
-            out.write(&[byte])?;
+            check_raw(offset, byte, dialect)?;
+            if is_close(byte) {
+                state.depth -= 1;
+            } else if have_close && dialect.open_delimiter == Some(byte) {
+                state.depth += 1;
+            }
+            out.write_all(std::slice::from_ref(literal))?;
         }
-        last_offset = Some(offset);
+        out.check(offset)?;
+        state.last_offset = Some(offset);
     }
     
     // At this point we have run out of bytes!
     
-    if have_close {
-        Err(UnescapeError::missing_close(close_delimiter))
+    if have_close && ! state.end_closes {
+        Err(ParseError::missing_close(close_delimiter).into())
     } else {
-        return Ok(last_offset.expect("If last_offset isn't set by now, it's a bug."));
+        // an empty input has no last offset
+        let last_offset = state.last_offset.unwrap_or(0);
+        out.finish()?;
+        out.check(last_offset)?;
+        return Ok(last_offset);
     }
 }
 
+/// Returns a quick upper bound on how long the unescaped form of `bytes` can be in `dialect`
+/// 
+/// This looks for the short escapes like `\xNN`, `\NNN`, and `\n`, so inputs that are mostly escapes
+/// don't need as much room as their escaped length. [unescape_bytes_with] reserves this much, and it's
+/// handy for sizing the buffer for [unescape_bytes_into].
+pub fn unescaped_len_bound(bytes: &[u8], dialect: &Dialect) -> usize {
+    let mut r: usize = 0;
+    let mut i: usize = 0;
+    while i < bytes.len() {
+        let digits = |start: usize, max: usize, is_digit: fn(&u8) -> bool| -> usize {
+            return bytes[start.min(bytes.len())..].iter().take(max).take_while(|b| is_digit(b)).count();
+        };
+        let octal_digits = match bytes.get(i+1) {
+            Some(b'4'..=b'7') if dialect.octal_overflow == OctalOverflow::TwoDigits => dialect.max_octal_digits.min(2),
+            _ => dialect.max_octal_digits,
+        };
+        // how many bytes the next piece is, and how long it can be unescaped
+        let (len, unescaped_len): (usize, usize) = match (bytes[i], bytes.get(i+1)) {
+            (b'\\', Some(b'x')) if bytes.get(i+2) != Some(&b'{') && digits(i+2, dialect.max_hex_digits, u8::is_ascii_hexdigit) > 0 => {
+                (2 + digits(i+2, dialect.max_hex_digits, u8::is_ascii_hexdigit), 1)
+            }
+            (b'\\', Some(b'0'..=b'7')) => (2 + digits(i+2, octal_digits.saturating_sub(1), |b| (b'0'..=b'7').contains(b)), 1),
+            (b'\\', Some(&letter)) if letter == b's' || named_escape(letter).is_some() => (2, 1),
+            // these can swallow a backslash after them, like `\c\`, so skip their longest form to stay in step
+            (b'\\', Some(b'c' | b'M')) => {
+                let len = (bytes.len() - i).min(5);
+                (len, len)
+            }
+            (b'^', Some(b'\\')) if dialect.caret_notation => (2, 1),
+            // anything else is never longer unescaped than escaped, so count it byte by byte
+            _ => (1, 1),
+        };
+        i += len;
+        r += unescaped_len;
+    }
+    // only UTF-16 and HTML entities for unmappable characters make the output longer than the input
+    r = match dialect.encoding {
+        OutputEncoding::Utf16Le | OutputEncoding::Utf16Be => r.saturating_mul(2),
+        #[cfg(feature = "encoding_rs")]
+        OutputEncoding::Legacy(_, UnmappablePolicy::HtmlEntity) => r.saturating_mul(4),
+        _ => r,
+    };
+    return match dialect.max_output_len {
+        Some(max_len) => r.min(max_len),
+        None => r,
+    };
+}
+
+/// Writes an unescaped string from a byte slice, and returns how many bytes of it were used
+/// 
+/// With a `close` delimiter, that's up to and including the delimiter, so the rest of the slice starts
+/// right after it. Otherwise it's the whole slice. Offsets in errors are from the start of the slice.
+/// 
+/// # Arguments
+/// 
+/// * `bs` - A slice of bytes
+/// * `out` - An output stream, like `Vec<u8>`
+/// * `close` - An optional closing delimiter to look for
+pub fn unescape_slice<O: Write>(
+    bs: &[u8],
+    out: &mut O,
+    close: Option<u8>,
+) -> Result<usize, UnescapeError> {
+    return unescape_slice_with(bs, out, close, &Dialect::default());
+}
+
+/// Writes an unescaped string from a byte slice using a specific [Dialect], and returns how many bytes
+/// of it were used, like [unescape_slice]
+/// 
+/// # Arguments
+/// 
+/// * `bs` - A slice of bytes
+/// * `out` - An output stream, like `Vec<u8>`
+/// * `close` - An optional closing delimiter to look for
+/// * `dialect` - Which optional escape syntaxes to understand
+pub fn unescape_slice_with<O: Write>(
+    bs: &[u8],
+    out: &mut O,
+    close: Option<u8>,
+    dialect: &Dialect,
+) -> Result<usize, UnescapeError> {
+    let mut encoder = dialect.encoding.encoder();
+    let offset = unescape_slice_at_zero(bs, out, close, dialect, encoder.as_mut(), &mut DecodeHooks::default()).map_err(|e| match e {
+        UnescapeError::Parse(e) => UnescapeError::Parse(e.offset_by(dialect.base_offset)),
+        e => e,
+    })?;
+    return Ok(match close {
+        Some(_) => offset + 1,
+        None => bs.len(),
+    });
+}
+
+/// How many bytes of input [unescape_iter_consumed] used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Consumed {
+    /// How many bytes came before the close delimiter, or before the end of the input if there wasn't one
+    pub content: usize,
+    
+    /// Whether the close delimiter was found. It's always one byte, and it's consumed too.
+    pub closed: bool,
+}
+
+impl Consumed {
+    /// How many bytes were used in all, including the close delimiter
+    pub fn total(&self) -> usize {
+        return self.content + usize::from(self.closed);
+    }
+}
+
+/// Writes an unescaped string from an iterator, using a specific [Dialect], and returns exactly how many
+/// bytes it took from the iterator
+///
+/// Unlike [unescape_iter_with], which returns the offset of the close delimiter or of the last byte, this
+/// counts the bytes taken from `bytes`, so it doesn't depend on where the offsets start, and an empty
+/// input is different from a one-byte one. Afterwards, `bytes` starts right after the close delimiter.
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - An iterator that yields a position and byte like `[u8].iter().enumerate().peekable()`
+/// * `out` - An output stream, like `Vec<u8>`
+/// * `close` - An optional closing delimiter to look for
+/// * `dialect` - Which optional escape syntaxes to understand
+pub fn unescape_iter_consumed<'a, I, O>(
+    bytes: &mut Peekable<I>, 
+    out: &mut O, 
+    close: Option<u8>,
+    dialect: &Dialect,
+) -> Result<Consumed, UnescapeError>
+where
+    I: Iterator<Item = (usize, &'a u8)>,
+    I: ExactSizeIterator<Item = (usize, &'a u8)>,
+    O: Write,
+{
+    let before = bytes.len();
+    unescape_iter_with(bytes, out, close, dialect)?;
+    let used = before - bytes.len();
+    return Ok(match close {
+        Some(_) => Consumed { content: used - 1, closed: true },
+        None => Consumed { content: used, closed: false },
+    });
+}
+
 /// Returns a new unescaped byte string from a byte slice
 /// 
+/// Writing to a [Vec] can't fail, so this only returns a [ParseError].
+/// 
 /// # Arguments
 /// 
 /// * `bytes` - A slice of bytes
 pub fn unescape_bytes(
     bytes: &[u8],
+) -> Result<Vec<u8>, ParseError> {
+    let mut r: Vec<u8> = Vec::with_capacity(unescaped_len_bound(bytes, &Dialect::default()));
+    unescape_bytes_into(bytes, &mut r)?;
+    return Ok(r);
+}
+
+/// Returns a new unescaped byte string from a byte slice, using a specific [Dialect]
+/// 
+/// Writing to a [Vec] can't fail, so this only returns a [ParseError].
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - A slice of bytes
+/// * `dialect` - Which optional escape syntaxes to understand
+pub fn unescape_bytes_with(
+    bytes: &[u8],
+    dialect: &Dialect,
+) -> Result<Vec<u8>, ParseError> {
+    let mut r: Vec<u8> = Vec::with_capacity(unescaped_len_bound(bytes, dialect));
+    unescape_bytes_into_with(bytes, &mut r, dialect)?;
+    return Ok(r);
+}
+
+/// Unescapes a byte slice into `out`, replacing what was in it but keeping its allocation
+/// 
+/// This saves allocating a new [Vec] for each string when unescaping lots of them.
+/// After an error, `out` has whatever was unescaped before it.
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - A slice of bytes
+/// * `out` - Where to put the unescaped bytes
+pub fn unescape_bytes_into(
+    bytes: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<(), ParseError> {
+    return unescape_bytes_into_with(bytes, out, &Dialect::default());
+}
+
+/// Unescapes a byte slice into `out` using a specific [Dialect], like [unescape_bytes_into]
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - A slice of bytes
+/// * `out` - Where to put the unescaped bytes
+/// * `dialect` - Which optional escape syntaxes to understand
+pub fn unescape_bytes_into_with(
+    bytes: &[u8],
+    out: &mut Vec<u8>,
+    dialect: &Dialect,
+) -> Result<(), ParseError> {
+    out.clear();
+    unescape_slice_with(bytes, out, None, dialect).map_err(UnescapeError::into_parse_error)?;
+    return Ok(());
+}
+
+/// A [Write] into a [Vec] that grows it with [try_reserve](Vec::try_reserve), so running out of memory
+/// is an [OutOfMemory](std::io::ErrorKind::OutOfMemory) error instead of an abort
+struct TryReserveWriter<'v> {
+    out: &'v mut Vec<u8>,
+}
+
+impl Write for TryReserveWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.out.try_reserve(buf.len()).map_err(|e| std::io::Error::new(std::io::ErrorKind::OutOfMemory, e))?;
+        self.out.extend_from_slice(buf);
+        return Ok(buf.len());
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        return Ok(());
+    }
+}
+
+/// Returns a new unescaped byte string from a byte slice like [unescape_bytes], but returns an error
+/// instead of aborting when there isn't enough memory for it
+/// 
+/// Running out of memory is an [IOError](UnescapeError::IOError) with the kind
+/// [OutOfMemory](std::io::ErrorKind::OutOfMemory). For untrusted input, set
+/// [max_output_len](Dialect::max_output_len) with [try_unescape_bytes_with] too, so huge output is an
+/// error before it uses up the memory.
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - A slice of bytes
+pub fn try_unescape_bytes(
+    bytes: &[u8],
+) -> Result<Vec<u8>, UnescapeError> {
+    return try_unescape_bytes_with(bytes, &Dialect::default());
+}
+
+/// Returns a new unescaped byte string from a byte slice using a specific [Dialect], with an error
+/// instead of an abort when there isn't enough memory, like [try_unescape_bytes]
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - A slice of bytes
+/// * `dialect` - Which optional escape syntaxes to understand
+pub fn try_unescape_bytes_with(
+    bytes: &[u8],
+    dialect: &Dialect,
 ) -> Result<Vec<u8>, UnescapeError> {
-    let mut r: Vec<u8> = Vec::with_capacity(bytes.len());
-    unescape_iter(&mut bytes.iter().enumerate().peekable(), &mut r, None)?;
+    let mut r: Vec<u8> = Vec::new();
+    r.try_reserve(unescaped_len_bound(bytes, dialect)).map_err(|e| std::io::Error::new(std::io::ErrorKind::OutOfMemory, e))?;
+    unescape_slice_with(bytes, &mut TryReserveWriter { out: &mut r }, None, dialect)?;
     return Ok(r);
 }
 
+/// Splits a byte slice on each `delim` that isn't backslash-escaped, and unescapes each field
+/// 
+/// An escaped delimiter, like `\,` for `,`, is kept in its field as just the delimiter. Like
+/// [str::split], a delimiter at the end makes an empty last field, and an empty slice is one empty field.
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - A slice of bytes
+/// * `delim` - The byte between fields
+pub fn split_on_unescaped(
+    bytes: &[u8],
+    delim: u8,
+) -> Result<Vec<Vec<u8>>, ParseError> {
+    return split_on_unescaped_with(bytes, delim, &Dialect::default());
+}
+
+/// Splits a byte slice on each `delim` that isn't backslash-escaped, and unescapes each field using a
+/// specific [Dialect], like [split_on_unescaped]
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - A slice of bytes
+/// * `delim` - The byte between fields
+/// * `dialect` - Which optional escape syntaxes to understand
+pub fn split_on_unescaped_with(
+    bytes: &[u8],
+    delim: u8,
+    dialect: &Dialect,
+) -> Result<Vec<Vec<u8>>, ParseError> {
+    let mut fields: Vec<Vec<u8>> = Vec::new();
+    let mut start: usize = 0;
+    loop {
+        let dialect = Dialect {
+            base_offset: dialect.base_offset + start,
+            // only the very start can have a byte order mark
+            bom: if start == 0 { dialect.bom } else { BomPolicy::Keep },
+            ..dialect.clone()
+        };
+        let mut field: Vec<u8> = Vec::new();
+        let consumed = unescape_field(&bytes[start..], &mut field, delim, &dialect)?;
+        fields.push(field);
+        if ! consumed.closed {
+            return Ok(fields);
+        }
+        start += consumed.total();
+    }
+}
+
+/// Finds the first `byte` in `bytes` that isn't part of an escape sequence, without unescaping anything
+/// 
+/// This is for finding where a record ends before deciding whether to unescape it, so it doesn't check
+/// that the escapes are valid. Everything after an escape that's cut off by the end of `bytes`, like
+/// `\x{41`, counts as part of it.
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - A slice of bytes
+/// * `byte` - The byte to look for
+pub fn find_unescaped(
+    bytes: &[u8],
+    byte: u8,
+) -> Option<usize> {
+    return find_unescaped_with(bytes, byte, &Dialect::default());
+}
+
+/// Finds the first `byte` in `bytes` that isn't part of an escape sequence in a specific [Dialect], like
+/// [find_unescaped]
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - A slice of bytes
+/// * `byte` - The byte to look for
+/// * `dialect` - Which optional escape syntaxes to understand
+pub fn find_unescaped_with(
+    bytes: &[u8],
+    byte: u8,
+    dialect: &Dialect,
+) -> Option<usize> {
+    let mut i: usize = 0;
+    while let Some((len, _)) = stream::token_len(bytes, i, dialect) {
+        if len == 1 && bytes[i] == byte {
+            return Some(i);
+        }
+        i += len;
+    }
+    return None;
+}
+
+/// Cuts an escaped byte slice short, so that it unescapes to at most `max_decoded_len` bytes
+/// 
+/// The cut is always between escapes, so what's left unescapes to the start of what the whole slice
+/// would. Unicode escapes next to each other are kept together, so surrogate pairs aren't split. If the
+/// whole slice doesn't unescape, this returns the error instead.
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - A slice of bytes
+/// * `max_decoded_len` - The most bytes the kept part can unescape to
+pub fn truncate_escaped(
+    bytes: &[u8],
+    max_decoded_len: usize,
+) -> Result<&[u8], ParseError> {
+    return truncate_escaped_with(bytes, max_decoded_len, &Dialect::default());
+}
+
+/// Cuts an escaped byte slice short, so that it unescapes to at most `max_decoded_len` bytes in a
+/// specific [Dialect], like [truncate_escaped]
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - A slice of bytes
+/// * `max_decoded_len` - The most bytes the kept part can unescape to
+/// * `dialect` - Which optional escape syntaxes to understand
+pub fn truncate_escaped_with<'a>(
+    bytes: &'a [u8],
+    max_decoded_len: usize,
+    dialect: &Dialect,
+) -> Result<&'a [u8], ParseError> {
+    if unescape_bytes_with(bytes, dialect)?.len() <= max_decoded_len {
+        return Ok(bytes);
+    }
+    let mut out: Vec<u8> = Vec::new();
+    let mut decoded: usize = 0;
+    let mut i: usize = 0;
+    while let Some((mut len, mut safe)) = stream::token_len(bytes, i, dialect) {
+        loop {
+            match stream::token_len(bytes, i + len, dialect) {
+                // a high surrogate pairs with the escape after it
+                Some((len2, false)) if ! safe => {
+                    len += len2;
+                }
+                // the rest of a UTF-8 character
+                Some((1, _)) if (0x80..=0xBF).contains(&bytes[i + len]) => {
+                    len += 1;
+                    safe = true;
+                }
+                _ => break,
+            }
+        }
+        let piece = Dialect {
+            base_offset: dialect.base_offset + i,
+            bom: if i == 0 { dialect.bom } else { BomPolicy::Keep },
+            max_output_len: None,
+            ..dialect.clone()
+        };
+        unescape_bytes_into_with(&bytes[i..i + len], &mut out, &piece)?;
+        if decoded + out.len() > max_decoded_len {
+            break;
+        }
+        decoded += out.len();
+        i += len;
+    }
+    return Ok(&bytes[..i]);
+}
+
+/// One field from [SplitUnescaped], still escaped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EscapedField<'a> {
+    /// The field, as it was in the input
+    pub escaped: &'a [u8],
+    /// Where the field starts in the input
+    pub offset: usize,
+    /// The delimiter between fields, which can be escaped inside them
+    pub delim: u8,
+}
+
+impl EscapedField<'_> {
+    /// Unescapes the field, where an escaped delimiter like `\,` becomes just the delimiter
+    pub fn unescape(&self) -> Result<Vec<u8>, ParseError> {
+        return self.unescape_with(&Dialect::default());
+    }
+    
+    /// Unescapes the field using a specific [Dialect], with error offsets counted from the start of the whole input
+    pub fn unescape_with(&self, dialect: &Dialect) -> Result<Vec<u8>, ParseError> {
+        let dialect = Dialect {
+            base_offset: dialect.base_offset + self.offset,
+            // only the very start can have a byte order mark
+            bom: if self.offset == 0 { dialect.bom } else { BomPolicy::Keep },
+            ..dialect.clone()
+        };
+        let mut out: Vec<u8> = Vec::with_capacity(unescaped_len_bound(self.escaped, &dialect));
+        unescape_field(self.escaped, &mut out, self.delim, &dialect)?;
+        return Ok(out);
+    }
+}
+
+/// An iterator over the fields of a byte slice, split on each delimiter that isn't backslash-escaped
+///
+/// This finds the same fields as [split_on_unescaped], but without unescaping them or collecting them
+/// all up front. Each [EscapedField] can be unescaped when it's needed. Make one with [split_unescaped]
+/// or [split_unescaped_with].
+#[derive(Debug, Clone)]
+pub struct SplitUnescaped<'a> {
+    bytes: &'a [u8],
+    delim: u8,
+    dialect: Dialect,
+    start: Option<usize>,
+}
+
+impl<'a> Iterator for SplitUnescaped<'a> {
+    type Item = EscapedField<'a>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.start?;
+        let rest = &self.bytes[start..];
+        return match find_unescaped_with(rest, self.delim, &self.dialect) {
+            Some(len) => {
+                self.start = Some(start + len + 1);
+                Some(EscapedField { escaped: &rest[..len], offset: start, delim: self.delim })
+            }
+            None => {
+                self.start = None;
+                Some(EscapedField { escaped: rest, offset: start, delim: self.delim })
+            }
+        };
+    }
+}
+
+impl std::iter::FusedIterator for SplitUnescaped<'_> {
+}
+
+/// Returns an iterator over the still-escaped fields of a byte slice, split on each `delim` that isn't
+/// backslash-escaped
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - A slice of bytes
+/// * `delim` - The byte between fields
+pub fn split_unescaped(
+    bytes: &[u8],
+    delim: u8,
+) -> SplitUnescaped<'_> {
+    return split_unescaped_with(bytes, delim, &Dialect::default());
+}
+
+/// Returns an iterator over the still-escaped fields of a byte slice, split on each `delim` that isn't
+/// backslash-escaped in a specific [Dialect]
+/// 
+/// # Arguments
+/// 
+/// * `bytes` - A slice of bytes
+/// * `delim` - The byte between fields
+/// * `dialect` - Which optional escape syntaxes to understand
+pub fn split_unescaped_with<'a>(
+    bytes: &'a [u8],
+    delim: u8,
+    dialect: &Dialect,
+) -> SplitUnescaped<'a> {
+    return SplitUnescaped {
+        bytes,
+        delim,
+        dialect: dialect.clone(),
+        start: Some(0),
+    };
+}
+
 #[cfg(test)]
+// the original tests borrow their inputs more than they need to
+#[allow(clippy::needless_borrow, clippy::single_component_path_imports)]
 mod tests;
 
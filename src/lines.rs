@@ -0,0 +1,154 @@
+//! Unescaping text a line at a time, and turning byte offsets, like the ones in errors, into lines and columns
+
+use std::io::BufRead;
+use crate::{unescape_bytes_with, BomPolicy, Dialect, LineContinuation, UnescapeError};
+
+/// A line and column in some text, both counting from 0
+///
+/// The column counts bytes from the start of the line, like the offsets in errors do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Position {
+    /// The line, counting from 0
+    pub line: usize,
+    /// The byte in the line, counting from 0
+    pub column: usize,
+}
+
+/// Where each line starts in some text, for turning byte offsets into [Position]s and back
+///
+/// Build it once, and each lookup is a binary search. Lines end after each `\n`, so a `\r` before it
+/// is the last byte of its line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl LineIndex {
+    /// Finds the lines in `bs`
+    pub fn new(bs: &[u8]) -> Self {
+        let mut starts: Vec<usize> = vec![0];
+        starts.extend(bs.iter().enumerate().filter(|&(_, &b)| b == b'\n').map(|(i, _)| i + 1));
+        return Self { starts, len: bs.len() };
+    }
+
+    /// How many lines there are. Text that ends with `\n` has an empty line after it.
+    pub fn lines(&self) -> usize {
+        return self.starts.len();
+    }
+
+    /// Returns the line and column of a byte offset, or [None] if it's past the end of the text.
+    /// The offset just past the end is allowed, since that's where errors about the end point.
+    pub fn position(&self, offset: usize) -> Option<Position> {
+        if offset > self.len {
+            return None;
+        }
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        return Some(Position { line, column: offset - self.starts[line] });
+    }
+
+    /// Returns the byte offset of a line and column, or [None] if the line doesn't exist or isn't that long
+    pub fn offset(&self, position: Position) -> Option<usize> {
+        let start = *self.starts.get(position.line)?;
+        let end = self.starts.get(position.line + 1).map_or(self.len, |&next| next - 1);
+        if start + position.column > end {
+            return None;
+        }
+        return Some(start + position.column);
+    }
+}
+
+/// An iterator of unescaped lines, from [lines_unescaped]
+#[derive(Debug)]
+pub struct LinesUnescaped<R> {
+    reader: R,
+    dialect: Dialect,
+    offset: usize,
+    done: bool,
+}
+
+impl<R: BufRead> LinesUnescaped<R> {
+    /// Reads the next line, including its line ending, and any lines it continues onto.
+    /// Returns whether the last line continues onto one that isn't there.
+    fn read_record(&mut self, record: &mut Vec<u8>) -> std::io::Result<bool> {
+        let mut continued = false;
+        loop {
+            let start = record.len();
+            if self.reader.read_until(b'\n', record)? == 0 {
+                return Ok(continued);
+            }
+            continued = continues(&record[start..], self.dialect.line_continuation);
+            if ! continued {
+                return Ok(false);
+            }
+        }
+    }
+}
+
+/// Whether `line` ends with a backslash and a line ending that `continuation` joins onto the next line
+fn continues(line: &[u8], continuation: LineContinuation) -> bool {
+    let body = match (line.strip_suffix(b"\n"), continuation) {
+        (_, LineContinuation::Off) | (None, _) => { return false; }
+        (Some(body), LineContinuation::NewlineOrCrlf) => body.strip_suffix(b"\r").unwrap_or(body),
+        (Some(body), LineContinuation::Newline) => body,
+    };
+    // `\\` is an escaped backslash, so only an odd number of them escapes the line ending
+    let backslashes = body.iter().rev().take_while(|&&b| b == b'\\').count();
+    return backslashes % 2 == 1;
+}
+
+impl<R: BufRead> Iterator for LinesUnescaped<R> {
+    type Item = Result<Vec<u8>, UnescapeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut record: Vec<u8> = Vec::new();
+        let dangling = match self.read_record(&mut record) {
+            Ok(dangling) => dangling,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e.into()));
+            }
+        };
+        if record.is_empty() {
+            self.done = true;
+            return None;
+        }
+        let start = self.offset;
+        self.offset += record.len();
+        let content = record.strip_suffix(b"\n").unwrap_or(&record);
+        let content = content.strip_suffix(b"\r").unwrap_or(content);
+        // a continuation on the last line joins it to the end of the input
+        let content = if dangling { &content[..content.len() - 1] } else { content };
+        let dialect = Dialect {
+            base_offset: self.dialect.base_offset + start,
+            bom: if start == 0 { self.dialect.bom } else { BomPolicy::Keep },
+            ..self.dialect.clone()
+        };
+        let r = unescape_bytes_with(content, &dialect).map_err(UnescapeError::from);
+        self.done = r.is_err();
+        return Some(r);
+    }
+}
+
+impl<R: BufRead> std::iter::FusedIterator for LinesUnescaped<R> {
+}
+
+/// Unescapes each line of `reader` on its own, for files with one escaped record per line
+///
+/// Lines end at `\n`, which isn't part of the output, and neither is a `\r` before it. Offsets in errors
+/// count from the start of the reader. After an error, there are no more lines.
+pub fn lines_unescaped<R: BufRead>(reader: R) -> LinesUnescaped<R> {
+    return lines_unescaped_with(reader, &Dialect::default());
+}
+
+/// Unescapes each line of `reader` in `dialect`, like [lines_unescaped]
+///
+/// With [line_continuation](Dialect::line_continuation), a line that ends with a backslash is joined
+/// with the next one, and the backslash and line ending are left out, so one item can span several lines.
+/// A continuation on the last line just ends the input.
+pub fn lines_unescaped_with<R: BufRead>(reader: R, dialect: &Dialect) -> LinesUnescaped<R> {
+    return LinesUnescaped { reader, dialect: dialect.clone(), offset: 0, done: false };
+}
@@ -0,0 +1,386 @@
+#![deny(rust_2018_idioms)]
+#![allow(clippy::needless_return)]
+
+//! smashquote - Unescapes a file or stdin to stdout or a file
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::process::ExitCode;
+
+use smashquote::{
+    escape_bytes_with, pretty_string, unescape_bytes_with, unescape_slice_vectored_with, Dialect, EscapeOptions, EscapeStyle,
+    LineContinuation, OctalOverflow, StreamDecoder, SurrogatePolicy, UnescapeError,
+};
+
+/// How many bytes are read at a time, unless there's a `--buffer-size`
+const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// How many bytes of the input on each side of an error are shown as its context
+const CONTEXT_LEN: usize = 16;
+
+const USAGE: &str = "\
+Usage: smashquote [OPTIONS] [FILE]
+
+Unescapes FILE, or stdin if there's no FILE, and writes the bytes to stdout.
+The input is read and unescaped a piece at a time, so it can be any length.
+
+Options:
+      --dialect DIALECT    Which escapes to understand: default, bash, c, json, rust, or
+                           posix-printf [default: default]
+  -o, --output FILE        Write to FILE instead of stdout
+      --mmap               Map FILE into memory instead of reading it, for big files
+      --buffer-size BYTES  Read this many bytes at a time [default: 65536]
+      --error-format FORMAT
+                           Report errors as text, or as one line of JSON each, with
+                           kind, offset, span, and context [default: text]
+      --check-roundtrip    Instead of writing the output, check that escaping it again
+                           and unescaping that gives back the same bytes
+      --style STYLE        How --check-roundtrip escapes bytes: named, hex, octal, or
+                           shortest [default: named]
+  -h, --help               Print this help
+";
+
+/// How errors are written to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorFormat {
+    /// A sentence
+    Text,
+    /// A line of JSON, for scripts and editors
+    Json,
+}
+
+/// What to do, from the command line
+#[derive(Debug)]
+struct Args {
+    input: Option<String>,
+    output: Option<String>,
+    mmap: bool,
+    buffer_size: usize,
+    error_format: ErrorFormat,
+    check_roundtrip: bool,
+    style: EscapeStyle,
+    dialect: Dialect,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        return Self {
+            input: None,
+            output: None,
+            mmap: false,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            error_format: ErrorFormat::Text,
+            check_roundtrip: false,
+            style: EscapeStyle::Named,
+            dialect: Dialect::default(),
+        };
+    }
+}
+
+/// Why smashquote stopped
+enum Failure {
+    /// The command line didn't make sense
+    Usage(String),
+    /// A file couldn't be read or written
+    Io(String, std::io::Error),
+    /// The input couldn't be unescaped, and the input around where, if that's still around. Only the
+    /// JSON format shows it.
+    #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+    Unescape(Box<UnescapeError>, Option<String>),
+    /// Escaping the output and unescaping it again didn't give the same bytes, starting at this offset
+    /// in the output, if it got that far
+    RoundTrip(Option<usize>, String),
+}
+
+impl From<UnescapeError> for Failure {
+    fn from(e: UnescapeError) -> Self {
+        return Failure::Unescape(Box::new(e), None);
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Usage(message) => write!(f, "{}", message),
+            Self::Io(path, e) => write!(f, "{}: {}", path, e),
+            Self::Unescape(e, _) => write!(f, "{}", e),
+            Self::RoundTrip(Some(offset), detail) => write!(f, "The round trip is different at byte {} of the output: {}", offset, detail),
+            Self::RoundTrip(None, detail) => write!(f, "The round trip failed: {}", detail),
+        }
+    }
+}
+
+/// Shows the bytes of `window`, which starts at `start` in the input, around `span`
+fn context(window: &[u8], start: usize, span: Range<usize>) -> Option<String> {
+    let from = span.start.saturating_sub(CONTEXT_LEN).max(start) - start;
+    let to = (span.end + CONTEXT_LEN).saturating_sub(start).min(window.len());
+    if span.start < start || from >= to {
+        return None;
+    }
+    return Some(pretty_string(&window[from..to]));
+}
+
+/// Adds the context from `window`, which starts at `start` in the input, to `e`
+fn with_context(e: UnescapeError, window: &[u8], start: usize) -> Failure {
+    let context = match &e {
+        UnescapeError::Parse(e) => e.span().and_then(|span| context(window, start, span)),
+        _ => None,
+    };
+    return Failure::Unescape(Box::new(e), context);
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, Failure> {
+    let mut r = Args::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => { return Ok(None); }
+            "-o" | "--output" => match args.next() {
+                Some(path) => r.output = Some(path),
+                None => { return Err(Failure::Usage(format!("{} needs a file", arg))); }
+            },
+            "--mmap" => r.mmap = true,
+            "--error-format" => {
+                let format = args.next().unwrap_or_default();
+                r.error_format = parse_error_format(&format)?;
+            }
+            _ if arg.starts_with("--error-format=") => r.error_format = parse_error_format(&arg["--error-format=".len()..])?,
+            "--dialect" => match args.next().as_deref().and_then(dialect_preset) {
+                Some(dialect) => r.dialect = dialect,
+                None => { return Err(Failure::Usage(format!("{} needs default, bash, c, json, rust, or posix-printf", arg))); }
+            },
+            _ if arg.starts_with("--dialect=") => match dialect_preset(&arg["--dialect=".len()..]) {
+                Some(dialect) => r.dialect = dialect,
+                None => { return Err(Failure::Usage(format!("unknown dialect {}", &arg["--dialect=".len()..]))); }
+            },
+            "--check-roundtrip" => r.check_roundtrip = true,
+            "--style" => r.style = match args.next().as_deref() {
+                Some("named") => EscapeStyle::Named,
+                Some("hex") => EscapeStyle::Hex,
+                Some("octal") => EscapeStyle::Octal,
+                Some("shortest") => EscapeStyle::Shortest,
+                _ => { return Err(Failure::Usage(format!("{} needs named, hex, octal, or shortest", arg))); }
+            },
+            "--buffer-size" => match args.next().and_then(|size| size.parse::<usize>().ok()) {
+                Some(size) if size > 0 => r.buffer_size = size,
+                _ => { return Err(Failure::Usage(format!("{} needs a number of bytes above 0", arg))); }
+            },
+            "-" if r.input.is_none() => r.input = Some(arg),
+            _ if arg.starts_with('-') => { return Err(Failure::Usage(format!("unknown option {}", arg))); }
+            _ if r.input.is_none() => r.input = Some(arg),
+            _ => { return Err(Failure::Usage(format!("unexpected argument {}", arg))); }
+        }
+    }
+    if r.mmap && matches!(r.input.as_deref(), None | Some("-")) {
+        return Err(Failure::Usage("--mmap needs a FILE".to_string()));
+    }
+    return Ok(Some(r));
+}
+
+/// The [Dialect] that's closest to how `name` reads escapes
+///
+/// smashquote understands every escape in every dialect, so these only change the ones that mean
+/// something different, like how many digits `\x` takes.
+fn dialect_preset(name: &str) -> Option<Dialect> {
+    return match name {
+        "default" => Some(Dialect::default()),
+        // like `$'...'`
+        "bash" => Some(Dialect {
+            octal_overflow: OctalOverflow::Wrap,
+            surrogates: SurrogatePolicy::Wtf8,
+            ..Dialect::default()
+        }),
+        // `\x` takes every hex digit after it, `\u` and `\U` take exactly 4 and 8, and a backslash
+        // at the end of a line joins it to the next one
+        "c" => Some(Dialect {
+            max_hex_digits: usize::MAX,
+            fixed_width_unicode: true,
+            line_continuation: LineContinuation::Newline,
+            ..Dialect::default()
+        }),
+        // `\uNNNN` with exactly 4 digits. Surrogate pairs like `\uD83D\uDE00` aren't put back
+        // together, so they're still an error.
+        "json" => Some(Dialect {
+            fixed_width_unicode: true,
+            ..Dialect::default()
+        }),
+        // `\u{...}` and `\xNN`, and a backslash at the end of a line joins it to the next one
+        "rust" => Some(Dialect {
+            line_continuation: LineContinuation::Newline,
+            ..Dialect::default()
+        }),
+        // the format string of `printf`, where `\NNN` takes up to 3 octal digits, and ones that don't
+        // fit in a byte keep their low 8 bits, like most implementations do
+        "posix-printf" => Some(Dialect {
+            octal_overflow: OctalOverflow::Wrap,
+            ..Dialect::default()
+        }),
+        _ => None,
+    };
+}
+
+fn parse_error_format(format: &str) -> Result<ErrorFormat, Failure> {
+    return match format {
+        "text" => Ok(ErrorFormat::Text),
+        "json" if cfg!(feature = "serde") => Ok(ErrorFormat::Json),
+        "json" => Err(Failure::Usage("--error-format=json needs smashquote to be built with the `serde` feature".to_string())),
+        _ => Err(Failure::Usage(format!("unknown error format {:?}, expected text or json", format))),
+    };
+}
+
+/// Unescapes all of `input` to `out`, with literal text written straight from `input`
+fn unescape_to<O: Write>(input: &[u8], out: &mut O, dialect: &Dialect) -> Result<(), Failure> {
+    unescape_slice_vectored_with(input, out, None, dialect).map_err(|e| with_context(e, input, 0))?;
+    out.flush().map_err(UnescapeError::from)?;
+    return Ok(());
+}
+
+#[cfg(feature = "mmap")]
+fn map_file(path: &str) -> Result<memmap2::Mmap, Failure> {
+    let file = File::open(path).map_err(|e| Failure::Io(path.to_string(), e))?;
+    // SAFETY: the map is only read, and if something else changes the file meanwhile, the worst that
+    // happens is that the output is a mix of old and new bytes
+    return unsafe { memmap2::Mmap::map(&file) }.map_err(|e| Failure::Io(path.to_string(), e));
+}
+
+#[cfg(not(feature = "mmap"))]
+fn map_file(_path: &str) -> Result<Vec<u8>, Failure> {
+    return Err(Failure::Usage("--mmap needs smashquote to be built with the `mmap` feature".to_string()));
+}
+
+/// Unescapes `input` to `out` a piece at a time, so it never has to be all in memory at once
+fn stream_to<R: Read, O: Write>(mut input: R, name: &str, out: &mut O, args: &Args) -> Result<(), Failure> {
+    let mut decoder = StreamDecoder::new(args.dialect.clone());
+    let mut buf: Vec<u8> = vec![0; args.buffer_size];
+    loop {
+        let n = match input.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => { return Err(Failure::Io(name.to_string(), e)); }
+        };
+        if let Err(e) = decoder.decode(&buf[..n], out) {
+            return Err(with_context(e, decoder.pending(), decoder.consumed()));
+        }
+        // whatever's next in a pipeline shouldn't have to wait for the rest of the input
+        out.flush().map_err(UnescapeError::from)?;
+    }
+    if let Err(e) = decoder.finish(out) {
+        return Err(with_context(e, decoder.pending(), decoder.consumed()));
+    }
+    out.flush().map_err(UnescapeError::from)?;
+    return Ok(());
+}
+
+/// Unescapes `input`, escapes that in `style`, and checks that unescaping it again gives the same bytes
+fn check_roundtrip(input: &[u8], dialect: &Dialect, style: EscapeStyle) -> Result<(), Failure> {
+    let unescaped = unescape_bytes_with(input, dialect).map_err(|e| with_context(e.into(), input, 0))?;
+    let escaped = escape_bytes_with(&unescaped, &EscapeOptions { style, ..EscapeOptions::default() });
+    let again = match unescape_bytes_with(escaped.as_bytes(), dialect) {
+        Ok(again) => again,
+        Err(e) => {
+            let offset = e.offset().unwrap_or(escaped.len());
+            let context = context(escaped.as_bytes(), 0, offset..offset + 1).unwrap_or_default();
+            return Err(Failure::RoundTrip(None, format!("escaping it gave {}, which doesn't unescape: {}", context, e)));
+        }
+    };
+    let offset = match unescaped.iter().zip(&again).position(|(a, b)| a != b) {
+        Some(offset) => offset,
+        None if unescaped.len() != again.len() => unescaped.len().min(again.len()),
+        None => { return Ok(()); }
+    };
+    let around = |bs: &[u8]| context(bs, 0, offset..offset + 1).unwrap_or_default();
+    return Err(Failure::RoundTrip(Some(offset), format!("{} came back as {}", around(&unescaped), around(&again))));
+}
+
+/// Reads all of the input, for when it has to be in memory at once
+fn read_input(args: &Args) -> Result<Vec<u8>, Failure> {
+    return match args.input.as_deref() {
+        None | Some("-") => {
+            let mut input: Vec<u8> = Vec::new();
+            std::io::stdin().lock().read_to_end(&mut input).map_err(|e| Failure::Io("stdin".to_string(), e))?;
+            Ok(input)
+        }
+        Some(path) => std::fs::read(path).map_err(|e| Failure::Io(path.to_string(), e)),
+    };
+}
+
+fn run(args: &Args) -> Result<(), Failure> {
+    if args.check_roundtrip && args.mmap {
+        let map = map_file(args.input.as_deref().expect("Checked by parse_args."))?;
+        return check_roundtrip(&map, &args.dialect, args.style);
+    } else if args.check_roundtrip {
+        return check_roundtrip(&read_input(args)?, &args.dialect, args.style);
+    }
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path).map_err(|e| Failure::Io(path.clone(), e))?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    if args.mmap {
+        let map = map_file(args.input.as_deref().expect("Checked by parse_args."))?;
+        return unescape_to(&map, &mut out, &args.dialect);
+    }
+    return match args.input.as_deref() {
+        None | Some("-") => stream_to(std::io::stdin().lock(), "stdin", &mut out, args),
+        Some(path) => {
+            let file = File::open(path).map_err(|e| Failure::Io(path.to_string(), e))?;
+            stream_to(file, path, &mut out, args)
+        }
+    };
+}
+
+/// Writes `failure` as a line of JSON, with the same message as the text format
+#[cfg(feature = "serde")]
+fn json_error(failure: &Failure) -> String {
+    let (kind, offset, span, context, error) = match failure {
+        Failure::Unescape(e, context) => {
+            let error = serde_json::to_value(e).expect("The error types can always be serialized.");
+            match e.as_ref() {
+                // the error is serialized as `{"Parse": {"InvalidBackslash": {...}}}`, so the kind is the inner key
+                UnescapeError::Parse(parse) => (error["Parse"].as_object().and_then(|o| o.keys().next().cloned()), parse.offset(), parse.span(), context.clone(), error),
+                UnescapeError::IOError(_) => (Some("IOError".to_string()), None, None, None, error),
+            }
+        }
+        Failure::Io(..) => (Some("IOError".to_string()), None, None, None, serde_json::Value::Null),
+        Failure::Usage(_) => (Some("Usage".to_string()), None, None, None, serde_json::Value::Null),
+        Failure::RoundTrip(offset, _) => (Some("RoundTrip".to_string()), *offset, offset.map(|offset| offset..offset + 1), None, serde_json::Value::Null),
+    };
+    return serde_json::json!({
+        "kind": kind,
+        "offset": offset,
+        "span": span.map(|span| [span.start, span.end]),
+        "context": context,
+        "message": failure.to_string(),
+        "error": error,
+    }).to_string();
+}
+
+#[cfg(not(feature = "serde"))]
+fn json_error(_failure: &Failure) -> String {
+    unreachable!("--error-format=json is refused without the serde feature.");
+}
+
+fn main() -> ExitCode {
+    let mut error_format = ErrorFormat::Text;
+    let r = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => {
+            error_format = args.error_format;
+            run(&args)
+        }
+        Ok(None) => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => Err(e),
+    };
+    match r {
+        Ok(()) => { return ExitCode::SUCCESS; }
+        Err(Failure::Usage(message)) => {
+            eprint!("smashquote: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+        Err(failure) if error_format == ErrorFormat::Json => eprintln!("{}", json_error(&failure)),
+        Err(failure) => eprintln!("smashquote: {}", failure),
+    }
+    return ExitCode::FAILURE;
+}
@@ -0,0 +1,43 @@
+//! Unicode normalization of unescaped output, so that strings built from different escapes compare equal
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{unescape_bytes_with, Dialect, ParseError};
+
+/// Which [Unicode normalization form](https://unicode.org/reports/tr15/) to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Normalization {
+    /// Composed, so `e\u0301` becomes `\u00E9`
+    #[default]
+    Nfc,
+    /// Decomposed, so `\u00E9` becomes `e\u0301`
+    Nfd,
+}
+
+/// Returns a new unescaped byte string from a byte slice, normalized with `form`
+///
+/// Only the parts of the output that are valid UTF-8 are normalized, and any other bytes are kept as-is,
+/// so this is only useful when `dialect` has UTF-8 [encoding](Dialect::encoding).
+///
+/// # Arguments
+///
+/// * `bytes` - A slice of bytes
+/// * `dialect` - Which optional escape syntaxes to understand
+/// * `form` - Which normalization form to use
+pub fn unescape_bytes_normalized(
+    bytes: &[u8],
+    dialect: &Dialect,
+    form: Normalization,
+) -> Result<Vec<u8>, ParseError> {
+    let unescaped = unescape_bytes_with(bytes, dialect)?;
+    let mut r: Vec<u8> = Vec::with_capacity(unescaped.len());
+    for chunk in unescaped.utf8_chunks() {
+        let normalized: String = match form {
+            Normalization::Nfc => chunk.valid().nfc().collect(),
+            Normalization::Nfd => chunk.valid().nfd().collect(),
+        };
+        r.extend_from_slice(normalized.as_bytes());
+        r.extend_from_slice(chunk.invalid());
+    }
+    return Ok(r);
+}
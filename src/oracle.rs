@@ -0,0 +1,73 @@
+//! Checking smashquote against bash, to keep the claim that it understands bash's `$''` format honest
+//!
+//! This runs `bash` from the `PATH` once for each string, so it's meant for testing smashquote itself,
+//! not for use at runtime.
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::process::Command;
+
+use crate::{unescape_bytes_with, Dialect, OctalOverflow, ParseError, SurrogatePolicy};
+
+/// The [Dialect] that's closest to how bash reads `$'...'`
+///
+/// bash also keeps unknown escapes like `\q` as they are, where smashquote returns an error,
+/// and stops at the first NUL byte.
+pub fn bash_dialect() -> Dialect {
+    return Dialect {
+        octal_overflow: OctalOverflow::Wrap,
+        surrogates: SurrogatePolicy::Wtf8,
+        ..Dialect::default()
+    };
+}
+
+/// A string that smashquote and bash unescaped differently
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The string, as written between `$'` and `'`
+    pub escaped: Vec<u8>,
+    /// What bash unescaped it to
+    pub bash: Vec<u8>,
+    /// What smashquote unescaped it to, or why it couldn't
+    pub smashquote: Result<Vec<u8>, ParseError>,
+}
+
+/// Unescapes `escaped` by running `printf %s $'...'` in bash, in a UTF-8 locale
+///
+/// `escaped` is passed as an argument, so it can't have a NUL byte, and an unescaped `'` ends it early.
+pub fn bash_unescape(escaped: &[u8]) -> std::io::Result<Vec<u8>> {
+    let script = [&b"printf %s $'"[..], escaped, b"'"].concat();
+    let output = Command::new("bash")
+        .arg("-c")
+        .arg(OsStr::from_bytes(&script))
+        .env("LC_ALL", "C.UTF-8")
+        .output()?;
+    if ! output.status.success() {
+        return Err(std::io::Error::other(String::from_utf8_lossy(&output.stderr).into_owned()));
+    }
+    return Ok(output.stdout);
+}
+
+/// Unescapes `escaped` with both smashquote and bash, and returns how they differ, if they do
+pub fn compare_with_bash(escaped: &[u8], dialect: &Dialect) -> std::io::Result<Option<Divergence>> {
+    let bash = bash_unescape(escaped)?;
+    let smashquote = unescape_bytes_with(escaped, dialect);
+    if smashquote.as_ref() == Ok(&bash) {
+        return Ok(None);
+    }
+    return Ok(Some(Divergence { escaped: escaped.to_vec(), bash, smashquote }));
+}
+
+/// Compares each of `inputs` with [compare_with_bash], and returns the ones that differ
+pub fn divergences<'a, I>(inputs: I, dialect: &Dialect) -> std::io::Result<Vec<Divergence>>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut r: Vec<Divergence> = Vec::new();
+    for escaped in inputs {
+        if let Some(divergence) = compare_with_bash(escaped, dialect)? {
+            r.push(divergence);
+        }
+    }
+    return Ok(r);
+}
@@ -0,0 +1,114 @@
+//! Filling in placeholders like `{name}` while unescaping
+
+use crate::{pretty_string, stream, unescape_bytes_into_with, BomPolicy, Dialect, ParseError};
+
+/// Looks up the value of a placeholder for [unescape_placeholders], by its name
+pub type PlaceholderResolver<'a> = &'a mut dyn FnMut(&[u8]) -> Option<Vec<u8>>;
+
+/// How placeholders are written, for [unescape_placeholders]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlaceholderStyle {
+    /// `{name}`, where the name is everything up to the next `}`
+    #[default]
+    Braces,
+    /// `%x`, where the name is the one byte after the `%`. A `%` at the end is kept as it is.
+    Percent,
+}
+
+/// Unescapes a byte slice and fills in its placeholders, like `\x1E{record_id}\n`, in one pass
+///
+/// Only placeholders that are literal bytes count, so `\x7B` is a literal `{`. Escapes aren't
+/// unescaped in placeholder names, or in the values that `resolve` returns. A placeholder that `resolve`
+/// returns [None] for is an [UnsetVariable](ParseError::UnsetVariable) error, and a `{` without a `}`
+/// is a [MissingClose](ParseError::MissingClose) error.
+///
+/// # Arguments
+///
+/// * `bytes` - A slice of bytes
+/// * `style` - How placeholders are written
+/// * `resolve` - Looks up the value of each placeholder
+pub fn unescape_placeholders(
+    bytes: &[u8],
+    style: PlaceholderStyle,
+    resolve: PlaceholderResolver<'_>,
+) -> Result<Vec<u8>, ParseError> {
+    return unescape_placeholders_with(bytes, style, resolve, &Dialect::default());
+}
+
+/// Unescapes a byte slice and fills in its placeholders using a specific [Dialect], like [unescape_placeholders]
+///
+/// # Arguments
+///
+/// * `bytes` - A slice of bytes
+/// * `style` - How placeholders are written
+/// * `resolve` - Looks up the value of each placeholder
+/// * `dialect` - Which optional escape syntaxes to understand
+pub fn unescape_placeholders_with(
+    bytes: &[u8],
+    style: PlaceholderStyle,
+    resolve: PlaceholderResolver<'_>,
+    dialect: &Dialect,
+) -> Result<Vec<u8>, ParseError> {
+    let mut r: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut run: Vec<u8> = Vec::new();
+    // where the escaped text since the last placeholder starts
+    let mut start: usize = 0;
+    let mut i: usize = 0;
+    while let Some((len, _)) = stream::token_len(bytes, i, dialect) {
+        // escapes and other bytes aren't placeholders
+        let name = match (style, bytes[i]) {
+            _ if len != 1 => None,
+            (PlaceholderStyle::Braces, b'{') => match bytes[i+1..].iter().position(|&b| b == b'}') {
+                Some(n) => Some(i+1..i+1+n),
+                None => { return Err(ParseError::missing_close(b'}')); }
+            },
+            (PlaceholderStyle::Percent, b'%') if i + 1 < bytes.len() => Some(i+1..i+2),
+            _ => None,
+        };
+        let Some(name) = name else {
+            i += len;
+            continue;
+        };
+        unescape_run(bytes, start..i, &mut run, r.len(), dialect)?;
+        r.extend_from_slice(&run);
+        let value = match resolve(&bytes[name.clone()]) {
+            Some(value) => value,
+            None => { return Err(ParseError::UnsetVariable { offset: dialect.base_offset + i, name: pretty_string(&bytes[name]) }); }
+        };
+        if let Some(max_len) = dialect.max_output_len {
+            if r.len() + value.len() > max_len {
+                return Err(ParseError::OutputTooLong { offset: dialect.base_offset + i, max_len });
+            }
+        }
+        r.extend_from_slice(&value);
+        i = name.end + usize::from(style == PlaceholderStyle::Braces);
+        start = i;
+    }
+    unescape_run(bytes, start..bytes.len(), &mut run, r.len(), dialect)?;
+    r.extend_from_slice(&run);
+    return Ok(r);
+}
+
+/// Unescapes the text between placeholders into `out`, after `written` bytes of output so far
+fn unescape_run(
+    bytes: &[u8],
+    run: std::ops::Range<usize>,
+    out: &mut Vec<u8>,
+    written: usize,
+    dialect: &Dialect,
+) -> Result<(), ParseError> {
+    let dialect = Dialect {
+        base_offset: dialect.base_offset + run.start,
+        max_output_len: dialect.max_output_len.map(|max_len| max_len - written),
+        // only the very start can have a byte order mark
+        bom: if run.start == 0 { dialect.bom } else { BomPolicy::Keep },
+        ..dialect.clone()
+    };
+    return match unescape_bytes_into_with(&bytes[run], out, &dialect) {
+        Err(ParseError::OutputTooLong { offset, .. }) => Err(ParseError::OutputTooLong {
+            offset,
+            max_len: dialect.max_output_len.expect("Only a limit can be reached.") + written,
+        }),
+        r => r,
+    };
+}
@@ -0,0 +1,325 @@
+//! Helpers for showing raw bytes to humans, mostly in error messages
+
+/// Whether hex digits are printed as `0A` or `0a`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HexCase {
+    /// `0A`
+    #[default]
+    Upper,
+    /// `0a`
+    Lower,
+}
+
+/// Options for [pretty_bytes_with]
+///
+/// The [Default] options produce the same output as [pretty_bytes].
+#[derive(Debug, Clone)]
+pub struct PrettyBytesOptions {
+    /// Upper or lower case hex digits
+    pub case: HexCase,
+    /// Printed before each group, like `0x`
+    pub prefix: String,
+    /// How many bytes are printed together in each group, like 2 for pairs or 4 for words
+    pub group: usize,
+    /// Printed between groups
+    pub separator: String,
+    /// The maximum number of bytes to print before giving up and printing the ellipsis
+    pub max_len: Option<usize>,
+    /// Printed after the last group, if some bytes were left out because of `max_len`
+    pub ellipsis: String,
+}
+
+impl Default for PrettyBytesOptions {
+    fn default() -> Self {
+        return Self {
+            case: HexCase::Upper,
+            prefix: String::new(),
+            group: 1,
+            separator: String::from(" "),
+            max_len: None,
+            ellipsis: String::from("..."),
+        };
+    }
+}
+
+/// Prints bytes as space-separated hex digits
+pub fn pretty_bytes(bs: &[u8]) -> String {
+    return pretty_bytes_with(bs, &PrettyBytesOptions::default());
+}
+
+/// Prints bytes as hex digits, formatted according to `options`
+pub fn pretty_bytes_with(bs: &[u8], options: &PrettyBytesOptions) -> String {
+    let shown: &[u8] = match options.max_len {
+        Some(max_len) if max_len < bs.len() => &bs[..max_len],
+        _ => bs,
+    };
+    let mut r: String = shown
+        .chunks(options.group.max(1))
+        .map(|group| {
+            let digits: String = group.iter().map(|byte| match options.case {
+                HexCase::Upper => format!("{:02X}", byte),
+                HexCase::Lower => format!("{:02x}", byte),
+            }).collect();
+            format!("{}{}", options.prefix, digits)
+        })
+        .collect::<Vec<String>>()
+        .join(&options.separator);
+    if shown.len() < bs.len() {
+        r.push_str(&options.ellipsis);
+    }
+    return r;
+}
+
+/// How [pretty_string_with] shows control characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ControlStyle {
+    /// Unicode control pictures like `␍`, which also shows spaces as `␠`
+    #[default]
+    Pictures,
+    /// Caret notation like `^M`
+    Caret,
+    /// Hex escapes like `\x0D`
+    Hex,
+    /// Hex escapes like `\x0D`, also for each byte that isn't valid UTF-8, and `\\` for backslashes.
+    /// This ignores [replacement](PrettyStringOptions::replacement), and the result unescapes back to
+    /// the original bytes, as long as `max_width` didn't cut it short.
+    Reversible,
+}
+
+/// Options for [pretty_string_with]
+///
+/// The [Default] options produce the same output as [pretty_string].
+#[derive(Debug, Clone)]
+pub struct PrettyStringOptions {
+    /// How control characters are shown
+    pub control: ControlStyle,
+    /// Shown instead of each run of bytes that aren't valid UTF-8
+    pub replacement: String,
+    /// The maximum number of characters to show, including the ellipsis
+    pub max_width: Option<usize>,
+    /// The maximum number of terminal columns to fill, including the ellipsis. Wide characters
+    /// count as two columns and combining marks as none, and graphemes are never cut in half.
+    #[cfg(feature = "width")]
+    pub max_columns: Option<usize>,
+    /// Shown at the end, if some characters were left out because of `max_width` or `max_columns`.
+    /// It counts toward the limit too, so only as much of it as fits is shown.
+    pub ellipsis: String,
+    /// Remove ANSI escape sequences, like color codes, with [strip_ansi] before showing anything.
+    /// Otherwise they're shown like any other control characters.
+    pub strip_ansi: bool,
+}
+
+impl Default for PrettyStringOptions {
+    fn default() -> Self {
+        return Self {
+            control: ControlStyle::Pictures,
+            replacement: String::from("\u{FFFD}"),
+            max_width: None,
+            #[cfg(feature = "width")]
+            max_columns: None,
+            ellipsis: String::from("..."),
+            strip_ansi: false,
+        };
+    }
+}
+
+/// Tries to represent bytes as presentable unicode
+pub fn pretty_string(bs: &[u8]) -> String {
+    return pretty_string_with(bs, &PrettyStringOptions::default());
+}
+
+/// The characters that [ControlStyle::Pictures] shows control bytes and spaces as, as `(byte, picture)` pairs
+///
+/// [unpretty_string] turns these back into bytes, and also reads `U+2421` as `0x7F`.
+pub const CONTROL_PICTURES: [(u8, char); 34] = {
+    let mut r = [(0x7Fu8, '\u{247F}'); 34];
+    let mut byte: u8 = 0;
+    while byte <= 0x20 {
+        r[byte as usize] = (byte, match char::from_u32(0x2400u32 + byte as u32) {
+            Some(c) => c,
+            None => panic!("Unicode code points 0x2400-2420 are valid."),
+        });
+        byte += 1;
+    }
+    r
+};
+
+fn control_picture(c: char) -> Option<char> {
+    return CONTROL_PICTURES.iter().find(|&&(byte, _)| byte as u32 == c as u32).map(|&(_, picture)| picture);
+}
+
+fn push_pretty_char(r: &mut String, c: char, control: ControlStyle) {
+    if let (ControlStyle::Pictures, Some(picture)) = (control, control_picture(c)) {
+        r.push(picture);
+        return;
+    }
+    match (control, c) {
+        (ControlStyle::Caret, '\u{0}'..='\u{1F}') => {
+            r.push('^');
+            r.push(char::from_u32((c as u32) + 0x40u32).expect("Unicode code points 0x40-0x5F are valid."));
+        }
+        (ControlStyle::Caret, '\u{7F}') => r.push_str("^?"),
+        (ControlStyle::Hex | ControlStyle::Reversible, '\u{0}'..='\u{1F}' | '\u{7F}') => r.push_str(&format!("\\x{:02X}", c as u32)),
+        (ControlStyle::Reversible, '\\') => r.push_str("\\\\"),
+        _ => r.push(c),
+    }
+}
+
+/// Tries to represent bytes as presentable unicode, formatted according to `options`
+pub fn pretty_string_with(bs: &[u8], options: &PrettyStringOptions) -> String {
+    let stripped: Vec<u8>;
+    let bs: &[u8] = if options.strip_ansi {
+        stripped = strip_ansi(bs);
+        &stripped
+    } else {
+        bs
+    };
+    let mut r = String::with_capacity(bs.len());
+    for chunk in bs.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            push_pretty_char(&mut r, c, options.control);
+        }
+        if options.control == ControlStyle::Reversible {
+            for byte in chunk.invalid() {
+                r.push_str(&format!("\\x{:02X}", byte));
+            }
+        } else if ! chunk.invalid().is_empty() {
+            r.push_str(&options.replacement);
+        }
+    }
+    if let Some(max_width) = options.max_width {
+        if r.chars().count() > max_width {
+            let keep = max_width.saturating_sub(options.ellipsis.chars().count());
+            r = r.chars().take(keep).collect();
+            // an ellipsis that's too long on its own gets cut short too
+            r.extend(options.ellipsis.chars().take(max_width - keep));
+        }
+    }
+    #[cfg(feature = "width")]
+    if let Some(max_columns) = options.max_columns {
+        r = truncate_columns(r, max_columns, &options.ellipsis);
+    }
+    return r;
+}
+
+/// Cuts `s` down to `max_columns` terminal columns on a grapheme boundary, ending with as much of `ellipsis` as fits
+#[cfg(feature = "width")]
+fn truncate_columns(s: String, max_columns: usize, ellipsis: &str) -> String {
+    use unicode_segmentation::UnicodeSegmentation;
+    use unicode_width::UnicodeWidthStr;
+    if s.width() <= max_columns {
+        return s;
+    }
+    let budget = max_columns.saturating_sub(ellipsis.width());
+    let mut r = String::with_capacity(s.len().min(max_columns * 4));
+    let mut columns: usize = 0;
+    for grapheme in s.graphemes(true) {
+        columns += grapheme.width();
+        if columns > budget {
+            break;
+        }
+        r.push_str(grapheme);
+    }
+    // an ellipsis that's too wide on its own gets cut short too
+    let mut columns: usize = r.width();
+    for grapheme in ellipsis.graphemes(true) {
+        columns += grapheme.width();
+        if columns > max_columns {
+            break;
+        }
+        r.push_str(grapheme);
+    }
+    return r;
+}
+
+/// Removes ANSI/VT100 escape sequences, like color codes, from bytes
+///
+/// This understands CSI sequences like `ESC [ 3 1 m`, OSC sequences like
+/// `ESC ] 0 ; title BEL` (ended by `BEL` or `ESC \`), and other `ESC` sequences like `ESC ( B` or `ESC M`.
+/// An incomplete sequence at the end is removed too.
+pub fn strip_ansi(bs: &[u8]) -> Vec<u8> {
+    let mut r: Vec<u8> = Vec::with_capacity(bs.len());
+    let mut bytes = bs.iter().peekable();
+    while let Some(&byte) = bytes.next() {
+        if byte != 0x1B {
+            r.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'[') => {
+                // parameter and intermediate bytes, then one final byte
+                for &byte2 in bytes.by_ref() {
+                    if (0x40..=0x7E).contains(&byte2) {
+                        break;
+                    }
+                }
+            }
+            Some(b']') => {
+                while let Some(&byte2) = bytes.next() {
+                    if byte2 == 0x07 {
+                        break;
+                    } else if byte2 == 0x1B && bytes.peek() == Some(&&b'\\') {
+                        bytes.next();
+                        break;
+                    }
+                }
+            }
+            Some(&byte2) if (0x20..=0x2F).contains(&byte2) => {
+                // intermediate bytes, then one final byte, like `ESC ( B`
+                for &byte3 in bytes.by_ref() {
+                    if ! (0x20..=0x2F).contains(&byte3) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    return r;
+}
+
+/// Converts the output of [pretty_string] back into bytes
+///
+/// Control pictures `U+2400` through `U+2420` become `0x0` through `0x20` again, and
+/// `U+2421` or `U+247F` become `0x7F`. Everything else is kept as UTF-8. Note that any
+/// invalid UTF-8 that [pretty_string] replaced with `U+FFFD` can't be recovered.
+pub fn unpretty_string(s: &str) -> Vec<u8> {
+    let mut r: Vec<u8> = Vec::with_capacity(s.len());
+    for c in s.chars() {
+        if let Some(&(byte, _)) = CONTROL_PICTURES.iter().find(|&&(_, picture)| picture == c) {
+            r.push(byte);
+        } else if c == '\u{2421}' {
+            r.push(0x7F);
+        } else {
+            let mut buf = [0u8; 4];
+            r.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+    }
+    return r;
+}
+
+
+/// Prints bytes as a multi-line hex dump, like `xxd`
+///
+/// Each row shows the offset, 16 bytes as hex digits, and the same bytes as ASCII.
+/// Bytes that aren't printable ASCII are shown as `.` in the ASCII pane, unless
+/// `control_pictures` is set, in which case control bytes are shown the same way
+/// [pretty_string] shows them.
+pub fn hexdump(bs: &[u8], control_pictures: bool) -> String {
+    let hex_options = PrettyBytesOptions {
+        case: HexCase::Lower,
+        group: 2,
+        ..PrettyBytesOptions::default()
+    };
+    let mut r = String::with_capacity((bs.len() / 16 + 1) * 80);
+    for (row, chunk) in bs.chunks(16).enumerate() {
+        let ascii: String = chunk.iter().map(|&byte| match byte {
+            0x21..=0x7E => byte as char,
+            0x00..=0x20 | 0x7F if control_pictures => pretty_string(&[byte]).chars().next().expect("pretty_string never returns an empty string for 1 byte."),
+            b' ' => ' ',
+            _ => '.',
+        }).collect();
+        r.push_str(&format!("{:08x}: {:<39}  {}\n", row * 16, pretty_bytes_with(chunk, &hex_options), ascii));
+    }
+    return r;
+}
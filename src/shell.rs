@@ -0,0 +1,896 @@
+//! Helpers for finding the structure of shell-like text, like where the quotes are
+
+use std::ops::Range;
+
+use crate::{pretty_string, unescape_bytes_with, unescape_slice_with, Dialect, ParseError, UnescapeError};
+
+/// Which kind of quotes a [QuotedRegion] is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteStyle {
+    /// `'...'`, where backslashes aren't special
+    Single,
+    /// `"..."`, where a backslash escapes the next byte
+    Double,
+    /// `$'...'`, bash's ANSI-C quotes, where backslash escapes mean what smashquote says they mean
+    Dollar,
+}
+
+/// A quoted part of some text, found by [find_quoted_regions]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotedRegion {
+    /// Which kind of quotes
+    pub style: QuoteStyle,
+    /// Where the whole region is, including the quotes (and the `$` of `$'...'`)
+    pub span: Range<usize>,
+    /// Where the text between the quotes is, still escaped
+    pub content: Range<usize>,
+}
+
+/// Finds the quoted regions in shell-like text, without unescaping anything
+///
+/// Outside of quotes, a backslash escapes the next byte, so `\'` doesn't start a quoted region.
+/// A quoted region that isn't closed is a [MissingClose](ParseError::MissingClose) error.
+pub fn find_quoted_regions(bs: &[u8]) -> Result<Vec<QuotedRegion>, ParseError> {
+    let mut r: Vec<QuotedRegion> = Vec::new();
+    let mut i: usize = 0;
+    while let Some(region) = next_quoted_region(bs, &mut i) {
+        r.push(region?);
+    }
+    return Ok(r);
+}
+
+/// Finds the next quoted region at or after `*i`, and moves `*i` past it
+fn next_quoted_region(bs: &[u8], i: &mut usize) -> Option<Result<QuotedRegion, ParseError>> {
+    while *i < bs.len() {
+        let (style, start, quote) = match bs[*i] {
+            b'\\' => {
+                *i += 2;
+                continue;
+            }
+            b'\'' => (QuoteStyle::Single, *i, b'\''),
+            b'"' => (QuoteStyle::Double, *i, b'"'),
+            b'$' if bs.get(*i+1) == Some(&b'\'') => {
+                *i += 1;
+                (QuoteStyle::Dollar, *i-1, b'\'')
+            }
+            _ => {
+                *i += 1;
+                continue;
+            }
+        };
+        let end = match find_close(bs, *i+1, quote, style != QuoteStyle::Single) {
+            Some(end) => end,
+            None => {
+                *i = bs.len();
+                return Some(Err(ParseError::missing_close(quote)));
+            }
+        };
+        let region = QuotedRegion { style, span: start..end+1, content: *i+1..end };
+        *i = end + 1;
+        return Some(Ok(region));
+    }
+    return None;
+}
+
+/// Moves `*i` past the quoted region that starts there
+fn skip_quoted_region(bs: &[u8], i: &mut usize) -> Result<(), ParseError> {
+    return match next_quoted_region(bs, i) {
+        Some(Err(e)) => Err(e),
+        _ => Ok(()),
+    };
+}
+
+/// A quoted string literal found by [iter_quoted], with its quotes taken off
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotedLiteral {
+    /// Where the literal was in the input
+    pub region: QuotedRegion,
+    /// The unquoted, unescaped value
+    pub value: Vec<u8>,
+}
+
+/// Iterator over the quoted string literals in some text, from [iter_quoted]
+#[derive(Debug, Clone)]
+pub struct IterQuoted<'a> {
+    bs: &'a [u8],
+    i: usize,
+    dialect: Dialect,
+}
+
+impl Iterator for IterQuoted<'_> {
+    type Item = Result<QuotedLiteral, ParseError>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        let region = match next_quoted_region(self.bs, &mut self.i)? {
+            Ok(region) => region,
+            Err(e) => { return Some(Err(e)); }
+        };
+        let mut value: Vec<u8> = Vec::with_capacity(region.content.len());
+        if let Err(e) = decode_region(self.bs, &region, &self.dialect, &mut value) {
+            self.i = self.bs.len();
+            return Some(Err(e));
+        }
+        return Some(Ok(QuotedLiteral { region, value }));
+    }
+}
+
+impl std::iter::FusedIterator for IterQuoted<'_> {
+}
+
+/// Finds, unquotes and yields each `'...'`, `"..."` and `$'...'` literal in some text, in one pass
+///
+/// Everything between the literals is skipped over. Regions are found like [find_quoted_regions] finds
+/// them, and decoded like [decode_quoted_regions_with] decodes them with `single` and `double` set.
+/// After an error, like a quote that isn't closed, there are no more literals.
+pub fn iter_quoted(bs: &[u8]) -> IterQuoted<'_> {
+    return iter_quoted_with(bs, &Dialect::default());
+}
+
+/// Iterates over the quoted string literals in some text like [iter_quoted], unescaping `$'...'`
+/// literals in `dialect`
+pub fn iter_quoted_with<'a>(bs: &'a [u8], dialect: &Dialect) -> IterQuoted<'a> {
+    return IterQuoted { bs, i: 0, dialect: dialect.clone() };
+}
+
+/// Options for [decode_quoted_regions_with]
+///
+/// The [Default] options produce the same output as [decode_quoted_regions].
+#[derive(Debug, Clone, Default)]
+pub struct RegionOptions {
+    /// Also take the quotes off `'...'` regions
+    pub single: bool,
+    /// Also unquote `"..."` regions, which only understand `\$`, `` \` ``, `\"`, `\\`, and `\` followed by
+    /// a newline. Nothing inside them is expanded.
+    pub double: bool,
+    /// The dialect that `$'...'` regions are unescaped in
+    pub dialect: Dialect,
+}
+
+/// A quoted region that [decode_quoted_regions] replaced with its contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedRegion {
+    /// Where the region was in the input
+    pub region: QuotedRegion,
+    /// Where its unquoted contents are in the output
+    pub output: Range<usize>,
+}
+
+/// Unescapes the `$'...'` regions in some text, and leaves everything else as it is
+///
+/// Each region, including its `$` and quotes, is replaced with its unescaped contents. This returns the
+/// new text, and where each region was in the input and ended up in the output. Regions are found with
+/// [find_quoted_regions], so `'...'` and `"..."` are skipped over, and a quote that isn't closed is an error.
+pub fn decode_quoted_regions(bs: &[u8]) -> Result<(Vec<u8>, Vec<DecodedRegion>), ParseError> {
+    return decode_quoted_regions_with(bs, &RegionOptions::default());
+}
+
+/// Unescapes the quoted regions in some text, formatted according to `options`, and leaves everything
+/// else as it is, like [decode_quoted_regions]
+pub fn decode_quoted_regions_with(bs: &[u8], options: &RegionOptions) -> Result<(Vec<u8>, Vec<DecodedRegion>), ParseError> {
+    let mut r: Vec<u8> = Vec::with_capacity(bs.len());
+    let mut decoded: Vec<DecodedRegion> = Vec::new();
+    let mut i: usize = 0;
+    for region in find_quoted_regions(bs)? {
+        match region.style {
+            QuoteStyle::Single if ! options.single => { continue; }
+            QuoteStyle::Double if ! options.double => { continue; }
+            _ => {}
+        }
+        r.extend_from_slice(&bs[i..region.span.start]);
+        let start = r.len();
+        decode_region(bs, &region, &options.dialect, &mut r)?;
+        i = region.span.end;
+        decoded.push(DecodedRegion { output: start..r.len(), region });
+    }
+    r.extend_from_slice(&bs[i..]);
+    return Ok((r, decoded));
+}
+
+/// A `key=value` pair from [parse_key_value]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyValue {
+    /// The key, as it was in the line
+    pub key: Vec<u8>,
+    /// Where the key is in the line
+    pub key_span: Range<usize>,
+    /// The value, with its quotes removed and escapes unescaped
+    pub value: Vec<u8>,
+    /// Where the value is in the line, including any quotes, but not the line ending
+    pub value_span: Range<usize>,
+}
+
+/// Parses a `key=value` line, like in a `.env` file, where the value can be quoted and escaped
+///
+/// The key is everything before the first `=` that isn't escaped or in quotes, and isn't unescaped. The
+/// value is everything after it, up to a `\n` or `\r\n` at the end of the line, and can be empty.
+/// In the value, `'...'`, `"..."`, and `$'...'` are unquoted like [iter_quoted] does, and everything
+/// outside of quotes is unescaped with the [Default] [Dialect], so `a=x\ty` and `a=$'x\ty'` mean the same.
+/// Nothing is trimmed, and a line without an `=`, like a blank line, is [None].
+pub fn parse_key_value(line: &[u8]) -> Result<Option<KeyValue>, ParseError> {
+    return parse_key_value_with(line, &Dialect::default());
+}
+
+/// Parses a `key=value` line like [parse_key_value], unescaping the value in `dialect`
+pub fn parse_key_value_with(line: &[u8], dialect: &Dialect) -> Result<Option<KeyValue>, ParseError> {
+    let end = line.strip_suffix(b"\n").map_or(line.len(), |l| l.strip_suffix(b"\r").unwrap_or(l).len());
+    let line = &line[..end];
+    let mut i: usize = 0;
+    let eq = loop {
+        match line.get(i) {
+            None => { return Ok(None); }
+            Some(b'=') => break i,
+            Some(b'\\') => i += 2,
+            Some(b'\'' | b'"') => skip_quoted_region(line, &mut i)?,
+            Some(b'$') if line.get(i+1) == Some(&b'\'') => skip_quoted_region(line, &mut i)?,
+            Some(_) => i += 1,
+        }
+    };
+    let mut value: Vec<u8> = Vec::with_capacity(end - eq - 1);
+    let mut i: usize = eq + 1;
+    let unquoted = |value: &mut Vec<u8>, range: Range<usize>| -> Result<(), ParseError> {
+        let dialect = Dialect { base_offset: dialect.base_offset + range.start, ..dialect.clone() };
+        value.extend_from_slice(&unescape_bytes_with(&line[range], &dialect)?);
+        return Ok(());
+    };
+    let mut start = i;
+    while let Some(region) = next_quoted_region(line, &mut i) {
+        let region = region?;
+        unquoted(&mut value, start..region.span.start)?;
+        decode_region(line, &region, dialect, &mut value)?;
+        start = region.span.end;
+    }
+    unquoted(&mut value, start..end)?;
+    return Ok(Some(KeyValue { key: line[..eq].to_vec(), key_span: 0..eq, value, value_span: eq+1..end }));
+}
+
+/// Writes the unquoted contents of `region` of `bs` to `out`, unescaping `$'...'` in `dialect`
+fn decode_region(bs: &[u8], region: &QuotedRegion, dialect: &Dialect, out: &mut Vec<u8>) -> Result<(), ParseError> {
+    let content = &bs[region.content.clone()];
+    match region.style {
+        QuoteStyle::Single => out.extend_from_slice(content),
+        QuoteStyle::Double => {
+            lex_double_quoted(bs, region.content.start, bs.len(), true, out, &mut Vec::new(), &mut LexOptions::default())?;
+        }
+        QuoteStyle::Dollar => {
+            let dialect = Dialect { base_offset: dialect.base_offset + region.content.start, ..dialect.clone() };
+            out.extend_from_slice(&unescape_bytes_with(content, &dialect)?);
+        }
+    }
+    return Ok(());
+}
+
+/// Finds the offset of the next `quote` from `start`, skipping backslash escapes if `escapes` is set
+fn find_close(bs: &[u8], start: usize, quote: u8, escapes: bool) -> Option<usize> {
+    let mut i = start;
+    while i < bs.len() {
+        if escapes && bs[i] == b'\\' {
+            i += 2;
+        } else if bs[i] == quote {
+            return Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    return None;
+}
+
+/// What a [Token] from [lex_line] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A word, like an argument in argv, with its quotes removed and escapes unescaped
+    Word,
+    /// A control or redirection operator, like `|`, `&&`, `;`, or `>>`, which is kept as-is
+    Operator,
+    /// The body of a here-document, started by `<<TAG` or `<<-TAG`. It comes after the rest of the
+    /// tokens on the line with the `<<`, including the `TAG` word.
+    HereDoc,
+}
+
+/// A word or operator from [lex_line]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    /// Whether this is a word or an operator
+    pub kind: TokenKind,
+    /// The unquoted word, or the operator
+    pub value: Vec<u8>,
+    /// Where the token is in the line, including any quotes
+    pub span: Range<usize>,
+    /// Where the command substitutions like `$(...)` or `` `...` `` in the word are in the line
+    pub substitutions: Vec<Range<usize>>,
+    /// The word has an unquoted `*`, `?`, or `[`, so a shell would glob it. That includes ones that
+    /// unquoted variables and command substitutions expanded to, but not escaped ones like `\*`.
+    pub glob: bool,
+}
+
+/// Operators, longest first so that `&&` isn't read as two `&`s
+const OPERATORS: [&[u8]; 17] = [
+    b"<<<", b"<<-",
+    b"&&", b"||", b";;", b">>", b"<<", b"<&", b">&", b"<>",
+    b"|", b"&", b";", b"(", b")", b"<", b">",
+];
+
+fn operator_at(bs: &[u8], i: usize) -> Option<&'static [u8]> {
+    return OPERATORS.iter().copied().find(|op| bs[i..].starts_with(op));
+}
+
+fn is_blank(byte: u8) -> bool {
+    return matches!(byte, b' ' | b'\t' | b'\n');
+}
+
+/// What [lex_line_with] does with a variable that its resolver doesn't know
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnsetPolicy {
+    /// Leave the reference as it was, like `$NAME`
+    #[default]
+    Keep,
+    /// Return an [UnsetVariable](ParseError::UnsetVariable) error
+    Error,
+}
+
+/// Looks up the value of a variable by name, or returns [None] if it isn't set
+pub type VariableResolver<'a> = &'a mut dyn FnMut(&[u8]) -> Option<Vec<u8>>;
+
+/// Looks up the home directory of a user by name, or of the current user if the name is empty,
+/// or returns [None] if there's no such user
+pub type TildeResolver<'a> = &'a mut dyn FnMut(&[u8]) -> Option<Vec<u8>>;
+
+/// Runs the command inside a command substitution, and returns its output
+pub type CommandRunner<'a> = &'a mut dyn FnMut(&[u8]) -> Vec<u8>;
+
+/// What [lex_line_with] does with command substitutions like `$(...)` or `` `...` ``
+#[derive(Default)]
+pub enum SubstitutionPolicy<'a> {
+    /// Leave them in the word as they are, as opaque spans
+    #[default]
+    Keep,
+    /// Return a [CommandSubstitution](ParseError::CommandSubstitution) error
+    Error,
+    /// Replace them with what the [CommandRunner] returns for the command inside
+    Run(CommandRunner<'a>),
+}
+
+/// Options for [lex_line_with]
+///
+/// The [Default] options produce the same output as [lex_line].
+pub struct LexOptions<'a> {
+    /// Treat an unquoted `#` at the start of a word as the start of a comment, which goes to the end
+    /// of the line. Otherwise it's part of the word, like any other byte.
+    pub comments: bool,
+    /// Expand unquoted `{a,b}` and `{1..5}` patterns in words into several words, like bash does before
+    /// any other expansion. Each of those words has the span of the whole word it came from.
+    /// A word that would expand to more than [MAX_BRACE_WORDS] words is left as it is, braces and all.
+    pub braces: bool,
+    /// Looks up variables for `$NAME` and `${NAME}` in words and double quotes.
+    /// If this isn't set, variable references are left as they are.
+    pub variables: Option<VariableResolver<'a>>,
+    /// What to do when `variables` returns [None]
+    pub unset: UnsetPolicy,
+    /// Looks up home directories for a `~` or `~user` at the start of a word, up to the first `/`.
+    /// Like in shells, nothing in that part can be quoted, and if there's no such user it's left as it is.
+    pub tilde: Option<TildeResolver<'a>>,
+    /// What to do with command substitutions, in words and double quotes
+    pub substitution: SubstitutionPolicy<'a>,
+}
+
+impl Default for LexOptions<'_> {
+    fn default() -> Self {
+        return Self {
+            comments: true,
+            braces: false,
+            variables: None,
+            unset: UnsetPolicy::Keep,
+            tilde: None,
+            substitution: SubstitutionPolicy::Keep,
+        };
+    }
+}
+
+/// Splits a line of shell-like text into words and operators, like a shell would before running it
+///
+/// Words are split at unquoted spaces, tabs, and newlines, and at operators. Each word has its quotes
+/// removed: `'...'` is kept as-is, `"..."` only understands `\$`, `` \` ``, `\"`, `\\`, and `\` followed
+/// by a newline, and `$'...'` is unescaped with the [Default] [Dialect]. Outside of quotes, a backslash
+/// escapes the next byte. A `#` at the start of a word starts a comment that goes to the end of the line.
+///
+/// This doesn't expand anything, so `$HOME`, `*`, and `~` are left as they are. See [lex_line_with] for
+/// expanding variables and `~`.
+pub fn lex_line(bs: &[u8]) -> Result<Vec<Token>, ParseError> {
+    return lex_line_with(bs, &mut LexOptions::default());
+}
+
+/// Splits a line of shell-like text into words and operators, according to `options`
+///
+/// Expanded variables aren't split into more words. Use [split_fields] for that.
+pub fn lex_line_with(bs: &[u8], options: &mut LexOptions<'_>) -> Result<Vec<Token>, ParseError> {
+    let mut r: Vec<Token> = Vec::new();
+    // set right after a `<<` or `<<-`, to whether it was `<<-`
+    let mut heredoc_operator: Option<bool> = None;
+    let mut heredocs: Vec<HereDocTag> = Vec::new();
+    let mut i: usize = 0;
+    while i < bs.len() {
+        if bs[i] == b'\n' && ! heredocs.is_empty() {
+            // each body starts right after the one before it
+            let mut next = i + 1;
+            for tag in heredocs.drain(..) {
+                let token: Token;
+                (token, next) = lex_heredoc(bs, next, &tag, options)?;
+                r.push(token);
+            }
+            i = next;
+        } else if is_blank(bs[i]) {
+            i += 1;
+        } else if bs[i] == b'\\' && bs.get(i+1) == Some(&b'\n') {
+            i += 2; // line continuation
+        } else if bs[i] == b'#' && options.comments {
+            while i < bs.len() && bs[i] != b'\n' {
+                i += 1;
+            }
+        } else if let Some(op) = operator_at(bs, i) {
+            r.push(Token { kind: TokenKind::Operator, value: op.to_vec(), span: i..i+op.len(), substitutions: Vec::new(), glob: false });
+            heredoc_operator = match op {
+                b"<<" => Some(false),
+                b"<<-" => Some(true),
+                _ => None,
+            };
+            i += op.len();
+        } else {
+            let start = i;
+            let mut value: Vec<u8> = Vec::new();
+            let mut substitutions: Vec<Range<usize>> = Vec::new();
+            if options.braces && heredoc_operator.is_none() {
+                // find the end of the word first, without running anything
+                let end = lex_word(bs, i, &mut Vec::new(), &mut Vec::new(), &mut false, &mut LexOptions::default())?;
+                let words = expand_braces(bs[start..end].to_vec(), (start..end).collect(), MAX_BRACE_WORDS).unwrap_or_default();
+                if words.len() > 1 {
+                    for (word, origins) in words {
+                        r.push(lex_expanded_word(&word, &origins, start..end, options)?);
+                    }
+                    i = end;
+                    continue;
+                }
+            }
+            let mut glob = false;
+            i = lex_word(bs, i, &mut value, &mut substitutions, &mut glob, options)?;
+            if let Some(strip_tabs) = heredoc_operator.take() {
+                let quoted = bs[start..i].iter().any(|byte| matches!(byte, b'\\' | b'\'' | b'"'));
+                heredocs.push(HereDocTag { tag: value.clone(), quoted, strip_tabs });
+            }
+            r.push(Token { kind: TokenKind::Word, value, span: start..i, substitutions, glob });
+        }
+    }
+    return Ok(r);
+}
+
+/// Returns the offset just past the quotes, escape, or substitution at `i` in a word,
+/// or past the byte at `i` if it isn't one of those
+fn skip_quoted(bs: &[u8], i: usize) -> usize {
+    let end = match bs[i] {
+        b'\\' => Some(i + 1),
+        b'\'' => find_close(bs, i+1, b'\'', false),
+        b'"' => find_close(bs, i+1, b'"', true),
+        b'$' if bs.get(i+1) == Some(&b'\'') => find_close(bs, i+2, b'\'', true),
+        b'$' if bs.get(i+1) == Some(&b'{') => bs[i..].iter().position(|&byte| byte == b'}').map(|len| i + len),
+        b'`' => find_substitution_end(bs, i).ok().map(|end| end - 1),
+        b'$' if bs.get(i+1) == Some(&b'(') => find_substitution_end(bs, i).ok().map(|end| end - 1),
+        _ => Some(i),
+    };
+    return end.map_or(bs.len(), |end| end + 1);
+}
+
+/// The most words brace expansion makes out of one word, before it gives up and leaves the word as it is
+pub const MAX_BRACE_WORDS: usize = 10_000;
+
+/// One alternative inside braces, and where it starts in the word if it's copied from there
+type BracePart = (Vec<u8>, Option<usize>);
+
+/// Finds the first unquoted `{...}` in a word that brace expansion applies to, and returns where its
+/// `{` and `}` are along with what it expands to. Each part that's copied from the word has where it starts.
+fn find_braces(bs: &[u8]) -> Option<(usize, usize, Vec<BracePart>)> {
+    let mut open: usize = 0;
+    while open < bs.len() {
+        if bs[open] != b'{' {
+            open = skip_quoted(bs, open);
+            continue;
+        }
+        let mut depth: usize = 0;
+        let mut commas: Vec<usize> = Vec::new();
+        let mut i = open + 1;
+        while i < bs.len() {
+            match bs[i] {
+                b'{' => { depth += 1; }
+                b'}' if depth == 0 => break,
+                b'}' => { depth -= 1; }
+                b',' if depth == 0 => commas.push(i),
+                _ => {}
+            }
+            i = skip_quoted(bs, i);
+        }
+        if i < bs.len() {
+            if ! commas.is_empty() {
+                let mut starts = vec![open + 1];
+                starts.extend(commas.iter().map(|comma| comma + 1));
+                let mut ends = commas;
+                ends.push(i);
+                let parts = starts.into_iter().zip(ends).map(|(start, end)| (bs[start..end].to_vec(), Some(start))).collect();
+                return Some((open, i, parts));
+            }
+            if let Some(parts) = brace_sequence(&bs[open+1..i], MAX_BRACE_WORDS) {
+                return Some((open, i, parts.into_iter().map(|part| (part, None)).collect()));
+            }
+        }
+        open += 1;
+    }
+    return None;
+}
+
+/// Expands the inside of a `{1..5}`, `{a..e}`, or `{1..10..2}` sequence, or returns [None] if it isn't one
+/// or it has more than `limit` items
+fn brace_sequence(bs: &[u8], limit: usize) -> Option<Vec<Vec<u8>>> {
+    let text = std::str::from_utf8(bs).ok()?;
+    let parts: Vec<&str> = text.split("..").collect();
+    let step: i64 = match parts.len() {
+        2 => 1,
+        3 => parts[2].parse::<i64>().ok()?.checked_abs()?.max(1),
+        _ => { return None; }
+    };
+    let (first, last, letters) = match (parts[0].parse::<i64>(), parts[1].parse::<i64>()) {
+        (Ok(first), Ok(last)) => (first, last, false),
+        _ if parts[0].len() == 1 && parts[1].len() == 1
+            && parts[0].as_bytes()[0].is_ascii_alphabetic() && parts[1].as_bytes()[0].is_ascii_alphabetic() => {
+            (parts[0].as_bytes()[0] as i64, parts[1].as_bytes()[0] as i64, true)
+        }
+        _ => { return None; }
+    };
+    // count first, in a wider type so nothing overflows, rather than allocating a huge sequence
+    let count = (i128::from(last) - i128::from(first)).unsigned_abs() / step.unsigned_abs() as u128 + 1;
+    if count > limit as u128 {
+        return None;
+    }
+    let items = (0..count as i128).map(|k| {
+        let n = if first <= last { i128::from(first) + k * i128::from(step) } else { i128::from(first) - k * i128::from(step) };
+        return if letters { vec![n as u8] } else { n.to_string().into_bytes() };
+    });
+    return Some(items.collect());
+}
+
+/// Brace-expands a word, where `origins` has the offset in the line that each byte of it came from
+///
+/// Bytes that a sequence like `{1..5}` makes up come from its `{`. Returns [None] if there would be
+/// more than `limit` words.
+fn expand_braces(bs: Vec<u8>, origins: Vec<usize>, limit: usize) -> Option<Vec<(Vec<u8>, Vec<usize>)>> {
+    let (open, close, parts) = match find_braces(&bs) {
+        Some(found) => found,
+        None => { return Some(vec![(bs, origins)]); }
+    };
+    let mut r: Vec<(Vec<u8>, Vec<usize>)> = Vec::new();
+    for (part, start) in parts {
+        let part_origins: Vec<usize> = match start {
+            Some(start) => origins[start..start+part.len()].to_vec(),
+            None => vec![origins[open]; part.len()],
+        };
+        let word = [&bs[..open], &part, &bs[close+1..]].concat();
+        let word_origins = [&origins[..open], &part_origins, &origins[close+1..]].concat();
+        r.extend(expand_braces(word, word_origins, limit - r.len())?);
+        if r.len() > limit {
+            return None;
+        }
+    }
+    return Some(r);
+}
+
+/// Unquotes one word that came from brace expansion, where `origins` has the offset in the line
+/// that each byte of it came from
+fn lex_expanded_word(bs: &[u8], origins: &[usize], span: Range<usize>, options: &mut LexOptions<'_>) -> Result<Token, ParseError> {
+    let mut value: Vec<u8> = Vec::new();
+    let mut substitutions: Vec<Range<usize>> = Vec::new();
+    let mut glob = false;
+    match lex_word(bs, 0, &mut value, &mut substitutions, &mut glob, options) {
+        Ok(_) => {}
+        Err(ParseError::UnsetVariable { offset, name }) => {
+            return Err(ParseError::UnsetVariable { offset: origins[offset], name });
+        }
+        Err(ParseError::CommandSubstitution { offset }) => {
+            return Err(ParseError::CommandSubstitution { offset: origins[offset] });
+        }
+        Err(e) => { return Err(e); }
+    }
+    let substitutions = substitutions.into_iter().map(|range| origins[range.start]..origins[range.end-1]+1).collect();
+    return Ok(Token { kind: TokenKind::Word, value, span, substitutions, glob });
+}
+
+/// The word after a `<<`, which ends its here-document
+struct HereDocTag {
+    tag: Vec<u8>,
+    /// Any part of the tag was quoted, so the body is kept as it is
+    quoted: bool,
+    /// It was `<<-`, so tabs at the start of each line are removed
+    strip_tabs: bool,
+}
+
+/// Reads a here-document body that starts at `i`, and returns it along with where the next line starts
+///
+/// The body ends at a line that's just the tag. Like bash, if there isn't one, the body goes to the end.
+fn lex_heredoc(bs: &[u8], mut i: usize, tag: &HereDocTag, options: &mut LexOptions<'_>) -> Result<(Token, usize), ParseError> {
+    let start = i;
+    let mut value: Vec<u8> = Vec::new();
+    let mut substitutions: Vec<Range<usize>> = Vec::new();
+    while i < bs.len() {
+        let line_end = match bs[i..].iter().position(|&byte| byte == b'\n') {
+            Some(len) => i + len + 1,
+            None => bs.len(),
+        };
+        let mut content = i;
+        if tag.strip_tabs {
+            while content < line_end && bs[content] == b'\t' {
+                content += 1;
+            }
+        }
+        if bs[content..line_end].strip_suffix(b"\n").unwrap_or(&bs[content..line_end]) == tag.tag {
+            let token = Token { kind: TokenKind::HereDoc, value, span: start..i, substitutions, glob: false };
+            return Ok((token, line_end));
+        }
+        if tag.quoted {
+            value.extend_from_slice(&bs[content..line_end]);
+        } else {
+            lex_double_quoted(bs, content, line_end, false, &mut value, &mut substitutions, options)?;
+        }
+        i = line_end;
+    }
+    return Ok((Token { kind: TokenKind::HereDoc, value, span: start..bs.len(), substitutions, glob: false }, bs.len()));
+}
+
+/// Expands a `$NAME` or `${NAME}` at `i` into `value`, and returns where it ends,
+/// or [None] if there's nothing to expand there
+fn expand_variable(bs: &[u8], i: usize, value: &mut Vec<u8>, options: &mut LexOptions<'_>) -> Result<Option<usize>, ParseError> {
+    let resolve = match options.variables.as_mut() {
+        Some(resolve) => resolve,
+        None => { return Ok(None); }
+    };
+    let (name, end) = if bs.get(i+1) == Some(&b'{') {
+        match bs[i+2..].iter().position(|&byte| byte == b'}') {
+            Some(len) => (&bs[i+2..i+2+len], i+2+len+1),
+            None => { return Err(ParseError::missing_close(b'}')); }
+        }
+    } else {
+        let len = bs[i+1..].iter().take_while(|byte| byte.is_ascii_alphanumeric() || **byte == b'_').count();
+        if len == 0 || bs[i+1].is_ascii_digit() {
+            return Ok(None);
+        }
+        (&bs[i+1..i+1+len], i+1+len)
+    };
+    match resolve(name) {
+        Some(expanded) => value.extend_from_slice(&expanded),
+        None if options.unset == UnsetPolicy::Keep => value.extend_from_slice(&bs[i..end]),
+        None => { return Err(ParseError::UnsetVariable { offset: i, name: pretty_string(name) }); }
+    }
+    return Ok(Some(end));
+}
+
+/// Expands a `~` or `~user` prefix of the word at `i` into `value`, and returns where it ends,
+/// or [None] if there's nothing to expand there
+fn expand_tilde(bs: &[u8], i: usize, value: &mut Vec<u8>, options: &mut LexOptions<'_>) -> Option<usize> {
+    let resolve = options.tilde.as_mut()?;
+    if bs[i] != b'~' {
+        return None;
+    }
+    let mut end = i + 1;
+    while end < bs.len() && bs[end] != b'/' && ! is_blank(bs[end]) && operator_at(bs, end).is_none() {
+        if matches!(bs[end], b'\\' | b'\'' | b'"' | b'$' | b'`') {
+            return None;
+        }
+        end += 1;
+    }
+    let home = resolve(&bs[i+1..end])?;
+    value.extend_from_slice(&home);
+    return Some(end);
+}
+
+/// Finds the end of the `$(...)` or `` `...` `` at `i`, just past the closing `)` or `` ` ``
+fn find_substitution_end(bs: &[u8], i: usize) -> Result<usize, ParseError> {
+    if bs[i] == b'`' {
+        return match find_close(bs, i+1, b'`', true) {
+            Some(end) => Ok(end + 1),
+            None => Err(ParseError::missing_close(b'`')),
+        };
+    }
+    let mut depth: usize = 0;
+    let mut j = i + 1;
+    while j < bs.len() {
+        match bs[j] {
+            b'\\' => { j += 1; }
+            b'\'' | b'"' => match find_close(bs, j+1, bs[j], bs[j] == b'"') {
+                Some(end) => { j = end; }
+                None => { return Err(ParseError::missing_close(bs[j])); }
+            },
+            b'(' => { depth += 1; }
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(j + 1);
+                }
+            }
+            _ => {}
+        }
+        j += 1;
+    }
+    return Err(ParseError::missing_close(b')'));
+}
+
+/// Handles the command substitution at `i` according to [substitution](LexOptions::substitution),
+/// and returns where it ends
+fn substitute(
+    bs: &[u8],
+    i: usize,
+    value: &mut Vec<u8>,
+    substitutions: &mut Vec<Range<usize>>,
+    options: &mut LexOptions<'_>,
+) -> Result<usize, ParseError> {
+    let end = find_substitution_end(bs, i)?;
+    let command = if bs[i] == b'`' { &bs[i+1..end-1] } else { &bs[i+2..end-1] };
+    match &mut options.substitution {
+        SubstitutionPolicy::Keep => value.extend_from_slice(&bs[i..end]),
+        SubstitutionPolicy::Error => { return Err(ParseError::CommandSubstitution { offset: i }); }
+        SubstitutionPolicy::Run(run) => value.extend_from_slice(&run(command)),
+    }
+    substitutions.push(i..end);
+    return Ok(end);
+}
+
+/// Unquotes the inside of double quotes starting at `i` into `value`, and returns where the closing `"` is
+///
+/// Without `in_quotes`, this unquotes an unquoted here-document body up to `end` instead, where
+/// `"` isn't special and `\"` is kept as it is.
+fn lex_double_quoted(
+    bs: &[u8],
+    mut i: usize,
+    end: usize,
+    in_quotes: bool,
+    value: &mut Vec<u8>,
+    substitutions: &mut Vec<Range<usize>>,
+    options: &mut LexOptions<'_>,
+) -> Result<usize, ParseError> {
+    while i < end {
+        match bs[i] {
+            b'"' if in_quotes => { return Ok(i); }
+            b'\\' => match bs[..end].get(i+1) {
+                Some(b'\n') => { i += 2; }
+                Some(&byte @ (b'$' | b'`' | b'\\')) => {
+                    value.push(byte);
+                    i += 2;
+                }
+                Some(b'"') if in_quotes => {
+                    value.push(b'"');
+                    i += 2;
+                }
+                _ => {
+                    value.push(b'\\');
+                    i += 1;
+                }
+            },
+            b'`' => { i = substitute(bs, i, value, substitutions, options)?; }
+            b'$' if bs.get(i+1) == Some(&b'(') => { i = substitute(bs, i, value, substitutions, options)?; }
+            b'$' => match expand_variable(bs, i, value, options)? {
+                Some(end) => { i = end; }
+                None => {
+                    value.push(b'$');
+                    i += 1;
+                }
+            },
+            byte => {
+                value.push(byte);
+                i += 1;
+            }
+        }
+    }
+    if in_quotes {
+        return Err(ParseError::missing_close(b'"'));
+    }
+    return Ok(i);
+}
+
+fn is_glob(byte: &u8) -> bool {
+    return matches!(byte, b'*' | b'?' | b'[');
+}
+
+/// Unquotes one word starting at `i` into `value`, and returns where the word ends
+///
+/// `glob` is set if the word has any unquoted glob metacharacters.
+fn lex_word(
+    bs: &[u8],
+    mut i: usize,
+    value: &mut Vec<u8>,
+    substitutions: &mut Vec<Range<usize>>,
+    glob: &mut bool,
+    options: &mut LexOptions<'_>,
+) -> Result<usize, ParseError> {
+    if let Some(end) = expand_tilde(bs, i, value, options) {
+        i = end;
+    }
+    while i < bs.len() && ! is_blank(bs[i]) && operator_at(bs, i).is_none() {
+        let unquoted_at = i;
+        let unquoted_from = value.len();
+        match bs[i] {
+            b'\\' => {
+                match bs.get(i+1) {
+                    Some(b'\n') => {}
+                    Some(&byte) => value.push(byte),
+                    None => value.push(b'\\'),
+                }
+                i += 2;
+            }
+            b'\'' => {
+                let end = match find_close(bs, i+1, b'\'', false) {
+                    Some(end) => end,
+                    None => { return Err(ParseError::missing_close(b'\'')); }
+                };
+                value.extend_from_slice(&bs[i+1..end]);
+                i = end + 1;
+            }
+            b'$' if bs.get(i+1) == Some(&b'\'') => {
+                let dialect = Dialect { base_offset: i+2, ..Dialect::default() };
+                i += 2 + unescape_slice_with(&bs[i+2..], value, Some(b'\''), &dialect).map_err(UnescapeError::into_parse_error)?;
+            }
+            b'"' => {
+                i = lex_double_quoted(bs, i+1, bs.len(), true, value, substitutions, options)? + 1;
+            }
+            b'`' => { i = substitute(bs, i, value, substitutions, options)?; }
+            b'$' if bs.get(i+1) == Some(&b'(') => { i = substitute(bs, i, value, substitutions, options)?; }
+            b'$' => match expand_variable(bs, i, value, options)? {
+                Some(end) => { i = end; }
+                None => {
+                    value.push(b'$');
+                    i += 1;
+                }
+            },
+            byte => {
+                value.push(byte);
+                i += 1;
+            }
+        }
+        if ! matches!(bs[unquoted_at], b'\\' | b'\'' | b'"') && ! bs[unquoted_at..].starts_with(b"$'") {
+            *glob |= value[unquoted_from..].iter().any(is_glob);
+        }
+    }
+    return Ok(i.min(bs.len()));
+}
+
+/// The default value of `IFS` in POSIX shells: space, tab, and newline
+pub const DEFAULT_IFS: &[u8] = b" \t\n";
+
+/// Splits already unquoted bytes into fields, the way POSIX shells split expansions using `IFS`
+///
+/// Spaces, tabs, and newlines in `ifs` are whitespace: runs of them separate fields, and they're
+/// ignored at the start and end. Any other byte in `ifs` separates fields on its own, together with any
+/// whitespace around it, so two of them in a row make an empty field, like `a::b` with `ifs` of `:`.
+/// One at the end doesn't make an empty field, though. If `ifs` is empty, nothing is split.
+pub fn split_fields<'a>(bytes: &'a [u8], ifs: &[u8]) -> Vec<&'a [u8]> {
+    let is_ifs_blank = |byte: &u8| ifs.contains(byte) && is_blank(*byte);
+    let mut r: Vec<&[u8]> = Vec::new();
+    if ifs.is_empty() {
+        if ! bytes.is_empty() {
+            r.push(bytes);
+        }
+        return r;
+    }
+    let mut i: usize = 0;
+    while i < bytes.len() && is_ifs_blank(&bytes[i]) {
+        i += 1;
+    }
+    while i < bytes.len() {
+        let start = i;
+        while i < bytes.len() && ! ifs.contains(&bytes[i]) {
+            i += 1;
+        }
+        r.push(&bytes[start..i]);
+        while i < bytes.len() && is_ifs_blank(&bytes[i]) {
+            i += 1;
+        }
+        if i < bytes.len() && ifs.contains(&bytes[i]) {
+            i += 1;
+            while i < bytes.len() && is_ifs_blank(&bytes[i]) {
+                i += 1;
+            }
+        }
+    }
+    return r;
+}
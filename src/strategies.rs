@@ -0,0 +1,116 @@
+//! [proptest] strategies that generate raw bytes together with their escaped form
+//!
+//! Each escaped form unescapes back to its raw bytes in the [Dialect] it was generated for,
+//! so they're useful for round-trip tests of code that uses smashquote.
+
+use proptest::prelude::*;
+
+use crate::{BomPolicy, Dialect, LineContinuation, NulPolicy, OctalOverflow, OutputEncoding, SurrogatePolicy};
+use crate::escape::{spell_byte, SPELLINGS};
+
+/// Generates any [Dialect]
+pub fn dialect() -> impl Strategy<Value = Dialect> {
+    let octal_overflow = prop_oneof![
+        Just(OctalOverflow::Error),
+        Just(OctalOverflow::Wrap),
+        Just(OctalOverflow::TwoDigits),
+    ];
+    let line_continuation = prop_oneof![
+        Just(LineContinuation::Off),
+        Just(LineContinuation::Newline),
+        Just(LineContinuation::NewlineOrCrlf),
+    ];
+    let bom = prop_oneof![
+        Just(BomPolicy::Keep),
+        Just(BomPolicy::Strip),
+        Just(BomPolicy::StripAndRejectUtf16),
+    ];
+    let nul = prop_oneof![
+        Just(NulPolicy::Allow),
+        Just(NulPolicy::Reject),
+        Just(NulPolicy::Strip),
+    ];
+    let surrogates = prop_oneof![
+        Just(SurrogatePolicy::Error),
+        Just(SurrogatePolicy::Wtf8),
+        Just(SurrogatePolicy::Replace),
+    ];
+    // UTF-16 output isn't generated, because then the output isn't the raw bytes anymore
+    let encoding = prop_oneof![
+        Just(OutputEncoding::Utf8),
+        Just(OutputEncoding::Cesu8),
+    ];
+    let max_output_len = prop_oneof![Just(None), (0..=64usize).prop_map(Some)];
+    // digit limits are kept big enough that every byte can be spelled with a fixed number of digits
+    let digits = (2..=8usize, 3..=10usize, 2..=8usize, 2..=8usize);
+    let flags = (any::<bool>(), any::<bool>(), any::<bool>(), any::<bool>(), any::<bool>());
+    return (octal_overflow, line_continuation, bom, nul, max_output_len, surrogates, encoding, digits, flags).prop_map(|(
+        octal_overflow,
+        line_continuation,
+        bom,
+        nul,
+        max_output_len,
+        surrogates,
+        encoding,
+        (max_hex_digits, max_octal_digits, max_u_digits, max_big_u_digits),
+        (caret_notation, fixed_width_unicode, control_delete, control_double_backslash, space_escape),
+    )| Dialect {
+        caret_notation,
+        octal_overflow,
+        max_hex_digits,
+        max_octal_digits,
+        max_u_digits,
+        max_big_u_digits,
+        fixed_width_unicode,
+        control_delete,
+        control_double_backslash,
+        space_escape,
+        line_continuation,
+        bom,
+        nul,
+        max_output_len,
+        surrogates,
+        encoding,
+        // the generated escapes are never delimited
+        close_ignore_case: false,
+        close_bytes: Vec::new(),
+        open_delimiter: None,
+        // the padded spellings aren't canonical
+        canonical: false,
+        // literal spellings can be any byte
+        reject_raw_controls: false,
+        allowed_raw_controls: Vec::new(),
+        ascii_source: false,
+        base_offset: 0,
+    });
+}
+
+/// Generates `(raw, escaped)` pairs, where `escaped` unescapes to `raw` in `dialect`
+///
+/// Each byte of `raw` is spelled in a randomly chosen way, like a literal byte, `\xNN`, octal,
+/// `\cX`, or `\M-x`. `raw` never contains NUL bytes unless `dialect` allows them,
+/// and is never longer than its [max_output_len](Dialect::max_output_len).
+pub fn escaped_pair(dialect: Dialect) -> impl Strategy<Value = (Vec<u8>, Vec<u8>)> {
+    return proptest::collection::vec((any::<u8>(), 0..SPELLINGS), 0..64).prop_map(move |spelled| {
+        let mut raw: Vec<u8> = Vec::with_capacity(spelled.len());
+        let mut escaped: Vec<u8> = Vec::with_capacity(spelled.len() * 4);
+        for (byte, spelling) in spelled {
+            if byte == 0 && dialect.nul != NulPolicy::Allow {
+                continue;
+            }
+            if Some(raw.len()) == dialect.max_output_len {
+                break;
+            }
+            raw.push(byte);
+            spell_byte(&mut escaped, byte, spelling, &dialect);
+        }
+        (raw, escaped)
+    });
+}
+
+/// Generates `(dialect, raw, escaped)`, where `escaped` unescapes to `raw` in `dialect`
+pub fn any_escaped_pair() -> impl Strategy<Value = (Dialect, Vec<u8>, Vec<u8>)> {
+    return dialect().prop_flat_map(|dialect| {
+        escaped_pair(dialect.clone()).prop_map(move |(raw, escaped)| (dialect.clone(), raw, escaped))
+    });
+}
@@ -0,0 +1,279 @@
+//! Unescaping input that arrives a piece at a time
+
+use std::io::Write;
+use crate::{
+    control_key, unescape_bytes_into_with, BomPolicy, Dialect, LineContinuation, NulPolicy, OctalOverflow,
+    OutputEncoding, ParseError, UnescapeError,
+};
+
+/// Unescapes input that arrives in pieces, like from a socket, keeping track of where it's up to
+///
+/// Each call to [decode](StreamDecoder::decode) unescapes as much as it safely can, and keeps any escape
+/// that might not be finished yet, like `\x4` at the end of a piece, until the next piece or
+/// [finish](StreamDecoder::finish). The output is the same as unescaping all the input at once, and
+/// offsets in errors count from the start of the whole input. Legacy encodings that have state, like
+/// ISO-2022-JP, are reset wherever the input is split, though.
+///
+/// There's no close delimiter; all of the input is unescaped.
+#[derive(Debug, Clone)]
+pub struct StreamDecoder {
+    dialect: Dialect,
+    pending: Vec<u8>,
+    scratch: Vec<u8>,
+    consumed: usize,
+    produced: usize,
+}
+
+impl StreamDecoder {
+    /// Makes a decoder for input in `dialect`
+    pub fn new(dialect: Dialect) -> Self {
+        return Self {
+            dialect,
+            pending: Vec::new(),
+            scratch: Vec::new(),
+            consumed: 0,
+            produced: 0,
+        };
+    }
+
+    /// How many input bytes have been unescaped so far, not counting any that are still waiting for
+    /// the rest of their escape
+    pub fn consumed(&self) -> usize {
+        return self.consumed;
+    }
+
+    /// How many output bytes have been written so far
+    pub fn produced(&self) -> usize {
+        return self.produced;
+    }
+
+    /// The input bytes that are waiting for the next piece, because they might be the start of a longer escape
+    pub fn pending(&self) -> &[u8] {
+        return &self.pending;
+    }
+
+    /// Unescapes the next piece of input, writing as much output as it can to `out`
+    pub fn decode<O: Write>(&mut self, piece: &[u8], out: &mut O) -> Result<(), UnescapeError> {
+        self.pending.extend_from_slice(piece);
+        let cut = last_safe_cut(&self.pending, &self.dialect, self.consumed == 0);
+        if cut == 0 {
+            return Ok(());
+        }
+        return self.decode_pending(cut, out);
+    }
+
+    /// Unescapes whatever input is left, at the end of the input
+    pub fn finish<O: Write>(&mut self, out: &mut O) -> Result<(), UnescapeError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        return self.decode_pending(self.pending.len(), out);
+    }
+
+    fn decode_pending<O: Write>(&mut self, cut: usize, out: &mut O) -> Result<(), UnescapeError> {
+        let dialect = Dialect {
+            base_offset: self.dialect.base_offset + self.consumed,
+            max_output_len: self.dialect.max_output_len.map(|max_len| max_len - self.produced),
+            // only the very start can have a byte order mark
+            bom: if self.consumed == 0 { self.dialect.bom } else { BomPolicy::Keep },
+            ..self.dialect.clone()
+        };
+        match unescape_bytes_into_with(&self.pending[..cut], &mut self.scratch, &dialect) {
+            Ok(()) => {}
+            Err(ParseError::OutputTooLong { offset, .. }) => {
+                let max_len = self.dialect.max_output_len.expect("Only a limit can be reached.");
+                return Err(ParseError::OutputTooLong { offset, max_len }.into());
+            }
+            Err(e) => { return Err(e.into()); }
+        }
+        out.write_all(&self.scratch)?;
+        self.produced += self.scratch.len();
+        self.consumed += cut;
+        self.pending.drain(..cut);
+        return Ok(());
+    }
+}
+
+/// Finds the longest start of `bs` that unescapes the same on its own as it would with more bytes after it
+fn last_safe_cut(bs: &[u8], dialect: &Dialect, at_start: bool) -> usize {
+    // UTF-16 output is transcoded from UTF-8, so it can't stop in the middle of a character either,
+    // even one that's written as byte escapes like `\xC3\xA9`
+    let utf16 = matches!(dialect.encoding, OutputEncoding::Utf16Le | OutputEncoding::Utf16Be);
+    let bytes_dialect = Dialect {
+        encoding: OutputEncoding::Utf8,
+        bom: BomPolicy::Keep,
+        nul: NulPolicy::Allow,
+        max_output_len: None,
+        ..dialect.clone()
+    };
+    let mut token_out: Vec<u8> = Vec::new();
+    // how many more bytes the last UTF-8 character in the output needs
+    let mut unfinished: usize = 0;
+    let mut cut: usize = 0;
+    let mut i: usize = 0;
+    while let Some((len, safe)) = token_len(bs, i, dialect) {
+        if utf16 {
+            let token = &bs[i..i + len];
+            let out: &[u8] = if token[0] == b'\\' || token[0] == b'^' {
+                token_out.clear();
+                match unescape_bytes_into_with(token, &mut token_out, &bytes_dialect) {
+                    Ok(()) => &token_out,
+                    Err(_) => &[],
+                }
+            } else {
+                token
+            };
+            for &byte in out {
+                unfinished = match byte {
+                    0xC0..=0xDF => 1,
+                    0xE0..=0xEF => 2,
+                    0xF0..=0xF7 => 3,
+                    0x80..=0xBF => unfinished.saturating_sub(1),
+                    _ => 0,
+                };
+            }
+        }
+        i += len;
+        // the next byte shouldn't continue a UTF-8 character, and a byte order mark has to be seen whole
+        let boundary = matches!(bs.get(i), Some(&byte) if ! (0x80..=0xBF).contains(&byte));
+        if safe && boundary && unfinished == 0 && (! at_start || dialect.bom == BomPolicy::Keep || i >= 3) {
+            cut = i;
+        }
+    }
+    return cut;
+}
+
+/// Counts up to `max` digits from `start`, or returns [None] if `bs` ends before it's clear where they stop
+fn digits_len(bs: &[u8], start: usize, max: usize, is_digit: fn(&u8) -> bool) -> Option<usize> {
+    let mut n: usize = 0;
+    while n < max {
+        match bs.get(start + n) {
+            Some(byte) if is_digit(byte) => n += 1,
+            Some(_) => { return Some(n); }
+            None => { return None; }
+        }
+    }
+    return Some(n);
+}
+
+/// Returns how long the token starting at `i` is, like an escape or a literal byte, and whether it's safe to stop
+/// right after it, or [None] if `bs` ends before it's clear where the token ends
+pub(crate) fn token_len(bs: &[u8], i: usize, dialect: &Dialect) -> Option<(usize, bool)> {
+    let is_octal: fn(&u8) -> bool = |b| (b'0'..=b'7').contains(b);
+    let braced = |start: usize| bs[start..].iter().position(|&b| b == b'}').map(|n| start + n + 1 - i);
+    let byte = *bs.get(i)?;
+    if dialect.caret_notation && byte == b'^' {
+        return Some((if control_key(*bs.get(i + 1)?).is_some() { 2 } else { 1 }, true));
+    } else if byte != b'\\' {
+        return Some((1, true));
+    }
+    let byte2 = *bs.get(i + 1)?;
+    let len: usize = match byte2 {
+        b'\r' if dialect.line_continuation == LineContinuation::NewlineOrCrlf => 2 + usize::from(*bs.get(i + 2)? == b'\n'),
+        b'0'..=b'9' => {
+            let max_digits: usize = if dialect.octal_overflow == OctalOverflow::TwoDigits && byte2 > b'3' {
+                dialect.max_octal_digits.min(2)
+            } else {
+                dialect.max_octal_digits
+            };
+            2 + digits_len(bs, i + 2, max_digits.saturating_sub(1), is_octal)?
+        }
+        b'o' => if *bs.get(i + 2)? == b'{' { braced(i + 3)? } else { 3 },
+        b'x' => if *bs.get(i + 2)? == b'{' { braced(i + 3)? } else { 2 + digits_len(bs, i + 2, dialect.max_hex_digits, u8::is_ascii_hexdigit)? },
+        // a high surrogate waits to be paired with the next escape, so don't stop after these
+        #[cfg(feature = "unicode")]
+        b'u' | b'U' => {
+            let max_digits = if byte2 == b'u' { dialect.max_u_digits } else { dialect.max_big_u_digits };
+            let len = match *bs.get(i + 2)? {
+                b'{' if byte2 == b'u' => braced(i + 3)?,
+                byte3 if byte3.is_ascii_hexdigit() => 3 + digits_len(bs, i + 3, max_digits.saturating_sub(1), u8::is_ascii_hexdigit)?,
+                _ => 3,
+            };
+            return Some((len, false));
+        }
+        b'c' => {
+            if *bs.get(i + 2)? == b'\\' && dialect.control_double_backslash && *bs.get(i + 3)? == b'\\' { 4 } else { 3 }
+        }
+        b'M' => {
+            if *bs.get(i + 2)? != b'-' {
+                3
+            } else if *bs.get(i + 3)? != b'^' {
+                4
+            } else if matches!(*bs.get(i + 4)?, b'?' | b'@'..=b'_') {
+                5
+            } else {
+                4
+            }
+        }
+        _ => 2,
+    };
+    return Some((len, true));
+}
+
+/// An iterator of unescaped chunks, from an iterator of escaped pieces, like the body of an HTTP request
+///
+/// Escapes can be split between pieces, and are put back together with a [StreamDecoder]. Each item is
+/// the output that was ready after reading one or more pieces, so it's never empty. After an error,
+/// there are no more items.
+pub struct ChunkDecoder<I> {
+    pieces: I,
+    decoder: StreamDecoder,
+    done: bool,
+}
+
+impl<I> ChunkDecoder<I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    /// Unescapes `pieces` in `dialect`
+    pub fn new(pieces: I, dialect: Dialect) -> Self {
+        return Self {
+            pieces,
+            decoder: StreamDecoder::new(dialect),
+            done: false,
+        };
+    }
+
+    /// The [StreamDecoder] underneath, for its [consumed](StreamDecoder::consumed) and
+    /// [produced](StreamDecoder::produced) positions
+    pub fn decoder(&self) -> &StreamDecoder {
+        return &self.decoder;
+    }
+}
+
+impl<I> Iterator for ChunkDecoder<I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Item = Result<Vec<u8>, UnescapeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut out: Vec<u8> = Vec::new();
+        while ! self.done && out.is_empty() {
+            let r = match self.pieces.next() {
+                Some(piece) => self.decoder.decode(piece.as_ref(), &mut out),
+                None => {
+                    self.done = true;
+                    self.decoder.finish(&mut out)
+                }
+            };
+            if let Err(e) = r {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+        if out.is_empty() {
+            return None;
+        }
+        return Some(Ok(out));
+    }
+}
+
+impl<I> std::iter::FusedIterator for ChunkDecoder<I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+}
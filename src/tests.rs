@@ -93,6 +93,7 @@ fn hex0() {
         assert_eq!(r, [i]);
     }
 }
+#[cfg(feature = "unicode")]
 #[test]
 fn unicode4() {
     for i in 0u32..=0xFFFF {
@@ -112,6 +113,7 @@ fn unicode4() {
         }
     }
 }
+#[cfg(feature = "unicode")]
 #[test]
 fn unicode04() {
     for i in 0u32..=0xFFFF {
@@ -131,6 +133,7 @@ fn unicode04() {
         }
     }
 }
+#[cfg(feature = "unicode")]
 #[test]
 fn unicode_rust_style() {
     for i in 0u32..=0x10FFFF {
@@ -150,6 +153,7 @@ fn unicode_rust_style() {
         }
     }
 }
+#[cfg(feature = "unicode")]
 #[test]
 fn unicode8() {
     for i in 0u32..=0x10FFFF {
@@ -169,6 +173,7 @@ fn unicode8() {
         }
     }
 }
+#[cfg(feature = "unicode")]
 #[test]
 fn unicode08() {
     for i in 0u32..=0x10FFFF {
@@ -201,10 +206,1424 @@ fn control_x() {
 }
 #[test]
 fn anyhow_compatible() {
-    let _unescape_error = anyhow::Error::new::<UnescapeError>(UnescapeError::InvalidBackslash {
+    let _unescape_error = anyhow::Error::new::<UnescapeError>(UnescapeError::Parse(ParseError::InvalidBackslash {
         kind: InvalidBackslashKind::RustStyleUnicodeMissingCloseBrace,
         string: String::new(),
         bytes: String::new(),
         offset: 0,
-    });
+        len: 0,
+        suggestion: None,
+    }));
+    let _parse_error = anyhow::Error::new::<ParseError>(ParseError::missing_close(b'\''));
+}
+#[test]
+fn trailing_backslash() {
+    let e = unescape_bytes(b"ab\\").unwrap_err();
+    assert!(matches!(e, ParseError::InvalidBackslash { kind: InvalidBackslashKind::BackslashEndOfString, offset: 2, .. }));
+    assert!(unescape_bytes(b"ab\\\\\\").is_err());
+    assert_eq!(unescape_bytes(b"ab\\\\").unwrap(), b"ab\\");
+}
+#[test]
+fn meta_x() {
+    for x in 0u8..=0x7F {
+        let mut b = Vec::with_capacity(10);
+        b.extend(b"\\M-");
+        b.push(x);
+        let r = unescape_bytes(&b).unwrap();
+        assert_eq!(r, &[x | 0x80]);
+    }
+}
+#[test]
+fn meta_control_x() {
+    for x in b'@'..=b'_' {
+        let mut b = Vec::with_capacity(10);
+        b.extend(b"\\M-^");
+        b.push(x);
+        let r = unescape_bytes(&b).unwrap();
+        assert_eq!(r, &[(x & 0x1F) | 0x80]);
+    }
+    let r = unescape_bytes(b"\\M-^?").unwrap();
+    assert_eq!(r, [0xFF]);
+    let r = unescape_bytes(b"\\M-^a").unwrap();
+    assert_eq!(r, [0xDE, b'a']);
+    assert!(unescape_bytes(b"\\Mx").is_err());
+    assert!(unescape_bytes(b"\\M-").is_err());
+}
+#[test]
+fn caret_notation() {
+    let dialect = Dialect { caret_notation: true, ..Dialect::default() };
+    for x in b'@'..=b'_' {
+        let b = [b'^', x];
+        let r = unescape_bytes_with(&b, &dialect).unwrap();
+        assert_eq!(r, &[x & 0x1F]);
+    }
+    let r = unescape_bytes_with(b"^M^J^?", &dialect).unwrap();
+    assert_eq!(r, [0x0D, 0x0A, 0x7F]);
+    let r = unescape_bytes_with(b"2^3^", &dialect).unwrap();
+    assert_eq!(r, b"2^3^");
+    let r = unescape_bytes(b"^M").unwrap();
+    assert_eq!(r, b"^M");
+}
+#[test]
+fn unpretty_round_trip() {
+    let b: Vec<u8> = (0u8..=0x7F).collect();
+    let r = unpretty_string(&pretty_string(&b));
+    assert_eq!(r, b);
+    let r = unpretty_string("a\u{2409}b\u{2421}\u{00E9}");
+    assert_eq!(r, b"a\tb\x7F\xC3\xA9");
+}
+#[test]
+fn pretty_bytes_options() {
+    let b = [0x0A, 0xBC, 0xDE, 0xF0, 0x12];
+    assert_eq!(pretty_bytes(&b), "0A BC DE F0 12");
+    let options = PrettyBytesOptions {
+        case: HexCase::Lower,
+        prefix: String::from("0x"),
+        group: 2,
+        separator: String::from(", "),
+        ..PrettyBytesOptions::default()
+    };
+    assert_eq!(pretty_bytes_with(&b, &options), "0x0abc, 0xdef0, 0x12");
+    let options = PrettyBytesOptions {
+        max_len: Some(3),
+        ..PrettyBytesOptions::default()
+    };
+    assert_eq!(pretty_bytes_with(&b, &options), "0A BC DE...");
+}
+#[test]
+fn hexdump_rows() {
+    let r = hexdump(b"Hello, world!\r\n\x00\x01\xFF", false);
+    assert_eq!(r, "00000000: 4865 6c6c 6f2c 2077 6f72 6c64 210d 0a00  Hello, world!...\n\
+                   00000010: 01ff                                     ..\n");
+    let r = hexdump(b"a\tb", true);
+    assert_eq!(r, "00000000: 6109 62                                  a\u{2409}b\n");
+}
+#[test]
+fn pretty_string_options() {
+    let b = b"a b\r\n\x7F\xFF\xFEz";
+    assert_eq!(pretty_string(b), "a\u{2420}b\u{240D}\u{240A}\u{247F}\u{FFFD}\u{FFFD}z");
+    let options = PrettyStringOptions {
+        control: ControlStyle::Caret,
+        replacement: String::from("?"),
+        ..PrettyStringOptions::default()
+    };
+    assert_eq!(pretty_string_with(b, &options), "a b^M^J^???z");
+    let options = PrettyStringOptions {
+        control: ControlStyle::Hex,
+        max_width: Some(9),
+        ..PrettyStringOptions::default()
+    };
+    assert_eq!(pretty_string_with(b, &options), "a b\\x0...");
+    let options = PrettyStringOptions { max_width: Some(2), ..PrettyStringOptions::default() };
+    assert_eq!(pretty_string_with(b"abcdef", &options), "..");
+    let options = PrettyStringOptions { max_width: Some(0), ..PrettyStringOptions::default() };
+    assert_eq!(pretty_string_with(b"abcdef", &options), "");
+}
+#[test]
+fn pretty_string_reversible() {
+    let options = PrettyStringOptions {
+        control: ControlStyle::Reversible,
+        ..PrettyStringOptions::default()
+    };
+    let b = b"a\\b\r\n\x7F\xFF\xFE\xC3\xA9\x00z";
+    assert_eq!(pretty_string_with(b, &options), "a\\\\b\\x0D\\x0A\\x7F\\xFF\\xFE\u{e9}\\x00z");
+    let all: Vec<u8> = (0u8..=0xFF).collect();
+    assert_eq!(unescape_bytes(pretty_string_with(&all, &options).as_bytes()).unwrap(), all);
+}
+#[test]
+fn ansi_stripping() {
+    let b = b"\x1B[1;31mred\x1B[0m \x1B]0;title\x07ok\x1B]2;x\x1B\\!\x1B(B";
+    assert_eq!(strip_ansi(b), b"red ok!");
+    let options = PrettyStringOptions {
+        strip_ansi: true,
+        ..PrettyStringOptions::default()
+    };
+    assert_eq!(pretty_string_with(b"\x1B[32mgreen\x1B[m", &options), "green");
+    assert_eq!(pretty_string(b"\x1B[m"), "\u{241B}[m");
+}
+#[cfg(feature = "width")]
+#[test]
+fn pretty_string_columns() {
+    let options = PrettyStringOptions {
+        max_columns: Some(6),
+        ellipsis: String::from("\u{2026}"),
+        ..PrettyStringOptions::default()
+    };
+    // wide characters take two columns, and combining accents take none
+    assert_eq!(pretty_string_with("日本語です".as_bytes(), &options), "日本\u{2026}");
+    assert_eq!(pretty_string_with("e\u{301}e\u{301}e\u{301}e\u{301}e\u{301}e\u{301}x".as_bytes(), &options), "e\u{301}e\u{301}e\u{301}e\u{301}e\u{301}\u{2026}");
+    assert_eq!(pretty_string_with("e\u{301}e\u{301}".as_bytes(), &options), "e\u{301}e\u{301}");
+    let options = PrettyStringOptions { max_columns: Some(2), ..PrettyStringOptions::default() };
+    assert_eq!(pretty_string_with(b"abcdef", &options), "..");
+}
+#[test]
+fn escaped_display_round_trip() {
+    let b: Vec<u8> = (0u8..=0xFF).collect();
+    let s = format!("{}", EscapedDisplay(&b));
+    assert_eq!(unescape_bytes(s.as_bytes()).unwrap(), b);
+    assert_eq!(format!("{}", EscapedDisplay(b"a\"b\\c\r\n\xC3\xA9")), "a\"b\\\\c\\r\\né");
+    assert_eq!(format!("{:?}", EscapedDisplay(b"a\"b")), "\"a\\\"b\"");
+}
+#[test]
+fn escape_byte_forms() {
+    assert_eq!(escape_byte(b'a'), EscapeForm::Literal(b'a'));
+    assert_eq!(escape_byte(b'\n'), EscapeForm::Named(b'n'));
+    assert_eq!(escape_byte(b'\\'), EscapeForm::Named(b'\\'));
+    assert_eq!(escape_byte(0x7F), EscapeForm::Hex(0x7F));
+    assert_eq!(escape_byte(0xC3), EscapeForm::Hex(0xC3));
+    for byte in 0u8..=0xFF {
+        let s = escape_byte(byte).to_string();
+        assert_eq!(unescape_bytes(s.as_bytes()).unwrap(), [byte]);
+    }
+}
+#[test]
+fn shell_quoting() {
+    assert_eq!(shell_quote(b"plain-word_1.txt"), "plain-word_1.txt");
+    assert_eq!(shell_quote(b""), "''");
+    assert_eq!(shell_quote(b"two words $HOME"), "'two words $HOME'");
+    assert_eq!(shell_quote(b"it's"), "\"it's\"");
+    assert_eq!(shell_quote(b"it's $HOME"), "$'it\\'s $HOME'");
+    assert_eq!(shell_quote(b"a\r\n\xFF"), "$'a\\r\\n\\xFF'");
+    let b: Vec<u8> = (0u8..=0xFF).collect();
+    let s = shell_quote(&b);
+    assert_eq!(unescape_bytes(&s.as_bytes()[2..s.len() - 1]).unwrap(), b);
+}
+#[test]
+fn rust_byte_literal() {
+    assert_eq!(to_rust_byte_literal(b"a\"b\\c\r\n\t\0\x07\xFF'"), "b\"a\\\"b\\\\c\\r\\n\\t\\0\\x07\\xFF'\"");
+    assert_eq!(to_rust_byte_literal(b""), "b\"\"");
+}
+#[test]
+fn json_string() {
+    let r = to_json_string(b"a\"b\\c\r\n\x01\xC3\xA9", NonUtf8Policy::Error).unwrap();
+    assert_eq!(r, "\"a\\\"b\\\\c\\r\\n\\u0001\u{E9}\"");
+    assert!(to_json_string(b"\xFF", NonUtf8Policy::Error).is_err());
+    assert_eq!(to_json_string(b"a\xFFb", NonUtf8Policy::Lossy).unwrap(), "\"a\u{FFFD}b\"");
+    assert_eq!(to_json_string(b"a\xFFb", NonUtf8Policy::EscapedReplacement).unwrap(), "\"a\\uFFFDb\"");
+}
+#[test]
+fn c_literal() {
+    assert_eq!(to_c_literal(b"a\"b\\c\r\n\x07"), "\"a\\\"b\\\\c\\r\\n\\a\"");
+    assert_eq!(to_c_literal(b"\x01b\x01g\xFF"), "\"\\x01\" \"b\\x01g\\xFF\"");
+    assert_eq!(to_c_literal(b"??=???"), "\"?\\?=?\\?\\?\"");
+}
+#[test]
+fn printf_q_like_bash() {
+    assert_eq!(printf_q(b""), "''");
+    assert_eq!(printf_q(b"plain"), "plain");
+    assert_eq!(printf_q(b"it's a $HOME (x)"), "it\\'s\\ a\\ \\$HOME\\ \\(x\\)");
+    assert_eq!(printf_q(b"~a a~ #a a#"), "\\~a\\ a~\\ #a\\ a#");
+    assert_eq!(printf_q(b"a=~:~"), "a=\\~:\\~");
+    assert_eq!(printf_q(b"a'b\n"), "$'a\\'b\\n'");
+    assert_eq!(printf_q(b"\xFF\x01\x1B\x7F"), "$'\\377\\001\\E\\177'");
+}
+#[cfg(feature = "arbitrary")]
+#[test]
+fn arbitrary_escaped_input() {
+    use arbitrary::{Arbitrary, Unstructured};
+    let seed: Vec<u8> = (0..4096u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+    let mut u = Unstructured::new(&seed);
+    while ! u.is_empty() {
+        let input = EscapedInput::arbitrary(&mut u).unwrap();
+        assert_eq!(unescape_bytes_with(&input.escaped, &input.dialect).unwrap(), input.raw);
+    }
+}
+#[test]
+fn empty() {
+    let r = unescape_bytes(b"").unwrap();
+    assert_eq!(r, b"");
+}
+#[cfg(feature = "proptest")]
+proptest::proptest! {
+    #[test]
+    fn proptest_round_trip((dialect, raw, escaped) in strategies::any_escaped_pair()) {
+        proptest::prop_assert_eq!(unescape_bytes_with(&escaped, &dialect).unwrap(), raw);
+    }
+    #[test]
+    fn proptest_len_bound((dialect, raw, escaped) in strategies::any_escaped_pair()) {
+        proptest::prop_assert!(unescaped_len_bound(&escaped, &dialect) >= raw.len());
+    }
+}
+#[test]
+fn octal_overflow() {
+    let r = unescape_bytes(b"\\777");
+    assert!(matches!(r, Err(ParseError::InvalidBackslash { kind: InvalidBackslashKind::OctalEscapeOverflow(0o777), .. })));
+    let dialect = Dialect { octal_overflow: OctalOverflow::Wrap, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\\400\\777", &dialect).unwrap(), [0x00, 0xFF]);
+    let dialect = Dialect { octal_overflow: OctalOverflow::TwoDigits, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\\777\\377", &dialect).unwrap(), [0o77, b'7', 0xFF]);
+    assert_eq!(unescape_bytes(b"\\1a\\18").unwrap(), [1, b'a', 1, b'8']);
+}
+#[cfg(feature = "unicode")]
+#[test]
+fn digit_limits() {
+    assert_eq!(unescape_bytes(b"\\x414\\x4g").unwrap(), b"A4\x04g");
+    let dialect = Dialect { max_hex_digits: 4, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\\x00414", &dialect).unwrap(), b"A4");
+    assert!(unescape_bytes_with(b"\\x0100", &dialect).is_err());
+    let dialect = Dialect { max_octal_digits: 2, max_u_digits: 2, max_big_u_digits: 6, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\\101\\u41A\\U01F600", &dialect).unwrap(), "\u{8}1AA\u{1F600}".as_bytes());
+    assert_eq!(unescape_bytes(b"\\u41g\\U1F600!").unwrap(), "Ag\u{1F600}!".as_bytes());
+}
+#[cfg(feature = "unicode")]
+#[test]
+fn fixed_width_unicode() {
+    let dialect = Dialect { fixed_width_unicode: true, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\\u00e9\\U0001F600\\u{41}", &dialect).unwrap(), "\u{E9}\u{1F600}A".as_bytes());
+    let r = unescape_bytes_with(b"\\ue9", &dialect);
+    assert!(matches!(r, Err(ParseError::InvalidBackslash { kind: InvalidBackslashKind::UnicodeEscapeTooFewDigits, .. })));
+    assert!(unescape_bytes_with(b"\\U1F600", &dialect).is_err());
+}
+#[test]
+fn braced_hex() {
+    assert_eq!(unescape_bytes(b"\\x{0}baca\\x{FF}\\x{41}").unwrap(), b"\x00baca\xFFA");
+    assert!(unescape_bytes(b"\\x{}").is_err());
+    assert!(unescape_bytes(b"\\x{41").is_err());
+    assert!(unescape_bytes(b"\\x{100}").is_err());
+    let options = EscapeOptions { braced_hex: true, ..EscapeOptions::default() };
+    let s = escape_bytes_with(b"\x01b\xFF\n", &options);
+    assert_eq!(s, "\\x{01}b\\x{FF}\\n");
+    assert_eq!(unescape_bytes(s.as_bytes()).unwrap(), b"\x01b\xFF\n");
+    assert_eq!(escape_bytes(b"\x01b"), "\\x01b");
+}
+#[test]
+fn ascii_only_escapes() {
+    let options = EscapeOptions { ascii_only: true, ..EscapeOptions::default() };
+    let input = "caf\u{E9} \u{1F600}\n".as_bytes().iter().chain(b"\xFF").copied().collect::<Vec<u8>>();
+    let s = escape_bytes_with(&input, &options);
+    assert!(s.is_ascii());
+    if cfg!(feature = "unicode") {
+        assert_eq!(s, "caf\\u{E9} \\u{1F600}\\n\\xFF");
+    } else {
+        assert_eq!(s, "caf\\xC3\\xA9 \\xF0\\x9F\\x98\\x80\\n\\xFF");
+    }
+    assert_eq!(unescape_bytes(s.as_bytes()).unwrap(), input);
+}
+#[test]
+fn minimal_escapes() {
+    let input = "h\u{E9}llo, 'w\u{F6}rld' |\u{1F600}|\t\\".as_bytes().iter().chain(b"\xFF").copied().collect::<Vec<u8>>();
+    let options = EscapeOptions { quote: Some(b'|'), ..EscapeOptions::default() };
+    let s = escape_bytes_with(&input, &options);
+    assert_eq!(s, "h\u{E9}llo, 'w\u{F6}rld' \\|\u{1F600}\\|\\t\\\\\\xFF");
+    let mut out: Vec<u8> = Vec::new();
+    let delimited = [s.as_bytes(), b"|rest"].concat();
+    assert_eq!(unescape_slice(&delimited, &mut out, Some(b'|')).unwrap(), s.len() + 1);
+    assert_eq!(out, input);
+    let options = EscapeOptions { quote: Some(b'\n'), ..EscapeOptions::default() };
+    assert_eq!(escape_bytes_with(b"a\nb", &options), "a\\nb");
+}
+
+#[test]
+fn alphanumeric_quote() {
+    let input = b"an x\n1\\";
+    for quote in [b'n', b'a', b'x'] {
+        let options = EscapeOptions { quote: Some(quote), ..EscapeOptions::default() };
+        let s = escape_bytes_with(input, &options);
+        let mut out: Vec<u8> = Vec::new();
+        let delimited = [s.as_bytes(), &[quote], b"rest"].concat();
+        assert_eq!(unescape_slice(&delimited, &mut out, Some(quote)).unwrap(), s.len() + 1);
+        assert_eq!(out, input.to_vec());
+    }
+    let options = EscapeOptions { quote: Some(b'n'), ..EscapeOptions::default() };
+    assert_eq!(escape_bytes_with(b"an\n", &options), "a\\x6E\\n");
+}
+
+#[test]
+fn escape_styles() {
+    let input = b"\n\x01a\x017\x1F\\\xFF";
+    let escaped = |style: EscapeStyle, braced_hex: bool| escape_bytes_with(input, &EscapeOptions { style, braced_hex, ..EscapeOptions::default() });
+    assert_eq!(escaped(EscapeStyle::Named, false), "\\n\\x01a\\x017\\x1F\\\\\\xFF");
+    assert_eq!(escaped(EscapeStyle::Hex, false), "\\x0A\\x01a\\x017\\x1F\\x5C\\xFF");
+    assert_eq!(escaped(EscapeStyle::Octal, false), "\\012\\001a\\0017\\037\\134\\377");
+    assert_eq!(escaped(EscapeStyle::Shortest, false), "\\n\\1a\\x017\\37\\\\\\xFF");
+    assert_eq!(escaped(EscapeStyle::Shortest, true), "\\n\\1a\\0017\\37\\\\\\377");
+    for style in [EscapeStyle::Named, EscapeStyle::Hex, EscapeStyle::Octal, EscapeStyle::Shortest] {
+        assert_eq!(unescape_bytes(escaped(style, false).as_bytes()).unwrap(), input);
+        assert_eq!(unescape_bytes(escaped(style, true).as_bytes()).unwrap(), input);
+    }
+}
+
+#[test]
+fn canonical_form() {
+    let dialect = Dialect::default();
+    let spellings: [&[u8]; 4] = [b"A\\n\\xFF", b"\\x41\\012\\377", b"\\101\\cJ\\x{FF}", b"\\o{101}\\n\\M-^?"];
+    for spelling in spellings {
+        assert_eq!(canonicalize(spelling, &dialect).unwrap(), "A\\n\\xFF");
+    }
+    assert_ne!(canonicalize(b"a", &dialect).unwrap(), canonicalize(b"\\x61\\x00", &dialect).unwrap());
+    // without unicode escapes, `U+0085` is written as `\xC2\x85`
+    let strict = Dialect { canonical: true, ..Dialect::default() };
+    let input = "\u{E9}\u{85}\\".as_bytes().iter().chain(b"\x01\xFF").copied().collect::<Vec<u8>>();
+    let canonical = canonicalize(escape_bytes_with(&input, &EscapeOptions { style: EscapeStyle::Octal, ..EscapeOptions::default() }).as_bytes(), &dialect).unwrap();
+    assert_eq!(unescape_bytes_with(canonical.as_bytes(), &strict).unwrap(), input);
+    let expected = if cfg!(feature = "unicode") { "\u{E9}\\u0085" } else { "\u{E9}\\xC2\\x85" };
+    assert_eq!(escape_bytes("\u{E9}\u{85}".as_bytes()), expected);
+    assert!(canonicalize(b"\\q", &dialect).is_err());
+}
+
+#[test]
+fn escaped_equality() {
+    assert!(escaped_eq(b"a\\tb\\x41", b"a\tbA").unwrap());
+    assert!(escaped_eq(b"", b"").unwrap());
+    assert!(! escaped_eq(b"a\\tb", b"a\tbA").unwrap());
+    assert!(! escaped_eq(b"a\\tbA", b"a\tb").unwrap());
+    assert!(! escaped_eq(b"x\\q", b"a").unwrap());
+    assert!(escaped_eq(b"a\\q", b"a").is_err());
+    let dialect = Dialect { space_escape: true, ..Dialect::default() };
+    assert!(escaped_eq_with(b"a\\sb", b"a b", &dialect).unwrap());
+}
+
+#[test]
+fn fallible_allocation() {
+    assert_eq!(try_unescape_bytes(b"a\\tb\\x00").unwrap(), b"a\tb\0");
+    let long: Vec<u8> = b"x\\n".repeat(5000);
+    assert_eq!(try_unescape_bytes(&long).unwrap(), unescape_bytes(&long).unwrap());
+    assert!(matches!(try_unescape_bytes(b"\\q"), Err(UnescapeError::Parse(ParseError::InvalidBackslash { .. }))));
+    let dialect = Dialect { max_output_len: Some(3), ..Dialect::default() };
+    assert!(matches!(try_unescape_bytes_with(b"abcd", &dialect), Err(UnescapeError::Parse(ParseError::OutputTooLong { .. }))));
+}
+
+#[test]
+fn str_errors_count_chars() {
+    assert_eq!(unescape_str("héllo\\t").unwrap(), "héllo\t".as_bytes());
+    let e = unescape_str("é✓\\q").unwrap_err();
+    assert_eq!(e.error.offset(), Some(5));
+    assert_eq!(e.char_index, Some(CharIndex { index: 2, splits_char: false }));
+    assert_eq!(e.char_span, Some(2..4));
+    assert!(e.to_string().ends_with("(char 2)"));
+    let dialect = Dialect { max_output_len: Some(2), ..Dialect::default() };
+    let e = unescape_str_with("aé", &dialect).unwrap_err();
+    assert_eq!(e.error.offset(), Some(2));
+    assert_eq!(e.char_index, Some(CharIndex { index: 1, splits_char: true }));
+    assert_eq!(e.char_span, Some(1..2));
+    let dialect = Dialect { base_offset: 10, ..Dialect::default() };
+    let e = unescape_str_with("é\\q", &dialect).unwrap_err();
+    assert_eq!(e.error.offset(), Some(12));
+    assert_eq!(e.char_index, Some(CharIndex { index: 1, splits_char: false }));
+    assert_eq!(char_index("é", 3), None);
+}
+
+#[test]
+fn vectored_writes() {
+    /// Keeps each vectored write's slices
+    #[derive(Default)]
+    struct Recorder {
+        writes: Vec<Vec<Vec<u8>>>,
+    }
+    impl std::io::Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.writes.push(vec![buf.to_vec()]);
+            return Ok(buf.len());
+        }
+        fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> std::io::Result<usize> {
+            self.writes.push(bufs.iter().map(|buf| buf.to_vec()).collect());
+            return Ok(bufs.iter().map(|buf| buf.len()).sum());
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            return Ok(());
+        }
+    }
+    let mut out = Recorder::default();
+    assert_eq!(unescape_slice_vectored(b"hello\\t\\x41world\"rest", &mut out, Some(b'"')).unwrap(), 17);
+    assert_eq!(out.writes, vec![vec![b"hello".to_vec(), b"\tA".to_vec(), b"world".to_vec()]]);
+    let long: Vec<u8> = b"ab\\n".repeat(1000);
+    let mut out = Recorder::default();
+    unescape_slice_vectored(&long, &mut out, None).unwrap();
+    assert!(out.writes.len() < 100);
+    assert_eq!(out.writes.concat().concat(), unescape_bytes(&long).unwrap());
+    let mut out = Recorder::default();
+    assert!(unescape_slice_vectored(b"ok\\q", &mut out, None).is_err());
+    assert_eq!(out.writes.concat().concat(), b"ok");
+    let mut out: Vec<u8> = Vec::new();
+    let dialect = Dialect { encoding: OutputEncoding::Utf16Le, ..Dialect::default() };
+    unescape_slice_vectored_with(b"a\\x62", &mut out, None, &dialect).unwrap();
+    assert_eq!(out, b"a\0b\0");
+}
+
+#[test]
+fn hashing_unescaped() {
+    use std::hash::Hasher;
+    let hash = |escaped: &[u8]| {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        hash_unescaped(escaped, &mut hasher).unwrap();
+        hasher.finish()
+    };
+    let raw: Vec<u8> = (0..200u8).collect();
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    hash_raw(&raw, &mut hasher);
+    assert_eq!(hash(escape_bytes(&raw).as_bytes()), hasher.finish());
+    let octal = escape_bytes_with(&raw, &EscapeOptions { style: EscapeStyle::Octal, ..EscapeOptions::default() });
+    assert_eq!(hash(octal.as_bytes()), hasher.finish());
+    assert_eq!(hash(b"A\\x42"), hash(b"\\x41B"));
+    assert_ne!(hash(b"A"), hash(b"A\\0"));
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    assert!(hash_unescaped(b"\\q", &mut hasher).is_err());
+}
+
+#[test]
+fn braced_octal() {
+    assert_eq!(unescape_bytes(b"\\o{0}12\\o{377}\\o{00101}").unwrap(), b"\x0012\xFFA");
+    assert!(unescape_bytes(b"\\o{}").is_err());
+    assert!(unescape_bytes(b"\\o{17").is_err());
+    assert!(unescape_bytes(b"\\o17").is_err());
+    assert!(unescape_bytes(b"\\o{18}").is_err());
+    let r = unescape_bytes(b"\\o{400}");
+    assert!(matches!(r, Err(ParseError::InvalidBackslash { kind: InvalidBackslashKind::OctalEscapeOverflow(_), .. })));
+    let dialect = Dialect { octal_overflow: OctalOverflow::Wrap, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\\o{777}", &dialect).unwrap(), [0xFF]);
+}
+#[test]
+fn braced_hex_bytes() {
+    assert_eq!(unescape_bytes(b"\\x{DEADBEEF}\\x{0d0a}").unwrap(), b"\xDE\xAD\xBE\xEF\r\n");
+    let r = unescape_bytes(b"\\x{DEADBEE}");
+    assert!(matches!(r, Err(ParseError::InvalidBackslash { kind: InvalidBackslashKind::BracedHexOddDigits, .. })));
+    assert!(unescape_bytes(b"\\x{DEADBEEG}").is_err());
+}
+#[test]
+fn control_delete() {
+    assert_eq!(unescape_bytes(b"\\c?").unwrap(), [0x7F]);
+    let dialect = Dialect { control_delete: false, ..Dialect::default() };
+    let r = unescape_bytes_with(b"\\c?", &dialect);
+    assert!(matches!(r, Err(ParseError::InvalidBackslash { kind: InvalidBackslashKind::ControlEscapeBadKey(b'?'), .. })));
+}
+#[test]
+fn control_backslash() {
+    assert_eq!(unescape_bytes(b"\\c\\\\n").unwrap(), b"\x1Cn");
+    assert_eq!(unescape_bytes(b"\\c\\n").unwrap(), b"\x1Cn");
+    let dialect = Dialect { control_double_backslash: false, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\\c\\\\n", &dialect).unwrap(), b"\x1C\n");
+}
+#[test]
+fn space_escape() {
+    assert!(unescape_bytes(b"\\s").is_err());
+    let dialect = Dialect { space_escape: true, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"a\\sb", &dialect).unwrap(), b"a b");
+}
+#[test]
+fn line_continuation() {
+    assert!(unescape_bytes(b"a\\\nb").is_err());
+    let dialect = Dialect { line_continuation: LineContinuation::Newline, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"a\\\nb", &dialect).unwrap(), b"ab");
+    assert!(unescape_bytes_with(b"a\\\r\nb", &dialect).is_err());
+    let dialect = Dialect { line_continuation: LineContinuation::NewlineOrCrlf, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"a\\\r\nb\\\nc", &dialect).unwrap(), b"abc");
+}
+#[test]
+fn byte_order_mark() {
+    assert_eq!(unescape_bytes(b"\xEF\xBB\xBFa").unwrap(), b"\xEF\xBB\xBFa");
+    let dialect = Dialect { bom: BomPolicy::Strip, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\xEF\xBB\xBFa\xEF\xBB\xBF", &dialect).unwrap(), b"a\xEF\xBB\xBF");
+    assert_eq!(unescape_bytes_with(b"\xEF\xBBa", &dialect).unwrap(), b"\xEF\xBBa");
+    assert_eq!(unescape_bytes_with(b"\xFF\xFEa", &dialect).unwrap(), b"\xFF\xFEa");
+    let dialect = Dialect { bom: BomPolicy::StripAndRejectUtf16, ..Dialect::default() };
+    assert!(matches!(unescape_bytes_with(b"\xFF\xFEa", &dialect), Err(ParseError::Utf16Bom { offset: 0, .. })));
+    assert!(matches!(unescape_bytes_with(b"\xFE\xFFa", &dialect), Err(ParseError::Utf16Bom { offset: 0, .. })));
+    assert_eq!(unescape_bytes_with(b"\xFF\xFF", &dialect).unwrap(), b"\xFF\xFF");
+}
+
+#[test]
+fn nul_policy() {
+    assert_eq!(unescape_bytes(b"a\\0b\0c").unwrap(), b"a\0b\0c");
+    let dialect = Dialect { nul: NulPolicy::Strip, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"a\\0b\\x00\0c", &dialect).unwrap(), b"abc");
+    let dialect = Dialect { nul: NulPolicy::Reject, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"a\\x41\\101", &dialect).unwrap(), b"aAA");
+    assert!(matches!(unescape_bytes_with(b"ab\\x00", &dialect), Err(ParseError::NulByte { offset: 2 })));
+    assert!(matches!(unescape_bytes_with(b"a\0b", &dialect), Err(ParseError::NulByte { offset: 1 })));
+}
+
+#[test]
+fn max_output_len() {
+    let dialect = Dialect { max_output_len: Some(3), ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"a\\x41b", &dialect).unwrap(), b"aAb");
+    assert!(matches!(unescape_bytes_with(b"a\\x41b\\x42", &dialect), Err(ParseError::OutputTooLong { offset: 6, max_len: 3 })));
+    let dialect = Dialect { max_output_len: Some(0), ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"", &dialect).unwrap(), b"");
+    assert!(matches!(unescape_bytes_with(b"a", &dialect), Err(ParseError::OutputTooLong { offset: 0, .. })));
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn surrogate_policy() {
+    assert!(unescape_bytes(b"\\uD800").is_err());
+    assert!(unescape_bytes(b"\\uD83D\\uDE00").is_err());
+    let dialect = Dialect { surrogates: SurrogatePolicy::Wtf8, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\\uD83D\\uDE00", &dialect).unwrap(), "\u{1F600}".as_bytes());
+    assert_eq!(unescape_bytes_with(b"a\\uD800b", &dialect).unwrap(), b"a\xED\xA0\x80b");
+    assert_eq!(unescape_bytes_with(b"\\uDC00\\u{D800}", &dialect).unwrap(), b"\xED\xB0\x80\xED\xA0\x80");
+    let dialect = Dialect { surrogates: SurrogatePolicy::Replace, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\\uD800\\uD83D\\uDE00\\UDFFF", &dialect).unwrap(), "\u{FFFD}\u{1F600}\u{FFFD}".as_bytes());
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn cesu8() {
+    let dialect = Dialect { encoding: OutputEncoding::Cesu8, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\\u00E9\\U0001F600", &dialect).unwrap(), b"\xC3\xA9\xED\xA0\xBD\xED\xB8\x80");
+    assert_eq!(unescape_bytes_with("\u{1F600}".as_bytes(), &dialect).unwrap(), "\u{1F600}".as_bytes());
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn utf16_output() {
+    let dialect = Dialect { encoding: OutputEncoding::Utf16Le, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with("é\\x41\\U0001F600".as_bytes(), &dialect).unwrap(), b"\xE9\x00A\x00\x3D\xD8\x00\xDE");
+    assert!(matches!(unescape_bytes_with(b"a\\xFFb", &dialect), Err(ParseError::NotUtf8 { offset: 1 })));
+    assert!(matches!(unescape_bytes_with(b"a\\xC3", &dialect), Err(ParseError::NotUtf8 { offset: 1 })));
+    let dialect = Dialect { encoding: OutputEncoding::Utf16Be, surrogates: SurrogatePolicy::Wtf8, nul: NulPolicy::Strip, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\\0A\\uD800", &dialect).unwrap(), b"\x00A\xD8\x00");
+}
+
+#[cfg(feature = "encoding_rs")]
+#[test]
+fn legacy_encoding() {
+    let dialect = Dialect { encoding: OutputEncoding::Legacy(encoding_rs::WINDOWS_1252, UnmappablePolicy::Error), ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"caf\\u00E9 \\u20AC", &dialect).unwrap(), b"caf\xE9 \x80");
+    assert!(matches!(unescape_bytes_with(b"a\\u3042", &dialect), Err(ParseError::Unmappable { offset: 1, character: '\u{3042}', .. })));
+    let dialect = Dialect { encoding: OutputEncoding::Legacy(encoding_rs::SHIFT_JIS, UnmappablePolicy::HtmlEntity), ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\\u3042\\U0001F600", &dialect).unwrap(), b"\x82\xA0&#128512;");
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn custom_encoder() {
+    /// Writes ASCII as-is, and everything else as `<NN>`
+    struct Angles;
+    impl ScalarEncoder for Angles {
+        fn write_char(&mut self, c: char, out: &mut dyn std::io::Write) -> Result<(), EncodeError> {
+            if c.is_ascii() {
+                out.write_all(&[c as u8])?;
+            } else {
+                write!(out, "<{:X}>", c as u32)?;
+            }
+            return Ok(());
+        }
+    }
+    let mut out: Vec<u8> = Vec::new();
+    unescape_iter_with_encoder(&mut b"a\\u00E9\\x41".iter().enumerate().peekable(), &mut out, None, &Dialect::default(), &mut Angles).unwrap();
+    assert_eq!(out, b"a<E9>A");
+}
+
+#[cfg(feature = "unicode-normalization")]
+#[test]
+fn normalized() {
+    let dialect = Dialect::default();
+    assert_eq!(unescape_bytes_normalized(b"e\\u0301\\xFF", &dialect, Normalization::Nfc).unwrap(), b"\xC3\xA9\xFF");
+    assert_eq!(unescape_bytes_normalized(b"\\u00E9", &dialect, Normalization::Nfd).unwrap(), b"e\xCC\x81");
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn canonical() {
+    assert_eq!(unescape_bytes(b"\\x41\\u0041").unwrap(), b"AA");
+    let dialect = Dialect { canonical: true, max_hex_digits: 4, caret_notation: true, space_escape: true, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"\\x7F\\xFF \\u0085\\x00\\n\\e\\\\\"'\xC3\xA9", &dialect).unwrap(), b"\x7F\xFF \xC2\x85\0\n\x1B\\\"'\xC3\xA9");
+    let rejected: [&[u8]; 26] = [
+        // printable ASCII
+        b"\\x41", b"\\101", b"\\o{101}", b"\\x{41}", b"\\u{41}", b"\\U00000041", b"\\s", b"\\\"", b"\\'",
+        // numeric or control forms of bytes with a named escape
+        b"\\x0a", b"\\x0A", b"\\012", b"\\cJ", b"^J", b"\\u000A", b"\\0",
+        // other spellings of named escapes and hex
+        b"\\E", b"\\M-A", b"\\xff", b"\\x00FF", b"\\x{FF}", b"\\377", b"\\M-^?",
+        // unicode escapes for characters that are written as they are
+        b"\\u00E9", b"\\u{E9}", b"\\U0001F600",
+    ];
+    for escape in rejected {
+        assert!(matches!(unescape_bytes_with(escape, &dialect), Err(ParseError::InvalidBackslash { kind: InvalidBackslashKind::NotCanonical, .. })), "{:?}", pretty_string(escape));
+    }
+    assert!(unescape_bytes_with(b"\\u0085", &dialect).is_ok());
+    assert!(unescape_bytes_with(b"\\u0085x", &Dialect { max_u_digits: 6, ..dialect.clone() }).is_ok());
+    let continued = Dialect { line_continuation: LineContinuation::Newline, ..dialect.clone() };
+    assert!(unescape_bytes_with(b"a\\\nb", &continued).is_err());
+    // the close delimiter still has to be escaped
+    let mut out: Vec<u8> = Vec::new();
+    assert_eq!(unescape_slice_with(b"a\\\"b\"", &mut out, Some(b'"'), &dialect).unwrap(), 5);
+    assert_eq!(out, b"a\"b");
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn canonicalize_is_canonical() {
+    let strict = Dialect { canonical: true, ..Dialect::default() };
+    let mut input: Vec<u8> = (0..=255u8).collect();
+    input.extend_from_slice("é\u{85}\u{9F}😀\"'".as_bytes());
+    let canonical = canonicalize(escape_bytes(&input).as_bytes(), &Dialect::default()).unwrap();
+    assert_eq!(unescape_bytes_with(canonical.as_bytes(), &strict).unwrap(), input);
+    for spelling in [&b"\\x41\\cJ\\E"[..], b"\\101\\012\\e", b"A\\n\\x1B"] {
+        let canonical = canonicalize(spelling, &Dialect::default()).unwrap();
+        assert_eq!(canonical, "A\\n\\e");
+        assert!(unescape_bytes_with(canonical.as_bytes(), &strict).is_ok());
+    }
+}
+
+#[test]
+fn close_delimiter_set() {
+    let dialect = Dialect { close_bytes: vec![b'"'], ..Dialect::default() };
+    let mut out: Vec<u8> = Vec::new();
+    assert_eq!(unescape_iter_with(&mut b"a\\\"b\"c'".iter().enumerate().peekable(), &mut out, Some(b'\''), &dialect).unwrap(), 4);
+    assert_eq!(out, b"a\"b");
+    let dialect = Dialect { close_ignore_case: true, ..Dialect::default() };
+    let mut out: Vec<u8> = Vec::new();
+    assert_eq!(unescape_iter_with(&mut b"abcXd".iter().enumerate().peekable(), &mut out, Some(b'x'), &dialect).unwrap(), 3);
+    assert_eq!(out, b"abc");
+    assert!(unescape_bytes_with(b"abcXd", &dialect).is_ok());
+}
+
+#[test]
+fn nested_delimiters() {
+    let dialect = Dialect { open_delimiter: Some(b'{'), ..Dialect::default() };
+    let mut out: Vec<u8> = Vec::new();
+    assert_eq!(unescape_iter_with(&mut b"a{b{}\\}c}d}".iter().enumerate().peekable(), &mut out, Some(b'}'), &dialect).unwrap(), 10);
+    assert_eq!(out, b"a{b{}}c}d");
+    let mut out: Vec<u8> = Vec::new();
+    assert!(unescape_iter_with(&mut b"\\{a}".iter().enumerate().peekable(), &mut out, Some(b'}'), &dialect).is_ok());
+    assert_eq!(out, b"{a");
+    assert!(unescape_iter_with(&mut b"{a}".iter().enumerate().peekable(), &mut out, Some(b'}'), &dialect).is_err());
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn quoted_regions() {
+    let text = b"echo 'a\\' \\'b \"c\\\"d\" $'e\\'f'";
+    let regions = find_quoted_regions(text).unwrap();
+    assert_eq!(regions, vec![
+        QuotedRegion { style: QuoteStyle::Single, span: 5..9, content: 6..8 },
+        QuotedRegion { style: QuoteStyle::Double, span: 14..20, content: 15..19 },
+        QuotedRegion { style: QuoteStyle::Dollar, span: 21..28, content: 23..27 },
+    ]);
+    assert!(matches!(find_quoted_regions(b"a \"b"), Err(ParseError::MissingClose { .. })));
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn lex_line_argv() {
+    let tokens = lex_line(b"grep -e 'a b' \"c\\\"d\\n\" $'\\x41'\\ z 2>&1|wc # count\nls").unwrap();
+    let values: Vec<&[u8]> = tokens.iter().map(|t| t.value.as_slice()).collect();
+    assert_eq!(values, vec![&b"grep"[..], b"-e", b"a b", b"c\"d\\n", b"A z", b"2", b">&", b"1", b"|", b"wc", b"ls"]);
+    assert_eq!(tokens[3].span, 14..22);
+    assert_eq!(tokens[6].kind, TokenKind::Operator);
+    assert!(matches!(lex_line(b"echo 'a"), Err(ParseError::MissingClose { .. })));
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn ifs_split() {
+    assert_eq!(split_fields(b"  a \t b\n", DEFAULT_IFS), vec![&b"a"[..], b"b"]);
+    assert_eq!(split_fields(b":a::b : c:", b" :"), vec![&b""[..], b"a", b"", b"b", b"c"]);
+    assert_eq!(split_fields(b" a b ", b""), vec![&b" a b "[..]]);
+    assert!(split_fields(b"   ", DEFAULT_IFS).is_empty());
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn lex_variables() {
+    let mut resolve = |name: &[u8]| if name == b"HOME" { Some(b"/home/me".to_vec()) } else { None };
+    let mut options = LexOptions { variables: Some(&mut resolve), ..LexOptions::default() };
+    let tokens = lex_line_with(b"$HOME/a \"${HOME}$NOPE\" '$HOME' $ $1", &mut options).unwrap();
+    let values: Vec<&[u8]> = tokens.iter().map(|t| t.value.as_slice()).collect();
+    assert_eq!(values, vec![&b"/home/me/a"[..], b"/home/me$NOPE", b"$HOME", b"$", b"$1"]);
+    options.unset = UnsetPolicy::Error;
+    assert!(matches!(lex_line_with(b"a ${NOPE}", &mut options), Err(ParseError::UnsetVariable { offset: 2, .. })));
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn lex_tilde() {
+    let mut home = |user: &[u8]| match user {
+        b"" => Some(b"/home/me".to_vec()),
+        b"root" => Some(b"/root".to_vec()),
+        _ => None,
+    };
+    let mut options = LexOptions { tilde: Some(&mut home), ..LexOptions::default() };
+    let tokens = lex_line_with(b"~ ~/a ~root/b ~nobody a~ '~' ~\"root\"", &mut options).unwrap();
+    let values: Vec<&[u8]> = tokens.iter().map(|t| t.value.as_slice()).collect();
+    assert_eq!(values, vec![&b"/home/me"[..], b"/home/me/a", b"/root/b", b"~nobody", b"a~", b"~", b"~root"]);
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn lex_command_substitution() {
+    let line = b"a$(echo \")\" $(b)) \"`c`\" '$(d)'";
+    let tokens = lex_line(line).unwrap();
+    let values: Vec<&[u8]> = tokens.iter().map(|t| t.value.as_slice()).collect();
+    assert_eq!(values, vec![&b"a$(echo \")\" $(b))"[..], b"`c`", b"$(d)"]);
+    assert_eq!(tokens[0].substitutions, vec![1..17]);
+    assert!(tokens[2].substitutions.is_empty());
+    let mut options = LexOptions { substitution: SubstitutionPolicy::Error, ..LexOptions::default() };
+    assert!(matches!(lex_line_with(line, &mut options), Err(ParseError::CommandSubstitution { offset: 1 })));
+    let mut run = |command: &[u8]| command.to_ascii_uppercase();
+    let mut options = LexOptions { substitution: SubstitutionPolicy::Run(&mut run), ..LexOptions::default() };
+    let tokens = lex_line_with(b"x`ab`y", &mut options).unwrap();
+    assert_eq!(tokens[0].value, b"xABy");
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn lex_heredocs() {
+    let mut resolve = |_: &[u8]| Some(b"X".to_vec());
+    let mut options = LexOptions { variables: Some(&mut resolve), ..LexOptions::default() };
+    let script = b"cat <<EOF; cat <<-'END'\n$A \\$B\nEOF\n\t$C\n\tEND\necho done";
+    let tokens = lex_line_with(script, &mut options).unwrap();
+    let values: Vec<&[u8]> = tokens.iter().map(|t| t.value.as_slice()).collect();
+    assert_eq!(values, vec![&b"cat"[..], b"<<", b"EOF", b";", b"cat", b"<<-", b"END", b"X $B\n", b"$C\n", b"echo", b"done"]);
+    assert_eq!(tokens[7].kind, TokenKind::HereDoc);
+    assert_eq!(tokens[7].span, 24..31);
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn lex_comments() {
+    let line = b"a #b 'c#' d#e\nf";
+    let values = |tokens: Vec<Token>| tokens.into_iter().map(|t| t.value).collect::<Vec<Vec<u8>>>();
+    assert_eq!(values(lex_line(line).unwrap()), vec![b"a".to_vec(), b"f".to_vec()]);
+    let mut options = LexOptions { comments: false, ..LexOptions::default() };
+    assert_eq!(values(lex_line_with(line, &mut options).unwrap()), vec![b"a".to_vec(), b"#b".to_vec(), b"c#".to_vec(), b"d#e".to_vec(), b"f".to_vec()]);
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn lex_braces() {
+    let line = b"a{b,c}d x{1..3} {z..x} '{p,q}' {r} e{f,{g,h}}i";
+    let values = |tokens: Vec<Token>| tokens.into_iter().map(|t| t.value).collect::<Vec<Vec<u8>>>();
+    assert_eq!(values(lex_line(line).unwrap())[0], b"a{b,c}d".to_vec());
+    let mut options = LexOptions { braces: true, ..LexOptions::default() };
+    let tokens = lex_line_with(line, &mut options).unwrap();
+    assert_eq!(tokens[1].span, 0..7);
+    let expected: Vec<&[u8]> = vec![
+        b"abd", b"acd", b"x1", b"x2", b"x3", b"z", b"y", b"x", b"{p,q}", b"{r}", b"efi", b"egi", b"ehi",
+    ];
+    assert_eq!(values(tokens), expected.into_iter().map(|v| v.to_vec()).collect::<Vec<Vec<u8>>>());
+    let mut options = LexOptions { braces: true, ..LexOptions::default() };
+    let tokens = lex_line_with(b"{a,$(b)}", &mut options).unwrap();
+    assert_eq!(tokens[1].value, b"$(b)".to_vec());
+    assert_eq!(tokens[1].substitutions, vec![3..7]);
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn lex_braces_limit() {
+    let values = |line: &[u8]| {
+        let mut options = LexOptions { braces: true, ..LexOptions::default() };
+        return lex_line_with(line, &mut options).unwrap().into_iter().map(|t| t.value).collect::<Vec<Vec<u8>>>();
+    };
+    assert_eq!(values(format!("{{1..{}}}", MAX_BRACE_WORDS).as_bytes()).len(), MAX_BRACE_WORDS);
+    assert_eq!(values(format!("{{1..{}}}", MAX_BRACE_WORDS + 1).as_bytes()), vec![format!("{{1..{}}}", MAX_BRACE_WORDS + 1).into_bytes()]);
+    assert_eq!(values(b"x{1..1000000000}"), vec![b"x{1..1000000000}".to_vec()]);
+    assert_eq!(values(b"{1..100}{1..100}{a,b}"), vec![b"{1..100}{1..100}{a,b}".to_vec()]);
+    assert_eq!(values(b"{1..100}{1..100}").len(), 10_000);
+    // counting doesn't overflow near the ends of the range
+    assert_eq!(values(b"{9223372036854775800..9223372036854775807..5}"), vec![b"9223372036854775800".to_vec(), b"9223372036854775805".to_vec()]);
+    assert_eq!(values(b"{-9223372036854775808..9223372036854775807..9223372036854775807}").len(), 3);
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn lex_glob() {
+    let mut variables = |name: &[u8]| if name == b"PAT" { Some(b"*.rs".to_vec()) } else { None };
+    let mut options = LexOptions { variables: Some(&mut variables), ..LexOptions::default() };
+    let tokens = lex_line_with(b"*.rs '*.rs' \\*.rs a[b] \"$PAT\" $PAT x?y plain", &mut options).unwrap();
+    let globs: Vec<bool> = tokens.iter().map(|t| t.glob).collect();
+    assert_eq!(globs, vec![true, false, false, true, false, true, true, false]);
+    assert_eq!(tokens[2].value, b"*.rs".to_vec());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialize_error() {
+    let e = unescape_bytes(b"ab\\q").unwrap_err();
+    let json = serde_json::to_value(&e).unwrap();
+    assert_eq!(json["InvalidBackslash"]["kind"], serde_json::json!({"BackslashEscapeUnknown": b'q'}));
+    assert_eq!(json["InvalidBackslash"]["offset"], 2);
+    let e = UnescapeError::from(std::io::Error::other("disk full"));
+    assert_eq!(serde_json::to_value(&e).unwrap(), serde_json::json!({"IOError": "disk full"}));
+}
+
+#[test]
+fn compare_errors() {
+    let e = unescape_bytes(b"ab\\q").unwrap_err();
+    assert_eq!(e.clone(), unescape_bytes(b"ab\\q").unwrap_err());
+    assert_ne!(e, unescape_bytes(b"a\\q").unwrap_err());
+    let io = UnescapeError::from(std::io::Error::other("disk full"));
+    assert_eq!(io.clone(), io);
+    assert_ne!(io, UnescapeError::from(std::io::Error::other("disk on fire")));
+}
+
+#[test]
+fn io_errors_are_separate() {
+    struct Full;
+    impl std::io::Write for Full {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            return Err(std::io::Error::new(std::io::ErrorKind::StorageFull, "full"));
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            return Ok(());
+        }
+    }
+    let r = unescape_iter(&mut b"ab".iter().enumerate().peekable(), &mut Full, None);
+    assert!(matches!(r, Err(UnescapeError::IOError(e)) if e.kind() == std::io::ErrorKind::StorageFull));
+    let r = unescape_iter(&mut b"\\q".iter().enumerate().peekable(), &mut Vec::new(), None);
+    assert!(matches!(r, Err(UnescapeError::Parse(ParseError::InvalidBackslash { offset: 0, .. }))));
+}
+
+#[test]
+fn long_escapes() {
+    assert_eq!(unescape_bytes(b"\\x{6162636465666768696A6B6C6D}").unwrap(), b"abcdefghijklm".to_vec());
+    let escape = b"\\x{6162636465666768696A6B6C6DZZ}";
+    match unescape_bytes(escape) {
+        Err(ParseError::InvalidBackslash { string, .. }) => assert_eq!(string, pretty_string(escape)),
+        r => panic!("{:?}", r),
+    }
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn digits_parsed_in_place() {
+    assert_eq!(unescape_bytes(b"\\u{1F600}\\x7e\\176").unwrap(), "\u{1F600}~~".as_bytes().to_vec());
+    assert!(matches!(unescape_bytes(b"\\u{+41}"), Err(ParseError::InvalidBackslash { kind: InvalidBackslashKind::HexDigitsNotHexDigits(_), .. })));
+    assert!(matches!(unescape_bytes(b"\\u{\xFF}"), Err(ParseError::InvalidBackslash { kind: InvalidBackslashKind::HexDigitsNotUnicode(_), .. })));
+    let dialect = Dialect { max_big_u_digits: 10, ..Dialect::default() };
+    let r = unescape_bytes_with(b"\\U1000000000", &dialect);
+    assert!(matches!(r, Err(ParseError::InvalidBackslash { kind: InvalidBackslashKind::HexDigitsNotHexDigits(ref digits), .. }) if digits == b"1000000000"));
+}
+
+#[test]
+fn suggestions() {
+    let e = unescape_bytes(b"ab\\q").unwrap_err();
+    assert_eq!(e.suggestion(), Some(&Suggestion::EscapeBackslash { replacement: String::from("\\\\q") }));
+    assert!(e.to_string().ends_with("; did you mean \\\\q for a literal backslash?"));
+    let e = unescape_bytes(b"x\\x{1G}").unwrap_err();
+    assert_eq!(e.suggestion(), Some(&Suggestion::NotADigit { offset: 5, byte: b'G', radix: 16 }));
+    let e = unescape_bytes(b"\\o{78}").unwrap_err();
+    assert_eq!(e.suggestion(), Some(&Suggestion::NotADigit { offset: 4, byte: b'8', radix: 8 }));
+    let dialect = Dialect { base_offset: 10, ..Dialect::default() };
+    let e = unescape_bytes_with(b"\\o{78}", &dialect).unwrap_err();
+    assert_eq!(e.suggestion(), Some(&Suggestion::NotADigit { offset: 14, byte: b'8', radix: 8 }));
+    assert_eq!(unescape_bytes(b"\\c").unwrap_err().suggestion(), None);
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn unicode_suggestion() {
+    let e = unescape_bytes(b"\\u{12G4}").unwrap_err();
+    assert_eq!(e.suggestion(), Some(&Suggestion::NotADigit { offset: 5, byte: b'G', radix: 16 }));
+    assert!(e.to_string().ends_with("; G at byte 5 isn't a hex digit"));
+}
+
+#[test]
+fn kind_payloads() {
+    let kind = |input: &[u8]| match unescape_bytes(input) {
+        Err(ParseError::InvalidBackslash { kind, .. }) => kind,
+        r => panic!("{:?}", r),
+    };
+    let dialect = Dialect { max_hex_digits: 3, ..Dialect::default() };
+    assert!(matches!(unescape_bytes_with(b"\\x1FF", &dialect), Err(ParseError::InvalidBackslash { kind: InvalidBackslashKind::HexEscapeOverflow(0x1FF), .. })));
+    assert_eq!(kind(b"\\M-\xC3"), InvalidBackslashKind::MetaEscapeBadKey(0xC3));
+    let k = kind(b"\\o{18}");
+    assert_eq!(k, InvalidBackslashKind::OctalDigitsNotOctalDigits(b"18".to_vec()));
+    assert_eq!((k.digits(), k.radix()), (Some(b"18".as_slice()), Some(8)));
+    let k = kind(b"\\x{G1}");
+    assert_eq!((k.digits(), k.radix()), (Some(b"G1".as_slice()), Some(16)));
+    assert_eq!(InvalidBackslashKind::BackslashEscapeUnknown(b'q').radix(), None);
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn bad_codepoint_payload() {
+    assert!(matches!(unescape_bytes(b"\\u{110000}"), Err(ParseError::InvalidBackslash { kind: InvalidBackslashKind::UnicodeEscapeBadCodepoint(0x110000), .. })));
+}
+
+#[test]
+fn iter_consumed() {
+    let input = b"xa\\x41'rest";
+    let mut bytes = input.iter().enumerate().peekable();
+    bytes.next();
+    let mut out: Vec<u8> = Vec::new();
+    let consumed = unescape_iter_consumed(&mut bytes, &mut out, Some(b'\''), &Dialect::default()).unwrap();
+    assert_eq!(consumed, Consumed { content: 5, closed: true });
+    assert_eq!(consumed.total(), 6);
+    assert_eq!(out, b"aA".to_vec());
+    assert_eq!(bytes.next(), Some((7, &b'r')));
+    let mut out: Vec<u8> = Vec::new();
+    let empty = unescape_iter_consumed(&mut b"".iter().enumerate().peekable(), &mut out, None, &Dialect::default()).unwrap();
+    let one = unescape_iter_consumed(&mut b"a".iter().enumerate().peekable(), &mut out, None, &Dialect::default()).unwrap();
+    assert_eq!((empty.total(), one.total()), (0, 1));
+}
+
+#[test]
+fn iter_matches_slice() {
+    let dialect = Dialect { bom: BomPolicy::StripAndRejectUtf16, ..Dialect::default() };
+    for input in [&b"\xEF\xBB\xBFa\\x41\\M-^A'b"[..], b"\xEF\xBBz'", b"\xFF\xFE", b"\xFFa", b"\\c\\\\\\1234'x", b"\\"] {
+        let mut from_slice: Vec<u8> = Vec::new();
+        let slice_result = unescape_slice_with(input, &mut from_slice, Some(b'\''), &dialect);
+        let mut bytes = input.iter().enumerate().peekable();
+        let mut from_iter: Vec<u8> = Vec::new();
+        let iter_result = unescape_iter_with(&mut bytes, &mut from_iter, Some(b'\''), &dialect);
+        assert_eq!(slice_result.map(|used| used - 1), iter_result);
+        assert_eq!(from_slice, from_iter);
+        if let Ok(end) = iter_result {
+            assert_eq!(bytes.next().map(|(offset, _)| offset), input.get(end + 1).map(|_| end + 1));
+        }
+    }
+}
+
+#[test]
+fn slice_consumed() {
+    let mut out: Vec<u8> = Vec::new();
+    assert_eq!(unescape_slice(b"a\\x41'rest", &mut out, Some(b'\'')).unwrap(), 6);
+    assert_eq!(out, b"aA".to_vec());
+    let mut out: Vec<u8> = Vec::new();
+    assert_eq!(unescape_slice(b"a\\x41", &mut out, None).unwrap(), 5);
+    assert_eq!(unescape_slice(b"", &mut out, None).unwrap(), 0);
+    assert!(matches!(unescape_slice(b"a", &mut out, Some(b'\'')), Err(UnescapeError::Parse(ParseError::MissingClose { .. }))));
+}
+
+#[test]
+fn unescape_into_reused() {
+    let mut out: Vec<u8> = Vec::with_capacity(64);
+    let capacity = out.capacity();
+    unescape_bytes_into(b"a\\tb", &mut out).unwrap();
+    assert_eq!(out, b"a\tb".to_vec());
+    unescape_bytes_into(b"\\x41", &mut out).unwrap();
+    assert_eq!(out, b"A".to_vec());
+    assert_eq!(out.capacity(), capacity);
+    let dialect = Dialect { space_escape: true, ..Dialect::default() };
+    unescape_bytes_into_with(b"a\\sb", &mut out, &dialect).unwrap();
+    assert_eq!(out, b"a b".to_vec());
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn len_bound() {
+    assert_eq!(unescaped_len_bound(b"\\x41\\x42\\101\\n", &Dialect::default()), 4);
+    assert_eq!(unescape_bytes(b"\\x41\\x42\\101\\n").unwrap().capacity(), 4);
+    let dialect = Dialect { octal_overflow: OctalOverflow::TwoDigits, caret_notation: true, ..Dialect::default() };
+    for escaped in [&b"\\477"[..], b"\\c\\x41", b"\\M-^\\x41", b"^\\x41", b"\\u{1F600}", b"a\\\\x41"] {
+        let unescaped = unescape_bytes_with(escaped, &dialect).unwrap();
+        assert!(unescaped_len_bound(escaped, &dialect) >= unescaped.len(), "{:?}", pretty_string(escaped));
+    }
+    let dialect = Dialect { encoding: OutputEncoding::Utf16Le, ..Dialect::default() };
+    assert_eq!(unescaped_len_bound(b"ab", &dialect), 4);
+}
+
+#[cfg(not(feature = "unicode"))]
+#[test]
+fn unicode_compiled_out() {
+    assert!(matches!(unescape_bytes(b"\\u0041"), Err(ParseError::InvalidBackslash { kind: InvalidBackslashKind::BackslashEscapeUnknown(_), .. })));
+}
+
+#[cfg(all(unix, feature = "bash-oracle"))]
+#[test]
+fn bash_oracle() {
+    // `z` isn't a hex digit, because bash reads `\U1F600a` as one escape past U+10FFFF, where smashquote errors
+    let tokens: [&[u8]; 24] = [
+        b"z", "é".as_bytes(), b"\\a", b"\\b", b"\\e", b"\\E", b"\\f", b"\\n", b"\\r", b"\\t", b"\\v", b"\\\\",
+        b"\\'", b"\\\"", b"\\101", b"\\777", b"\\x41", b"\\x4", b"\\u00E9", b"\\u41", b"\\U1F600", b"\\cA", b"\\c?", b"\\x{41}",
+    ];
+    let inputs: Vec<Vec<u8>> = tokens.iter().flat_map(|a| tokens.iter().map(move |b| [*a, *b].concat())).collect();
+    let dialect = oracle::bash_dialect();
+    assert_eq!(oracle::divergences(inputs.iter().map(Vec::as_slice), &dialect).unwrap(), vec![]);
+    let divergence = oracle::compare_with_bash(b"a\\q", &dialect).unwrap().unwrap();
+    assert_eq!(divergence.bash, b"a\\q".to_vec());
+    assert!(matches!(divergence.smashquote, Err(ParseError::InvalidBackslash { kind: InvalidBackslashKind::BackslashEscapeUnknown(_), .. })));
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn conformance_vectors() {
+    let vectors = conformance::vectors();
+    assert!(vectors.len() > 40);
+    for vector in &vectors {
+        assert!(vector.passes(), "{}: {:?}", vector.name, unescape_bytes_with(&vector.escaped, &vector.dialect));
+    }
+    let bad = conformance::parse_vectors("# comment\n\nname\t41\t41\tnope=1\n");
+    assert_eq!(bad.unwrap_err().line, 3);
+}
+
+#[test]
+fn escape_tables() {
+    for &(letter, byte) in NAMED_ESCAPES {
+        assert_eq!(unescape_bytes(&[b'\\', letter]).unwrap(), vec![byte]);
+        assert_eq!(named_escape(letter), Some(byte));
+    }
+    assert_eq!(named_escape(b's'), None);
+    assert_eq!(escape_byte(0x1B), EscapeForm::Named(b'e'));
+    for (byte, picture) in CONTROL_PICTURES {
+        assert_eq!(pretty_string(&[byte]), picture.to_string());
+        assert_eq!(unpretty_string(&picture.to_string()), vec![byte]);
+    }
+}
+
+#[test]
+fn error_offset() {
+    assert_eq!(unescape_bytes(b"ab\\q").unwrap_err().offset(), Some(2));
+    assert_eq!(ParseError::missing_close(b'"').offset(), None);
+}
+
+#[test]
+fn raw_controls() {
+    let dialect = Dialect { reject_raw_controls: true, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"a\\tb\\x01 ~", &dialect).unwrap(), b"a\tb\x01 ~");
+    assert_eq!(unescape_bytes_with(b"ab\tc", &dialect).unwrap_err(), ParseError::RawByte { offset: 2, byte: b'\t' });
+    let dialect = Dialect { allowed_raw_controls: vec![b'\t'], base_offset: 10, ..dialect };
+    assert_eq!(unescape_bytes_with(b"a\tb", &dialect).unwrap(), b"a\tb");
+    assert_eq!(unescape_bytes_with(b"a\tb\r\n", &dialect).unwrap_err().span(), Some(13..14));
+    let dialect = Dialect { line_continuation: LineContinuation::Newline, ..dialect };
+    assert_eq!(unescape_bytes_with(b"a\\\nb", &dialect).unwrap(), b"ab");
+}
+
+#[test]
+fn ascii_source() {
+    let dialect = Dialect { ascii_source: true, base_offset: 4, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"a\\xFF\\303\\251~", &dialect).unwrap(), b"a\xFF\xC3\xA9~");
+    assert_eq!(unescape_bytes_with("caf\u{E9}".as_bytes(), &dialect).unwrap_err(), ParseError::RawByte { offset: 7, byte: 0xC3 });
+    let dialect = Dialect { bom: BomPolicy::Strip, ..dialect };
+    assert_eq!(unescape_bytes_with(b"\xEF\xBB\xBFa", &dialect).unwrap(), b"a");
+    assert!(matches!(unescape_bytes_with(b"\xEF\xBBa", &dialect), Err(ParseError::RawByte { offset: 4, byte: 0xEF })));
+}
+
+#[cfg(feature = "color")]
+#[test]
+fn render_colored() {
+    let input = b"first\nab\\qcd\nlast";
+    let e = unescape_bytes(input).unwrap_err();
+    assert_eq!(
+        e.render_colored(input),
+        format!("\x1B[1;31merror\x1B[0m: {e}\n  ab\x1B[31m\\\\q\x1B[0mcd\n    \x1B[31m^^^\x1B[0m"),
+    );
+    let e = UnescapeError::from(ParseError::missing_close(b'"'));
+    assert!(e.render_colored(b"ab").ends_with("\n  ab\x1B[31m\x1B[0m\n    \x1B[31m^\x1B[0m"));
+}
+
+#[test]
+fn base_offset() {
+    let dialect = Dialect { base_offset: 100, ..Dialect::default() };
+    assert_eq!(unescape_bytes_with(b"ab\\q", &dialect).unwrap_err().offset(), Some(102));
+    let mut out: Vec<u8> = Vec::new();
+    assert_eq!(unescape_slice_with(b"a\"b", &mut out, Some(b'"'), &dialect).unwrap(), 2);
+    assert_eq!(ParseError::missing_close(b'"').offset_by(5), ParseError::missing_close(b'"'));
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn stream_decoder() {
+    let dialect = Dialect {
+        caret_notation: true,
+        line_continuation: LineContinuation::NewlineOrCrlf,
+        surrogates: SurrogatePolicy::Wtf8,
+        ..Dialect::default()
+    };
+    let input: &[u8] = b"a\\x41\\x{4243}^M\\101\\7z\\c\\\\\\M-^?\\M-a\\\r\nb\\u00e9\\uD83D\\uDE00\xC3\xA9\\\\\\'";
+    let expected = unescape_bytes_with(input, &dialect).unwrap();
+    for size in 1..input.len() {
+        let mut decoder = StreamDecoder::new(dialect.clone());
+        let mut out: Vec<u8> = Vec::new();
+        for piece in input.chunks(size) {
+            decoder.decode(piece, &mut out).unwrap();
+            assert_eq!(decoder.produced(), out.len());
+        }
+        decoder.finish(&mut out).unwrap();
+        assert_eq!(out, expected, "split every {} bytes", size);
+        assert_eq!(decoder.consumed(), input.len());
+    }
+    let mut decoder = StreamDecoder::new(Dialect::default());
+    let mut out: Vec<u8> = Vec::new();
+    decoder.decode(b"abc", &mut out).unwrap();
+    decoder.decode(b"d\\x4", &mut out).unwrap();
+    assert_eq!((decoder.consumed(), decoder.produced(), decoder.pending()), (4, 4, b"\\x4".as_slice()));
+    decoder.decode(b"1\\q", &mut out).unwrap();
+    assert!(matches!(decoder.finish(&mut out), Err(UnescapeError::Parse(e)) if e.offset() == Some(8)));
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn stream_decoder_vectors() {
+    for vector in conformance::vectors() {
+        let whole = unescape_bytes_with(&vector.escaped, &vector.dialect);
+        for size in 1..=vector.escaped.len() {
+            let mut decoder = StreamDecoder::new(vector.dialect.clone());
+            let mut out: Vec<u8> = Vec::new();
+            let r = vector.escaped.chunks(size)
+                .try_for_each(|piece| decoder.decode(piece, &mut out))
+                .and_then(|()| decoder.finish(&mut out));
+            match (&whole, r) {
+                (Ok(whole), Ok(())) => assert_eq!(whole, &out, "{} split every {} bytes", vector.name, size),
+                (Err(whole), Err(UnescapeError::Parse(e))) => assert_eq!(whole, &e, "{} split every {} bytes", vector.name, size),
+                (whole, r) => panic!("{} split every {} bytes: {:?} but {:?}", vector.name, size, whole, r),
+            }
+        }
+    }
+}
+
+#[test]
+fn stream_decoder_utf16_byte_escapes() {
+    for input in [b"a\\xC3\\xA9b\\xE2\\x82\\xACc".as_slice(), b"a\xC3\\xA9b\\xE2\x82\\xACc".as_slice()] {
+        for encoding in [OutputEncoding::Utf16Le, OutputEncoding::Utf16Be] {
+            let dialect = Dialect { encoding, ..Dialect::default() };
+            let expected = unescape_bytes_with(input, &dialect).unwrap();
+            for size in 1..input.len() {
+                let mut decoder = StreamDecoder::new(dialect.clone());
+                let mut out: Vec<u8> = Vec::new();
+                for piece in input.chunks(size) {
+                    decoder.decode(piece, &mut out).unwrap();
+                }
+                decoder.finish(&mut out).unwrap();
+                assert_eq!(out, expected, "split every {} bytes", size);
+            }
+        }
+    }
+}
+
+#[test]
+fn chunk_decoder() {
+    let pieces: Vec<&[u8]> = vec![b"ab\\x", b"4", b"1\\", b"n", b"", b"\\t"];
+    let chunks: Vec<Vec<u8>> = ChunkDecoder::new(pieces.into_iter(), Dialect::default()).map(Result::unwrap).collect();
+    assert_eq!(chunks, vec![b"ab".to_vec(), b"A".to_vec(), b"\n".to_vec(), b"\t".to_vec()]);
+    let mut decoder = ChunkDecoder::new(vec![b"ab\\q".to_vec(), b"cd".to_vec()].into_iter(), Dialect::default());
+    assert_eq!(decoder.next().unwrap().unwrap(), b"ab");
+    assert!(matches!(decoder.next(), Some(Err(UnescapeError::Parse(e))) if e.offset() == Some(2)));
+    assert!(decoder.next().is_none());
+    assert_eq!(decoder.decoder().consumed(), 2);
+}
+
+#[test]
+fn lines_unescaped_records() {
+    let input: &[u8] = b"a\\tb\r\n\nc\\\\\ne";
+    let lines: Vec<Vec<u8>> = lines_unescaped(input).map(Result::unwrap).collect();
+    assert_eq!(lines, vec![b"a\tb".to_vec(), Vec::new(), b"c\\".to_vec(), b"e".to_vec()]);
+    let dialect = Dialect { line_continuation: LineContinuation::Newline, ..Dialect::default() };
+    let lines: Vec<Vec<u8>> = lines_unescaped_with(b"c\\\nd\\\\\ne".as_slice(), &dialect).map(Result::unwrap).collect();
+    assert_eq!(lines, vec![b"cd\\".to_vec(), b"e".to_vec()]);
+    let lines: Vec<Vec<u8>> = lines_unescaped_with(b"a\nb\\\nc\\\n".as_slice(), &dialect).map(Result::unwrap).collect();
+    assert_eq!(lines, vec![b"a".to_vec(), b"bc".to_vec()]);
+    let dialect = Dialect { line_continuation: LineContinuation::NewlineOrCrlf, ..Dialect::default() };
+    let lines: Vec<Vec<u8>> = lines_unescaped_with(b"a\\\r\n".as_slice(), &dialect).map(Result::unwrap).collect();
+    assert_eq!(lines, vec![b"a".to_vec()]);
+    let mut lines = lines_unescaped(b"ok\nx\\q\nnever\n".as_slice());
+    assert_eq!(lines.next().unwrap().unwrap(), b"ok");
+    assert!(matches!(lines.next(), Some(Err(UnescapeError::Parse(e))) if e.offset() == Some(4)));
+    assert!(lines.next().is_none());
+}
+
+#[test]
+fn escape_handler() {
+    let mut handler = |offset: usize, escape: &[u8], out: &mut dyn std::io::Write| -> Result<Handled, UnescapeError> {
+        return match escape {
+            b"\\%" => Ok(Handled::NeedMore),
+            b"\\%d" => {
+                write!(out, "{}", offset)?;
+                Ok(Handled::Done)
+            }
+            _ => Ok(Handled::Unknown),
+        };
+    };
+    let mut hooks = DecodeHooks { escape: Some(&mut handler), ..DecodeHooks::default() };
+    assert_eq!(unescape_bytes_with_hooks(b"a\\%d\\tb\\%d", &Dialect::default(), &mut hooks).unwrap(), b"a1\tb7".to_vec());
+    let dialect = Dialect { base_offset: 10, ..Dialect::default() };
+    let r = unescape_bytes_with_hooks(b"ab\\%x", &dialect, &mut hooks);
+    assert!(matches!(r, Err(UnescapeError::Parse(ParseError::InvalidBackslash { offset: 12, kind: InvalidBackslashKind::BackslashEscapeUnknown(b'%'), .. }))));
+    assert!(unescape_bytes_with_hooks(b"\\%", &Dialect::default(), &mut hooks).is_err());
+    assert!(unescape_bytes_with_hooks(b"\\q", &Dialect::default(), &mut DecodeHooks::default()).is_err());
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn scalar_hook() {
+    let mut fold = |scalar: Scalar| match scalar {
+        Scalar::Char(c) => c.to_lowercase().next().map(Scalar::Char),
+        Scalar::Byte(b'-') => None,
+        Scalar::Byte(b) => Some(Scalar::Byte(b.to_ascii_lowercase())),
+    };
+    let mut hooks = DecodeHooks { scalar: Some(&mut fold), ..DecodeHooks::default() };
+    let r = unescape_bytes_with_hooks(b"A-b\\x43\\u{C9}\\u{1F600}-", &Dialect::default(), &mut hooks).unwrap();
+    assert_eq!(r, "abc\u{E9}\u{1F600}".as_bytes());
+    let mut replace = |scalar: Scalar| match scalar {
+        Scalar::Byte(b'?') => Some(Scalar::Char('\u{FFFD}')),
+        _ => Some(scalar),
+    };
+    let mut hooks = DecodeHooks { scalar: Some(&mut replace), ..DecodeHooks::default() };
+    assert_eq!(unescape_bytes_with_hooks(b"a?b", &Dialect::default(), &mut hooks).unwrap(), "a\u{FFFD}b".as_bytes());
+}
+
+#[test]
+fn split_on_unescaped_delim() {
+    assert_eq!(split_on_unescaped(b"a,b\\,c,\\x41,", b',').unwrap(), vec![b"a".to_vec(), b"b,c".to_vec(), b"A".to_vec(), Vec::new()]);
+    assert_eq!(split_on_unescaped(b"", b',').unwrap(), vec![Vec::<u8>::new()]);
+    assert_eq!(split_on_unescaped(b"a\\\\,b", b',').unwrap(), vec![b"a\\".to_vec(), b"b".to_vec()]);
+    assert_eq!(split_on_unescaped(b"a,b\\q", b',').unwrap_err().offset(), Some(3));
+    assert_eq!(split_on_unescaped(b"a\\,b", b',').unwrap(), vec![b"a,b".to_vec()]);
+    assert_eq!(split_on_unescaped(b"x,a\\,", b',').unwrap(), vec![b"x".to_vec(), b"a,".to_vec()]);
+    assert_eq!(split_on_unescaped(b"x,a\\,b", b',').unwrap(), vec![b"x".to_vec(), b"a,b".to_vec()]);
+    assert_eq!(split_on_unescaped(b"x,a\\", b',').unwrap_err().offset(), Some(3));
+    // only the start of the input can have a byte order mark
+    let dialect = Dialect { bom: BomPolicy::Strip, ..Dialect::default() };
+    assert_eq!(split_on_unescaped_with(b"\xEF\xBB\xBFa,\xEF\xBB\xBFb", b',', &dialect).unwrap(), vec![b"a".to_vec(), b"\xEF\xBB\xBFb".to_vec()]);
+}
+
+#[test]
+fn find_unescaped_byte() {
+    assert_eq!(find_unescaped(b"ab,c", b','), Some(2));
+    assert_eq!(find_unescaped(b"a\\,b\\x2C,", b','), Some(8));
+    assert_eq!(find_unescaped(b"\\\\,", b','), Some(2));
+    assert_eq!(find_unescaped(b"\\x{2C,", b','), None);
+    assert_eq!(find_unescaped(b"abc", b','), None);
+    let dialect = Dialect { caret_notation: true, ..Dialect::default() };
+    assert_eq!(find_unescaped_with(b"^[[", b'[', &dialect), Some(2));
+}
+
+#[test]
+fn split_unescaped_lazily() {
+    let input = b"a,b\\,c,\\x41,";
+    let fields: Vec<EscapedField<'_>> = split_unescaped(input, b',').collect();
+    assert_eq!(fields.iter().map(|f| f.escaped).collect::<Vec<&[u8]>>(), vec![b"a".as_slice(), b"b\\,c", b"\\x41", b""]);
+    assert_eq!(fields.iter().map(|f| f.offset).collect::<Vec<usize>>(), vec![0, 2, 7, 12]);
+    assert_eq!(fields[2].unescape().unwrap(), b"A");
+    let decoded: Vec<Vec<u8>> = fields.iter().map(|f| f.unescape().unwrap()).collect();
+    assert_eq!(decoded, split_on_unescaped(input, b',').unwrap());
+    assert_eq!(split_unescaped(b"", b',').count(), 1);
+    assert_eq!(split_unescaped(b"x,\\q", b',').nth(1).unwrap().unescape().unwrap_err().offset(), Some(2));
+    let dialect = Dialect { bom: BomPolicy::Strip, ..Dialect::default() };
+    let fields: Vec<EscapedField<'_>> = split_unescaped_with(b"\xEF\xBB\xBFa,\xEF\xBB\xBFb", b',', &dialect).collect();
+    assert_eq!(fields[0].unescape_with(&dialect).unwrap(), b"a");
+    assert_eq!(fields[1].unescape_with(&dialect).unwrap(), b"\xEF\xBB\xBFb");
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn decode_regions() {
+    let input = b"echo $'a\\tb' 'c\\d' \"e\\\"f\" \\$'g'";
+    let (output, regions) = decode_quoted_regions(input).unwrap();
+    assert_eq!(output, b"echo a\tb 'c\\d' \"e\\\"f\" \\$'g'");
+    assert_eq!(regions.len(), 1);
+    assert_eq!((regions[0].region.span.clone(), regions[0].output.clone()), (5..12, 5..8));
+    let options = RegionOptions { single: true, double: true, ..RegionOptions::default() };
+    let (output, regions) = decode_quoted_regions_with(input, &options).unwrap();
+    assert_eq!(output, b"echo a\tb c\\d e\"f \\$g");
+    assert_eq!(regions.iter().map(|r| &output[r.output.clone()]).collect::<Vec<&[u8]>>(), vec![b"a\tb".as_slice(), b"c\\d", b"e\"f", b"g"]);
+    assert!(matches!(decode_quoted_regions(b"x $'\\q'"), Err(ParseError::InvalidBackslash { offset: 4, .. })));
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn quoted_literals() {
+    let input = b"log $'a\\tb' then 'c\\d' and \"e\\\"f\" ok";
+    let literals: Vec<QuotedLiteral> = iter_quoted(input).collect::<Result<_, _>>().unwrap();
+    assert_eq!(literals.iter().map(|l| l.value.as_slice()).collect::<Vec<&[u8]>>(), vec![b"a\tb".as_slice(), b"c\\d", b"e\"f"]);
+    assert_eq!(literals.iter().map(|l| l.region.span.clone()).collect::<Vec<_>>(), vec![4..11, 17..22, 27..33]);
+    let mut iter = iter_quoted(b"'ok' $'\\q' 'never'");
+    assert_eq!(iter.next().unwrap().unwrap().value, b"ok".to_vec());
+    assert!(matches!(iter.next(), Some(Err(ParseError::InvalidBackslash { offset: 7, .. }))));
+    assert!(iter.next().is_none());
+    assert!(matches!(iter_quoted(b"a \"b").next(), Some(Err(ParseError::MissingClose { .. }))));
+}
+
+#[cfg(feature = "shell")]
+#[test]
+fn key_values() {
+    let kv = parse_key_value(b"GREETING=hi\\tthere' x=y'\r\n").unwrap().unwrap();
+    assert_eq!((kv.key, kv.key_span), (b"GREETING".to_vec(), 0..8));
+    assert_eq!((kv.value, kv.value_span), (b"hi\tthere x=y".to_vec(), 9..24));
+    let kv = parse_key_value(b"EMPTY=\n").unwrap().unwrap();
+    assert_eq!((kv.key, kv.value, kv.value_span), (b"EMPTY".to_vec(), Vec::new(), 6..6));
+    let kv = parse_key_value(b"'A=B'=$'\\x41'\"=\"").unwrap().unwrap();
+    assert_eq!((kv.key, kv.value), (b"'A=B'".to_vec(), b"A=".to_vec()));
+    assert_eq!(parse_key_value(b"no equals sign\n").unwrap(), None);
+    assert_eq!(parse_key_value(b"").unwrap(), None);
+    assert!(matches!(parse_key_value(b"K=a\\qb"), Err(ParseError::InvalidBackslash { offset: 3, .. })));
+    assert!(matches!(parse_key_value(b"K='a"), Err(ParseError::MissingClose { .. })));
+}
+
+#[test]
+fn placeholders() {
+    let mut resolve = |name: &[u8]| match name {
+        b"record_id" => Some(b"42".to_vec()),
+        b"t" => Some(b"\\t".to_vec()),
+        _ => None,
+    };
+    assert_eq!(unescape_placeholders(b"\\x1E{record_id}\\n", PlaceholderStyle::Braces, &mut resolve).unwrap(), b"\x1E42\n");
+    assert_eq!(unescape_placeholders(b"\\x7B{t}}", PlaceholderStyle::Braces, &mut resolve).unwrap(), b"{\\t}");
+    assert_eq!(unescape_placeholders(b"a%t\\x25t%", PlaceholderStyle::Percent, &mut resolve).unwrap(), b"a\\t%t%");
+    assert!(matches!(
+        unescape_placeholders(b"ab{nope}", PlaceholderStyle::Braces, &mut resolve),
+        Err(ParseError::UnsetVariable { offset: 2, .. }),
+    ));
+    assert!(matches!(unescape_placeholders(b"{x", PlaceholderStyle::Braces, &mut resolve), Err(ParseError::MissingClose { .. })));
+    assert!(matches!(
+        unescape_placeholders(b"{t}\\q", PlaceholderStyle::Braces, &mut resolve),
+        Err(ParseError::InvalidBackslash { offset: 3, .. }),
+    ));
+    let dialect = Dialect { max_output_len: Some(3), ..Dialect::default() };
+    assert!(matches!(
+        unescape_placeholders_with(b"a{record_id}b", PlaceholderStyle::Braces, &mut resolve, &dialect),
+        Err(ParseError::OutputTooLong { offset: 12, max_len: 3 }),
+    ));
+}
+
+#[test]
+fn truncate_escaped_at_escapes() {
+    assert_eq!(truncate_escaped(b"ab\\x41cd", 10).unwrap(), b"ab\\x41cd");
+    assert_eq!(truncate_escaped(b"ab\\x41cd", 3).unwrap(), b"ab\\x41");
+    assert_eq!(truncate_escaped(b"ab\\x41cd", 2).unwrap(), b"ab");
+    assert_eq!(truncate_escaped(b"\\\\\\\\\\\\", 2).unwrap(), b"\\\\\\\\");
+    assert_eq!(truncate_escaped("aé".as_bytes(), 2).unwrap(), b"a");
+    assert!(truncate_escaped(b"ab\\q", 1).is_err());
+    for max in 0..12 {
+        let input = b"a\\tb\\x{4243}\\101\\cA\\M-a";
+        let kept = truncate_escaped(input, max).unwrap();
+        let decoded = unescape_bytes(kept).unwrap();
+        assert!(decoded.len() <= max);
+        assert!(unescape_bytes(input).unwrap().starts_with(&decoded));
+    }
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn truncate_escaped_surrogates() {
+    let dialect = Dialect { surrogates: SurrogatePolicy::Wtf8, ..Dialect::default() };
+    assert_eq!(truncate_escaped_with(b"a\\uD83D\\uDE00b", 4, &dialect).unwrap(), b"a");
+    assert_eq!(truncate_escaped_with(b"a\\uD83D\\uDE00b", 5, &dialect).unwrap(), b"a\\uD83D\\uDE00");
+}
+
+#[test]
+fn line_index() {
+    let input = b"ab\ncd\r\n\nx";
+    let index = LineIndex::new(input);
+    assert_eq!(index.lines(), 4);
+    assert_eq!(index.position(0), Some(Position { line: 0, column: 0 }));
+    assert_eq!(index.position(2), Some(Position { line: 0, column: 2 }));
+    assert_eq!(index.position(3), Some(Position { line: 1, column: 0 }));
+    assert_eq!(index.position(5), Some(Position { line: 1, column: 2 }));
+    assert_eq!(index.position(7), Some(Position { line: 2, column: 0 }));
+    assert_eq!(index.position(9), Some(Position { line: 3, column: 1 }));
+    assert_eq!(index.position(10), None);
+    for offset in 0..=input.len() {
+        assert_eq!(index.offset(index.position(offset).unwrap()), Some(offset));
+    }
+    assert_eq!(index.offset(Position { line: 0, column: 3 }), None);
+    assert_eq!(index.offset(Position { line: 4, column: 0 }), None);
+    let e = unescape_bytes(b"ok\n\\q").unwrap_err();
+    assert_eq!(LineIndex::new(b"ok\n\\q").position(e.offset().unwrap()), Some(Position { line: 1, column: 0 }));
+}
+
+#[test]
+fn error_span() {
+    assert_eq!(unescape_bytes(b"ab\\xZ").unwrap_err().span(), Some(2..4));
+    assert_eq!(unescape_bytes(b"ab\\cA\\q").unwrap_err().span(), Some(5..7));
+    assert_eq!(unescape_bytes(b"\\M-\xFF").unwrap_err().span(), Some(0..4));
+    if cfg!(feature = "unicode") {
+        assert_eq!(unescape_bytes(b"x\\u{\xE9\xE9}").unwrap_err().span(), Some(1..7));
+    }
+    assert_eq!(ParseError::missing_close(b'"').span(), None);
+}
+
+#[cfg(feature = "codespan-reporting")]
+#[test]
+fn codespan_diagnostic() {
+    let input = b"ab\\qcd";
+    let diagnostic = unescape_bytes(input).unwrap_err().to_diagnostic(7, input.len());
+    assert_eq!(diagnostic.labels.len(), 1);
+    assert_eq!((diagnostic.labels[0].file_id, diagnostic.labels[0].range.clone()), (7, 2..4));
+    assert_eq!(diagnostic.labels[0].message, "BackslashEscapeUnknown(113)");
+    assert_eq!(diagnostic.notes, vec![String::from("help: did you mean \\\\q for a literal backslash?")]);
+    let diagnostic = UnescapeError::from(ParseError::missing_close(b'"')).to_diagnostic((), 6);
+    assert_eq!(diagnostic.labels[0].range, 6..6);
 }
@@ -0,0 +1,135 @@
+//! Unescaping into sinks like pipes and sockets with few, vectored writes
+
+use std::io::{IoSlice, Write};
+use std::ops::Range;
+
+use crate::{unescape_slice_with, Dialect, UnescapeError};
+
+/// How many slices [VectoredWriter] gathers before writing them
+const MAX_SLICES: usize = 64;
+
+/// How many copied bytes [VectoredWriter] gathers before writing them
+const MAX_COPIED: usize = 8192;
+
+/// Where a piece of the output is
+enum Piece {
+    /// Bytes of the input, written as-is
+    Input(Range<usize>),
+    /// Bytes that were copied, like ones decoded from escapes
+    Copied(Range<usize>),
+}
+
+/// A [Write] that gathers what's written and passes it on with [write_vectored](Write::write_vectored)
+///
+/// Writes that point into `input` are kept as ranges of it instead of being copied, and runs of them
+/// are put together, so literal text goes out in one slice however many writes it took.
+struct VectoredWriter<'a, 'o, O: Write> {
+    input: &'a [u8],
+    out: &'o mut O,
+    pieces: Vec<Piece>,
+    copied: Vec<u8>,
+}
+
+impl<'a, 'o, O: Write> VectoredWriter<'a, 'o, O> {
+    fn new(input: &'a [u8], out: &'o mut O) -> Self {
+        return Self { input, out, pieces: Vec::new(), copied: Vec::new() };
+    }
+
+    /// Writes everything that's been gathered so far
+    fn write_pieces(&mut self) -> std::io::Result<()> {
+        let Self { input, out, pieces, copied } = self;
+        let mut slices: Vec<IoSlice<'_>> = pieces.iter().map(|piece| IoSlice::new(match piece {
+            Piece::Input(range) => &input[range.clone()],
+            Piece::Copied(range) => &copied[range.clone()],
+        })).collect();
+        let mut slices = slices.as_mut_slice();
+        while ! slices.is_empty() {
+            match out.write_vectored(slices) {
+                Ok(0) => { return Err(std::io::ErrorKind::WriteZero.into()); }
+                Ok(n) => IoSlice::advance_slices(&mut slices, n),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => { return Err(e); }
+            }
+        }
+        pieces.clear();
+        copied.clear();
+        return Ok(());
+    }
+}
+
+impl<O: Write> Write for VectoredWriter<'_, '_, O> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let input = self.input.as_ptr_range();
+        let within = buf.as_ptr_range();
+        if input.start <= within.start && within.end <= input.end {
+            let start = within.start as usize - input.start as usize;
+            match self.pieces.last_mut() {
+                Some(Piece::Input(range)) if range.end == start => range.end += buf.len(),
+                _ => self.pieces.push(Piece::Input(start..start + buf.len())),
+            }
+        } else {
+            let start = self.copied.len();
+            self.copied.extend_from_slice(buf);
+            match self.pieces.last_mut() {
+                Some(Piece::Copied(range)) if range.end == start => range.end += buf.len(),
+                _ => self.pieces.push(Piece::Copied(start..start + buf.len())),
+            }
+        }
+        if self.pieces.len() >= MAX_SLICES || self.copied.len() >= MAX_COPIED {
+            self.write_pieces()?;
+        }
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_pieces()?;
+        return self.out.flush();
+    }
+}
+
+/// Writes an unescaped string from a byte slice like [unescape_slice](crate::unescape_slice), but with
+/// few, vectored writes
+///
+/// Runs of literal bytes are written straight from `bs`, next to the bytes decoded from escapes, with
+/// [write_vectored](Write::write_vectored). That saves system calls for unbuffered sinks like pipes and
+/// sockets. Sinks that don't support vectored writes still get one write per run instead of one per byte.
+/// After an error, `out` has whatever was unescaped before it, like with [unescape_slice](crate::unescape_slice).
+///
+/// # Arguments
+///
+/// * `bs` - A slice of bytes
+/// * `out` - An output stream, like a [TcpStream](std::net::TcpStream)
+/// * `close` - An optional closing delimiter to look for
+pub fn unescape_slice_vectored<O: Write>(
+    bs: &[u8],
+    out: &mut O,
+    close: Option<u8>,
+) -> Result<usize, UnescapeError> {
+    return unescape_slice_vectored_with(bs, out, close, &Dialect::default());
+}
+
+/// Writes an unescaped string from a byte slice using a specific [Dialect], with few, vectored writes,
+/// like [unescape_slice_vectored]
+///
+/// # Arguments
+///
+/// * `bs` - A slice of bytes
+/// * `out` - An output stream, like a [TcpStream](std::net::TcpStream)
+/// * `close` - An optional closing delimiter to look for
+/// * `dialect` - Which optional escape syntaxes to understand
+pub fn unescape_slice_vectored_with<O: Write>(
+    bs: &[u8],
+    out: &mut O,
+    close: Option<u8>,
+    dialect: &Dialect,
+) -> Result<usize, UnescapeError> {
+    let mut writer = VectoredWriter::new(bs, out);
+    let used = unescape_slice_with(bs, &mut writer, close, dialect);
+    let written = writer.write_pieces();
+    let used = used?;
+    written?;
+    return Ok(used);
+}